@@ -1,5 +1,14 @@
+use std::time::Duration;
+
 use bevy::{prelude::*, window::close_on_esc};
-use bevy::gltf::Gltf;
+use bevy::app::ScheduleRunnerPlugin;
+use bevy::gltf::{Gltf, GltfPlugin};
+use bevy::input::InputPlugin;
+use bevy::log::LogPlugin;
+use bevy::pbr::{CascadeShadowConfig, Cascades, CascadesVisibleEntities, CubemapVisibleEntities};
+use bevy::render::primitives::{Aabb, CascadesFrusta, CubemapFrusta, Frustum};
+use bevy::render::view::VisibleEntities;
+use bevy::scene::ScenePlugin;
 use bevy_asset_loader::prelude::*;
 use bevy_editor_pls::controls;
 use bevy_editor_pls::controls::EditorControls;
@@ -57,6 +66,68 @@ impl Plugin for AppPlugin {
     }
 }
 
+#[derive(Resource, Debug, Default)]
+/// Present if the app runs without a window and renderer, e.g. as dedicated server.
+/// Plugins use it to leave out anything which needs the renderer.
+pub struct Headless;
+
+/// Replaces `AppPlugin` for the dedicated server. It loads the same assets and levels, but only
+/// with `MinimalPlugins` and what is needed to spawn the gltf scenes.
+pub struct ServerAppPlugin {
+    /// Frames per second of the main loop.
+    pub frame_rate: f64,
+}
+
+impl Plugin for ServerAppPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Headless)
+            .add_state::<AppState>()
+            .add_plugins(MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+                1.0 / self.frame_rate,
+            ))))
+            .add_plugins((
+                LogPlugin::default(),
+                TransformPlugin,
+                HierarchyPlugin,
+                // Game systems read the input resources, even if nothing ever presses a key.
+                InputPlugin,
+                AssetPlugin::default(),
+                ScenePlugin,
+                GltfPlugin::default(),
+            ))
+            // Usually added by the render plugins, the gltf loader and its scenes need them.
+            .init_asset::<Mesh>()
+            .init_asset::<Image>()
+            .init_asset::<StandardMaterial>()
+            .init_asset::<AnimationClip>()
+            .register_type::<Handle<Mesh>>()
+            .register_type::<Handle<StandardMaterial>>()
+            .register_type::<Visibility>()
+            .register_type::<InheritedVisibility>()
+            .register_type::<ViewVisibility>()
+            .register_type::<VisibleEntities>()
+            .register_type::<Aabb>()
+            .register_type::<Frustum>()
+            .register_type::<CubemapFrusta>()
+            .register_type::<CascadesFrusta>()
+            .register_type::<PointLight>()
+            .register_type::<SpotLight>()
+            .register_type::<DirectionalLight>()
+            .register_type::<CubemapVisibleEntities>()
+            .register_type::<CascadesVisibleEntities>()
+            .register_type::<Cascades>()
+            .register_type::<CascadeShadowConfig>()
+
+            // There is no menu, the server starts the game right away.
+            .add_loading_state(
+                LoadingState::new(AppState::AssetLoading)
+                    .continue_to_state(AppState::Game)
+            )
+            .add_collection_to_loading_state::<_, MyAssets>(AppState::AssetLoading)
+            .add_plugins((ComponentsFromGltfPlugin, PhysicsPlugin));
+    }
+}
+
 #[derive(AssetCollection, Resource)]
 pub struct MyAssets {
     #[asset(path = "models/World.glb")]
@@ -1,7 +1,7 @@
 use bevy::prelude::*;
 // use bevy::render::primitives::Aabb;
 use bevy_rapier3d::geometry::Collider as RapierCollider;
-use bevy_rapier3d::prelude::ComputedColliderShape;
+use bevy_rapier3d::prelude::{ComputedColliderShape, Sensor};
 
 use super::utils::*;
 
@@ -42,6 +42,10 @@ pub fn physics_replace_proxies(
         if name.ends_with("_collider") || name.ends_with("_sensor") {
             *visibility = Visibility::Hidden;
         }
+        // sensors only report intersections (ladders, water, triggers...) and never block movement
+        if name.ends_with("_sensor") {
+            commands.entity(entity).insert(Sensor);
+        }
 
         let mut rapier_collider: RapierCollider;
         match collider_proxy {
@@ -0,0 +1,263 @@
+use bevy::input::mouse::MouseWheel;
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::app::AppState;
+use crate::game::inventory::{apply_inventory_controls, switch_weapons};
+use crate::game::player::{apply_mouse, LocalPlayer, PlayerCamera};
+use crate::game::view_model::ViewModel;
+use crate::game::weapons::{Weapon, WeaponDefinition};
+
+#[derive(Reflect, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AimMode {
+    /// Aim while the button is held.
+    #[default]
+    Hold,
+    /// Each press switches between aiming and not aiming.
+    Toggle,
+}
+
+#[derive(Resource, Reflect, Debug)]
+#[reflect(Resource)]
+pub struct AimSettings {
+    pub mode: AimMode,
+    /// Field of view without aiming, in degrees.
+    pub hip_fov: f32,
+    /// Field of view while aiming without a scope, in degrees.
+    pub aim_fov: f32,
+    /// How fast the field of view blends, full blends per second.
+    pub blend_speed: f32,
+    /// Mouse sensitivity multiplier at the default field of view.
+    /// Zooming in further lowers it proportionally.
+    pub sensitivity_factor: f32,
+}
+
+impl Default for AimSettings {
+    fn default() -> Self {
+        AimSettings {
+            mode: AimMode::Hold,
+            hip_fov: 90.0,
+            aim_fov: 60.0,
+            blend_speed: 8.0,
+            sensitivity_factor: 0.6,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+/// Scope of a weapon, part of a `WeaponDefinition`.
+pub struct ScopeDefinition {
+    /// Field of view per zoom level in degrees, cycled with the mouse wheel.
+    pub zoom_levels: Vec<f32>,
+}
+
+#[derive(Resource, Reflect, Debug)]
+#[reflect(Resource)]
+/// Aim state of the local player.
+pub struct AimState {
+    pub aiming: bool,
+    /// `0.0` without aiming, `1.0` fully aimed.
+    pub blend: f32,
+    /// Set while aiming with a weapon which has a scope.
+    pub scoped: bool,
+    pub zoom_level: usize,
+    /// Current field of view in degrees.
+    pub fov: f32,
+    /// Multiplier for the mouse movement in `apply_mouse`.
+    pub sensitivity: f32,
+}
+
+impl Default for AimState {
+    fn default() -> Self {
+        AimState {
+            aiming: false,
+            blend: 0.0,
+            scoped: false,
+            zoom_level: 0,
+            fov: AimSettings::default().hip_fov,
+            sensitivity: 1.0,
+        }
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct ScopeOverlay;
+
+pub struct AimPlugin;
+
+impl Plugin for AimPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<AimSettings>()
+            .register_type::<AimState>()
+            .init_resource::<AimSettings>()
+            .init_resource::<AimState>()
+            .add_systems(OnEnter(AppState::Game), spawn_scope_overlay)
+            .add_systems(OnExit(AppState::Game), despawn_scope_overlay)
+            .add_systems(Update, (
+                // the mouse wheel zooms while scoped and switches weapons otherwise
+                apply_aim_controls.before(apply_inventory_controls),
+                update_aim.after(apply_aim_controls).after(switch_weapons).before(apply_mouse),
+                show_scope_overlay.after(update_aim),
+            ).run_if(in_state(AppState::Game)));
+    }
+}
+
+pub fn apply_aim_controls(
+    mouse: Res<Input<MouseButton>>,
+    settings: Res<AimSettings>,
+    definitions: Res<Assets<WeaponDefinition>>,
+    mut scroll: EventReader<MouseWheel>,
+    weapons: Query<&Weapon, With<LocalPlayer>>,
+    mut aim_state: ResMut<AimState>,
+) {
+    let scope = weapons
+        .get_single()
+        .ok()
+        .filter(|weapon| !weapon.holstering)
+        .and_then(|weapon| definitions.get(&weapon.definition))
+        .and_then(|definition| definition.scope.as_ref());
+
+    let aiming = match settings.mode {
+        AimMode::Hold => mouse.pressed(MouseButton::Right),
+        AimMode::Toggle => aim_state.aiming != mouse.just_pressed(MouseButton::Right),
+    };
+    // switching weapons ends aiming
+    let aiming = aiming && weapons.get_single().is_ok_and(|weapon| !weapon.holstering);
+    if !aiming {
+        aim_state.zoom_level = 0;
+    }
+    aim_state.aiming = aiming;
+    aim_state.scoped = aiming && scope.is_some();
+
+    let scrolled: f32 = scroll.read().map(|event| event.y).sum();
+    if let Some(scope) = scope.filter(|_| aim_state.scoped) {
+        let levels = scope.zoom_levels.len().max(1);
+        if scrolled > 0.0 {
+            aim_state.zoom_level = (aim_state.zoom_level + 1).min(levels - 1);
+        } else if scrolled < 0.0 {
+            aim_state.zoom_level = aim_state.zoom_level.saturating_sub(1);
+        }
+    }
+}
+
+pub fn update_aim(
+    time: Res<Time>,
+    settings: Res<AimSettings>,
+    definitions: Res<Assets<WeaponDefinition>>,
+    weapons: Query<&Weapon, With<LocalPlayer>>,
+    mut aim_state: ResMut<AimState>,
+    mut cameras: Query<&mut Projection, With<PlayerCamera>>,
+) {
+    let target_fov = weapons
+        .get_single()
+        .ok()
+        .and_then(|weapon| definitions.get(&weapon.definition))
+        .and_then(|definition| definition.scope.as_ref())
+        .filter(|_| aim_state.scoped)
+        .and_then(|scope| scope.zoom_levels.get(aim_state.zoom_level).copied())
+        .unwrap_or(settings.aim_fov);
+
+    let step = settings.blend_speed * time.delta_seconds();
+    aim_state.blend = if aim_state.aiming {
+        (aim_state.blend + step).min(1.0)
+    } else {
+        (aim_state.blend - step).max(0.0)
+    };
+    aim_state.fov = settings.hip_fov + (target_fov - settings.hip_fov) * aim_state.blend;
+
+    // Zooming in further than the default aim lowers the sensitivity even more.
+    let half_tan = |fov: f32| (fov.to_radians() * 0.5).tan();
+    let zoom = (half_tan(target_fov) / half_tan(settings.aim_fov)).min(1.0);
+    aim_state.sensitivity = 1.0 + (settings.sensitivity_factor * zoom - 1.0) * aim_state.blend;
+
+    for mut projection in cameras.iter_mut() {
+        if let Projection::Perspective(perspective) = projection.as_mut() {
+            perspective.fov = aim_state.fov.to_radians();
+        }
+    }
+}
+
+pub fn spawn_scope_overlay(mut commands: Commands) {
+    build_scope_overlay(&mut commands);
+}
+
+pub fn despawn_scope_overlay(mut commands: Commands, overlay_query: Query<Entity, With<ScopeOverlay>>) {
+    if let Ok(overlay_entity) = overlay_query.get_single() {
+        commands.entity(overlay_entity).despawn_recursive();
+    }
+}
+
+// black borders left and right of a square with a crosshair
+pub fn build_scope_overlay(commands: &mut Commands) -> Entity {
+    let border = || NodeBundle {
+        style: Style {
+            flex_grow: 1.0,
+            height: Val::Percent(100.0),
+            ..default()
+        },
+        background_color: Color::BLACK.into(),
+        ..default()
+    };
+    let line = |width: Val, height: Val| NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            width,
+            height,
+            ..default()
+        },
+        background_color: Color::BLACK.into(),
+        ..default()
+    };
+
+    commands
+        .spawn((
+            Name::new("ScopeOverlay"),
+            ScopeOverlay,
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn(border());
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Vh(100.0),
+                        height: Val::Percent(100.0),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(line(Val::Percent(100.0), Val::Px(1.0)));
+                    parent.spawn(line(Val::Px(1.0), Val::Percent(100.0)));
+                });
+            parent.spawn(border());
+        })
+        .id()
+}
+
+pub fn show_scope_overlay(
+    aim_state: Res<AimState>,
+    mut overlays: Query<&mut Visibility, (With<ScopeOverlay>, Without<ViewModel>)>,
+    mut view_models: Query<&mut Visibility, With<ViewModel>>,
+) {
+    // The scope only appears once the weapon is fully raised.
+    let scope_visible = aim_state.scoped && aim_state.blend >= 1.0;
+
+    for mut visibility in overlays.iter_mut() {
+        *visibility = if scope_visible { Visibility::Inherited } else { Visibility::Hidden };
+    }
+    for mut visibility in view_models.iter_mut() {
+        *visibility = if scope_visible { Visibility::Hidden } else { Visibility::Inherited };
+    }
+}
@@ -0,0 +1,381 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use rand::seq::SliceRandom;
+
+use crate::app::AppState;
+use crate::game::enemies::Enemy;
+use crate::game::game_modes::{MatchRoster, Participant, Team};
+use crate::game::navigation::{NavPath, PathRequest};
+use crate::game::network::has_authority;
+use crate::game::player::{add_player_view, build_player_body, PlayerBody, PlayerView, VIEW_HEIGHT};
+use crate::game::respawn::{choose_spawn, occupied_positions, Dead, PlayerDied, PlayerSpawn};
+use crate::game::weapons::{fire_weapons, Weapon};
+use crate::game::world::world_ready;
+
+#[derive(Component, Reflect, Default, Debug)]
+#[reflect(Component)]
+/// A point bots walk to, placed in Blender. Without any, bots walk between the player spawns.
+pub struct BotWaypoint;
+
+#[derive(Resource, Reflect, Debug)]
+#[reflect(Resource)]
+pub struct BotSettings {
+    /// Bots kept in the game, they respawn after dying.
+    pub count: u32,
+    pub respawn_delay: f32,
+    /// Same unit as the walking speed of `apply_controls`.
+    pub speed: f32,
+    /// Radians per second the view turns.
+    pub turn_speed: f32,
+    /// Seconds a target has to be visible before shooting at it.
+    pub reaction_time: f32,
+    /// Maximum angle between the view and the target to pull the trigger.
+    pub aim_tolerance: f32,
+    pub sight_range: f32,
+    /// Shoot at other players, not only at enemies.
+    pub attack_players: bool,
+    /// Seconds without progress until the bot tries to jump over the obstacle.
+    pub stuck_time: f32,
+    /// Horizontal distance at which a waypoint counts as reached.
+    pub arrive_distance: f32,
+}
+
+impl Default for BotSettings {
+    fn default() -> Self {
+        BotSettings {
+            count: 0,
+            respawn_delay: 3.0,
+            speed: 3.0,
+            turn_speed: 6.0,
+            reaction_time: 0.3,
+            aim_tolerance: 0.05,
+            sight_range: 40.0,
+            attack_players: false,
+            stuck_time: 0.6,
+            arrive_distance: 0.6,
+        }
+    }
+}
+
+#[derive(Component, Default, Debug)]
+/// A player which is driven by a simple waypoint and aim brain instead of keyboard and mouse.
+pub struct Bot {
+    pub waypoint: Option<Vec3>,
+    /// Number of waypoints reached so far.
+    pub reached: u32,
+    pub target: Option<Entity>,
+    /// Seconds the target has been visible.
+    pub target_time: f32,
+    pub pitch: f32,
+    stuck_time: f32,
+}
+
+#[derive(Resource, Default, Debug)]
+/// Slots and timers of the bots waiting to respawn.
+pub struct BotRespawns(pub Vec<(u32, Timer)>);
+
+pub struct BotsPlugin;
+
+impl Plugin for BotsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<BotWaypoint>()
+            .register_type::<BotSettings>()
+            .init_resource::<BotSettings>()
+            .init_resource::<BotRespawns>()
+            .add_systems(OnEnter(AppState::Game), reset_bots)
+            .add_systems(Update, (
+                handle_bot_death,
+                // Wait for the level, so that the spawn markers exist.
+                spawn_bots.after(handle_bot_death).run_if(has_authority).run_if(world_ready),
+                choose_waypoints,
+                find_bot_targets,
+                drive_bots.after(choose_waypoints).after(find_bot_targets).before(fire_weapons),
+            ).run_if(in_state(AppState::Game)));
+    }
+}
+
+pub fn build_bot(commands: &mut Commands, transform: Transform) -> Entity {
+    let entity = build_player_body(commands, transform);
+    commands.entity(entity).insert((Name::new("Bot"), Bot::default()));
+    add_player_view(commands, entity);
+    entity
+}
+
+pub fn reset_bots(mut respawns: ResMut<BotRespawns>) {
+    respawns.0.clear();
+}
+
+pub fn handle_bot_death(
+    mut commands: Commands,
+    settings: Res<BotSettings>,
+    mut respawns: ResMut<BotRespawns>,
+    mut died: EventReader<PlayerDied>,
+    bots: Query<&Participant, With<Bot>>,
+) {
+    for event in died.read() {
+        let Ok(Participant::Bot(slot)) = bots.get(event.entity) else {
+            continue;
+        };
+        let Some(entity) = commands.get_entity(event.entity) else {
+            continue;
+        };
+        entity.despawn_recursive();
+        respawns.0.push((*slot, Timer::from_seconds(settings.respawn_delay, TimerMode::Once)));
+    }
+}
+
+pub fn spawn_bots(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<BotSettings>,
+    mut respawns: ResMut<BotRespawns>,
+    mut roster: ResMut<MatchRoster>,
+    spawns: Query<(&PlayerSpawn, &GlobalTransform)>,
+    players: Query<&GlobalTransform, (With<PlayerBody>, Without<Dead>)>,
+    bots: Query<Option<&Participant>, With<Bot>>,
+) {
+    respawns.0.iter_mut().for_each(|(_, timer)| {
+        timer.tick(time.delta());
+    });
+    respawns.0.retain(|(_, timer)| !timer.finished());
+
+    // Finished timers free their slot, which also fills up the bots after joining the game.
+    // A bot keeps its slot, so that it keeps its score in the match.
+    let taken: Vec<u32> = bots
+        .iter()
+        .flatten()
+        .filter_map(|participant| match participant {
+            Participant::Bot(slot) => Some(*slot),
+            _ => None,
+        })
+        .chain(respawns.0.iter().map(|(slot, _)| *slot))
+        .collect();
+    let missing = (settings.count as usize).saturating_sub(bots.iter().count() + respawns.0.len());
+    let free_slots = (0..).filter(|slot| !taken.contains(slot)).take(missing);
+    let mut occupied = occupied_positions(&players);
+    for slot in free_slots {
+        let participant = Participant::Bot(slot);
+        let name = format!("Bot {}", slot + 1);
+        let team = roster.join(participant, &name, 0);
+        let transform = choose_spawn(team, &spawns, &occupied).unwrap_or(Transform::from_xyz(0., 5., 0.));
+        occupied.push(transform.translation);
+        let entity = build_bot(&mut commands, transform);
+        commands.entity(entity).insert((Name::new(name), participant, Team(team)));
+    }
+}
+
+pub fn choose_waypoints(
+    mut commands: Commands,
+    settings: Res<BotSettings>,
+    waypoints: Query<&GlobalTransform, With<BotWaypoint>>,
+    spawns: Query<&GlobalTransform, With<PlayerSpawn>>,
+    mut bots: Query<(Entity, &mut Bot, &GlobalTransform)>,
+) {
+    let mut rng = rand::thread_rng();
+    let mut points: Vec<Vec3> = waypoints.iter().map(|transform| transform.translation()).collect();
+    if points.is_empty() {
+        points = spawns.iter().map(|transform| transform.translation()).collect();
+    }
+
+    for (entity, mut bot, transform) in bots.iter_mut() {
+        let position = transform.translation();
+        let reached = bot.waypoint.is_some_and(|waypoint| {
+            Vec2::new(waypoint.x - position.x, waypoint.z - position.z).length() <= settings.arrive_distance
+        });
+        if reached {
+            bot.reached += 1;
+        }
+        if bot.waypoint.is_some() && !reached {
+            continue;
+        }
+
+        // any other waypoint than the current one
+        let current = bot.waypoint;
+        let candidates: Vec<Vec3> = points.iter().copied().filter(|point| Some(*point) != current).collect();
+        bot.waypoint = candidates.choose(&mut rng).copied();
+        if let Some(goal) = bot.waypoint {
+            commands.entity(entity).insert(PathRequest { start: position, goal });
+        }
+    }
+}
+
+pub fn find_bot_targets(
+    time: Res<Time>,
+    settings: Res<BotSettings>,
+    rapier_context: Res<RapierContext>,
+    enemies: Query<(Entity, &GlobalTransform), With<Enemy>>,
+    players: Query<(Entity, &GlobalTransform, Option<&Team>), With<PlayerBody>>,
+    parents: Query<&Parent>,
+    mut bots: Query<(Entity, &mut Bot, &GlobalTransform, Option<&Team>)>,
+) {
+    for (entity, mut bot, transform, team) in bots.iter_mut() {
+        let eye = transform.translation() + Vec3::Y * VIEW_HEIGHT;
+        let team = team.map_or(0, |team| team.0);
+        let other_players = players
+            .iter()
+            .filter(|(player, _, other_team)| {
+                let teammate = team != 0 && other_team.is_some_and(|other_team| other_team.0 == team);
+                settings.attack_players && *player != entity && !teammate
+            })
+            .map(|(player, transform, _)| (player, transform));
+
+        // the closest target with nothing in between
+        let target = enemies
+            .iter()
+            .chain(other_players)
+            .filter_map(|(target, target_transform)| {
+                let to_target = target_transform.translation() - eye;
+                let distance = to_target.length();
+                if distance > settings.sight_range || distance <= f32::EPSILON {
+                    return None;
+                }
+                let filter = QueryFilter::new().exclude_sensors().exclude_rigid_body(entity);
+                let blocked = rapier_context
+                    .cast_ray(eye, to_target / distance, distance, true, filter)
+                    .is_some_and(|(hit, _)| !std::iter::once(hit).chain(parents.iter_ancestors(hit)).any(|hit| hit == target));
+                (!blocked).then_some((target, distance))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(target, _)| target);
+
+        if target.is_some() && target == bot.target {
+            bot.target_time += time.delta_seconds();
+        } else {
+            bot.target = target;
+            bot.target_time = 0.0;
+        }
+    }
+}
+
+/// The shorter way around, between `-PI` and `PI`.
+fn angle_difference(current: f32, target: f32) -> f32 {
+    (target - current + std::f32::consts::PI).rem_euclid(std::f32::consts::TAU) - std::f32::consts::PI
+}
+
+fn turn_towards(current: f32, target: f32, max_step: f32) -> f32 {
+    current + angle_difference(current, target).clamp(-max_step, max_step)
+}
+
+// fills the same `PlayerBody` fields as `apply_controls` and `apply_mouse` do for humans
+pub fn drive_bots(
+    time: Res<Time>,
+    settings: Res<BotSettings>,
+    targets: Query<&GlobalTransform>,
+    mut views: Query<&mut Transform, (With<PlayerView>, Without<PlayerBody>)>,
+    mut bots: Query<(
+        &mut Bot,
+        &mut PlayerBody,
+        &Transform,
+        &Velocity,
+        &Children,
+        Option<&mut NavPath>,
+        Option<&mut Weapon>,
+    )>,
+) {
+    let max_turn = settings.turn_speed * time.delta_seconds();
+
+    for (mut bot, mut body, transform, velocity, children, path, weapon) in bots.iter_mut() {
+        let position = transform.translation;
+
+        // Walk along the path, or straight to the waypoint without one.
+        let mut goal = bot.waypoint;
+        if let Some(mut path) = path {
+            let reached = |point: Vec3| Vec2::new(point.x - position.x, point.z - position.z).length() <= settings.arrive_distance;
+            while path.next_point().is_some_and(reached) {
+                path.current += 1;
+            }
+            goal = path.next_point().or(goal);
+        }
+        let direction = goal
+            .map(|goal| Vec3::new(goal.x - position.x, 0.0, goal.z - position.z).normalize_or_zero())
+            .unwrap_or(Vec3::ZERO);
+
+        // Aim at the target, otherwise look where it walks.
+        let aim_point = bot.target
+            .and_then(|target| targets.get(target).ok())
+            .map(|target| target.translation())
+            .or(goal.map(|goal| Vec3::new(goal.x, position.y + VIEW_HEIGHT, goal.z)));
+        let (yaw, _, _) = body.desired_rotation.to_euler(EulerRot::YXZ);
+        let mut aim_error = f32::MAX;
+        if let Some(aim_point) = aim_point {
+            let to_aim = aim_point - (position + Vec3::Y * VIEW_HEIGHT);
+            let target_yaw = f32::atan2(-to_aim.x, -to_aim.z);
+            let target_pitch = f32::atan2(to_aim.y, Vec2::new(to_aim.x, to_aim.z).length());
+            let new_yaw = turn_towards(yaw, target_yaw, max_turn);
+            bot.pitch = turn_towards(bot.pitch, target_pitch, max_turn);
+            body.desired_rotation = Quat::from_rotation_y(new_yaw);
+            aim_error = angle_difference(new_yaw, target_yaw).abs().max((target_pitch - bot.pitch).abs());
+        }
+        for child in children.iter() {
+            if let Ok(mut view) = views.get_mut(*child) {
+                view.rotation = Quat::from_rotation_x(bot.pitch);
+            }
+        }
+
+        body.desired_direction = direction;
+        body.desired_velocity = direction * settings.speed;
+        body.crouch = false;
+
+        // Jump if it wants to move but doesn't get anywhere.
+        let speed = Vec2::new(velocity.linvel.x, velocity.linvel.z).length();
+        if direction != Vec3::ZERO && speed < settings.speed * 0.2 {
+            bot.stuck_time += time.delta_seconds();
+        } else {
+            bot.stuck_time = 0.0;
+        }
+        body.jump = bot.stuck_time >= settings.stuck_time;
+        if body.jump {
+            bot.stuck_time = 0.0;
+        }
+
+        if let Some(mut weapon) = weapon {
+            let shoot = bot.target.is_some() && bot.target_time >= settings.reaction_time && aim_error <= settings.aim_tolerance;
+            weapon.trigger_pressed = shoot && !weapon.trigger_held;
+            weapon.trigger_held = shoot;
+            weapon.reload_requested = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::simulation::headless_app;
+
+    use super::*;
+
+    fn spawn_course(mut commands: Commands) {
+        commands.spawn((
+            TransformBundle::from_transform(Transform::from_xyz(0.0, -0.5, 0.0)),
+            Collider::cuboid(30.0, 0.5, 30.0),
+        ));
+        for (x, z) in [(-8.0, -8.0), (8.0, -8.0), (8.0, 8.0), (-8.0, 8.0)] {
+            commands.spawn((TransformBundle::from_transform(Transform::from_xyz(x, 0.0, z)), BotWaypoint));
+        }
+        for x in [-2.0, 0.0, 2.0] {
+            build_bot(&mut commands, Transform::from_xyz(x, 1.0, 0.0));
+        }
+    }
+
+    // bots go through the whole movement pipeline without a window or assets
+    #[test]
+    fn bots_walk_between_waypoints() {
+        let mut app = headless_app(64);
+        app.init_resource::<BotSettings>()
+            .add_systems(Startup, spawn_course)
+            .add_systems(Update, (
+                choose_waypoints,
+                find_bot_targets,
+                drive_bots.after(choose_waypoints).after(find_bot_targets),
+            ));
+
+        for _ in 0..(30 * 64) {
+            app.update();
+        }
+
+        let mut bots = app.world.query::<(&Bot, &Transform)>();
+        for (bot, transform) in bots.iter(&app.world) {
+            assert!(bot.reached >= 2, "a bot only reached {} waypoints", bot.reached);
+            assert!(transform.translation.y > -0.5, "a bot fell through the floor");
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::VecDeque;
+
+use bevy::gltf::Gltf;
+use bevy::prelude::*;
+use bevy::scene::{SceneInstance, SceneSpawner};
+use bevy::utils::HashMap;
+use bevy_rapier3d::prelude::*;
+
+use crate::app::{AppState, MyAssets};
+use crate::game::health::{apply_damage, Died, Health};
+use crate::game::simulation::{SimulationSet, SimulationSettings};
+use crate::game::world::GameEntity;
+
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+/// Breaks into pre-fractured pieces, either from a hard impact or when its health runs out.
+/// The pieces are the scene `<name>_fractured` in `World.glb`, e.g. `crate_fractured` for `crate.001`.
+pub struct Destructible {
+    /// Minimum impulse of a single contact which breaks it.
+    pub impulse_threshold: f32,
+    /// It can't be damaged if zero.
+    pub health: f32,
+    /// Scene with the pieces, derived from the node name if empty.
+    pub pieces: String,
+}
+
+impl Default for Destructible {
+    fn default() -> Self {
+        Destructible {
+            impulse_threshold: 20.0,
+            health: 50.0,
+            pieces: String::new(),
+        }
+    }
+}
+
+#[derive(Resource, Reflect, Debug)]
+#[reflect(Resource)]
+pub struct DebrisSettings {
+    /// Seconds until pieces are removed.
+    pub lifetime: f32,
+    /// The oldest pieces are removed early when there are more than this.
+    pub max_pieces: usize,
+    /// Part of the breaking impulse which is passed on to the pieces.
+    pub impulse_transfer: f32,
+}
+
+impl Default for DebrisSettings {
+    fn default() -> Self {
+        DebrisSettings {
+            lifetime: 15.0,
+            max_pieces: 128,
+            impulse_transfer: 0.5,
+        }
+    }
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+/// Breaks a destructible, sent for strong impacts and when it dies.
+pub struct BreakDestructible {
+    pub entity: Entity,
+    pub impulse: Vec3,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct Destructed {
+    pub entity: Entity,
+    pub pieces: Entity,
+}
+
+#[derive(Component, Debug)]
+/// Pieces scene which waits to be spawned, before it gets physics.
+pub struct FracturedPieces {
+    velocity: Velocity,
+    impulse: Vec3,
+}
+
+#[derive(Component, Debug)]
+pub struct Debris {
+    timer: Timer,
+}
+
+#[derive(Resource, Default, Debug)]
+/// All debris, oldest first, with their number of pieces.
+pub struct DebrisQueue {
+    entries: VecDeque<(Entity, usize)>,
+    pieces: usize,
+}
+
+pub struct DestructiblesPlugin;
+
+impl Plugin for DestructiblesPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Destructible>()
+            .register_type::<DebrisSettings>()
+            .init_resource::<DebrisSettings>()
+            .init_resource::<DebrisQueue>()
+            .add_event::<BreakDestructible>()
+            .add_event::<Destructed>()
+            .add_systems(Update, destructible_replace_proxies)
+            .add_systems(Update, (
+                break_dead_destructibles.after(apply_damage),
+                fracture.after(break_dead_destructibles),
+                activate_pieces,
+                expire_debris,
+            ).run_if(in_state(AppState::Game)))
+            .add_systems(FixedUpdate, break_on_impact
+                .in_set(SimulationSet::Record)
+                .run_if(in_state(AppState::Game)));
+    }
+}
+
+pub fn destructible_replace_proxies(
+    mut commands: Commands,
+    settings: Res<SimulationSettings>,
+    added_destructibles: Query<(Entity, &Destructible), Added<Destructible>>,
+    added_colliders: Query<Entity, Added<Collider>>,
+    destructibles: Query<&Destructible>,
+    parents: Query<&Parent>,
+) {
+    // rapier compares forces, the threshold is an impulse within one tick
+    let force_events = |destructible: &Destructible| (
+        ActiveEvents::CONTACT_FORCE_EVENTS,
+        ContactForceEventThreshold(destructible.impulse_threshold * settings.tick_rate as f32),
+    );
+    for (entity, destructible) in added_destructibles.iter() {
+        let mut cmd = commands.entity(entity);
+        cmd.insert(force_events(destructible));
+        if destructible.health > 0.0 {
+            cmd.insert(Health::new(destructible.health));
+        }
+    }
+    // The colliders are often children of the destructible, e.g. the `*_collider` proxies.
+    for collider in added_colliders.iter() {
+        match destructible_of(collider, &destructibles, &parents) {
+            Some((entity, destructible)) if entity != collider => {
+                commands.entity(collider).insert(force_events(destructible));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The destructible a collider belongs to, the collider itself or one of its ancestors.
+fn destructible_of<'a>(
+    collider: Entity,
+    destructibles: &'a Query<&Destructible>,
+    parents: &Query<&Parent>,
+) -> Option<(Entity, &'a Destructible)> {
+    std::iter::once(collider)
+        .chain(parents.iter_ancestors(collider))
+        .find_map(|entity| destructibles.get(entity).ok().map(|destructible| (entity, destructible)))
+}
+
+pub fn break_on_impact(
+    time: Res<Time>,
+    mut contact_forces: EventReader<ContactForceEvent>,
+    destructibles: Query<&Destructible>,
+    parents: Query<&Parent>,
+    mut breaks: EventWriter<BreakDestructible>,
+) {
+    for event in contact_forces.read() {
+        // the total force acts on the first collider
+        for (collider, force) in [(event.collider1, event.total_force), (event.collider2, -event.total_force)] {
+            let Some((entity, destructible)) = destructible_of(collider, &destructibles, &parents) else {
+                continue;
+            };
+            let impulse = force * time.delta_seconds();
+            if impulse.length() >= destructible.impulse_threshold {
+                breaks.send(BreakDestructible { entity, impulse });
+            }
+        }
+    }
+}
+
+pub fn break_dead_destructibles(
+    mut died: EventReader<Died>,
+    destructibles: Query<(), With<Destructible>>,
+    mut breaks: EventWriter<BreakDestructible>,
+) {
+    for event in died.read() {
+        if destructibles.contains(event.entity) {
+            breaks.send(BreakDestructible {
+                entity: event.entity,
+                impulse: Vec3::ZERO,
+            });
+        }
+    }
+}
+
+pub fn fracture(
+    mut commands: Commands,
+    assets: Res<MyAssets>,
+    assets_gltf: Res<Assets<Gltf>>,
+    mut breaks: EventReader<BreakDestructible>,
+    destructibles: Query<(&Destructible, Option<&Name>, &GlobalTransform, Option<&Velocity>)>,
+    mut destructed: EventWriter<Destructed>,
+) {
+    // An impact and the death can both happen in one frame, it only breaks once.
+    let mut impulses: HashMap<Entity, Vec3> = HashMap::new();
+    for event in breaks.read() {
+        *impulses.entry(event.entity).or_default() += event.impulse;
+    }
+
+    for (entity, impulse) in impulses {
+        let Ok((destructible, name, transform, velocity)) = destructibles.get(entity) else {
+            continue;
+        };
+        commands.entity(entity).despawn_recursive();
+
+        // Blender adds `.001` and so on to copies of an object.
+        let scene_name = if destructible.pieces.is_empty() {
+            let name = name.map_or("", |name| name.as_str());
+            format!("{}_fractured", name.split('.').next().unwrap_or(name))
+        } else {
+            destructible.pieces.clone()
+        };
+        let Some(scene) = assets_gltf
+            .get(&assets.world)
+            .and_then(|gltf| gltf.named_scenes.get(scene_name.as_str()))
+        else {
+            warn!("no fractured scene `{}` found", scene_name);
+            continue;
+        };
+
+        let pieces = commands
+            .spawn((
+                Name::new(scene_name),
+                GameEntity,
+                SceneBundle {
+                    scene: scene.clone(),
+                    transform: transform.compute_transform(),
+                    ..default()
+                },
+                FracturedPieces {
+                    velocity: velocity.copied().unwrap_or_default(),
+                    impulse,
+                },
+            ))
+            .id();
+        destructed.send(Destructed { entity, pieces });
+    }
+}
+
+// gives every piece its own body once the scene is spawned
+pub fn activate_pieces(
+    mut commands: Commands,
+    settings: Res<DebrisSettings>,
+    scene_spawner: Res<SceneSpawner>,
+    mut queue: ResMut<DebrisQueue>,
+    meshes: Res<Assets<Mesh>>,
+    spawned: Query<(Entity, &SceneInstance, &FracturedPieces, &Children)>,
+    children: Query<&Children>,
+    mesh_handles: Query<&Handle<Mesh>>,
+) {
+    for (entity, instance, fractured, roots) in spawned.iter() {
+        if !scene_spawner.instance_is_ready(**instance) {
+            continue;
+        }
+
+        // The scene roots are the pieces, their meshes are in the children.
+        let pieces: Vec<(Entity, Collider)> = roots
+            .iter()
+            .flat_map(|root| children.iter_descendants(*root).chain(std::iter::once(*root)))
+            .filter_map(|node| {
+                let mesh = meshes.get(mesh_handles.get(node).ok()?)?;
+                let collider = Collider::from_bevy_mesh(mesh, &ComputedColliderShape::ConvexHull)?;
+                Some((node, collider))
+            })
+            .collect();
+
+        let impulse = fractured.impulse * settings.impulse_transfer / pieces.len().max(1) as f32;
+        for (piece, collider) in pieces.iter().cloned() {
+            commands.entity(piece).insert((
+                RigidBody::Dynamic,
+                collider,
+                fractured.velocity,
+                ExternalImpulse { impulse, ..default() },
+            ));
+        }
+
+        commands.entity(entity)
+            .remove::<FracturedPieces>()
+            .insert(Debris {
+                timer: Timer::from_seconds(settings.lifetime, TimerMode::Once),
+            });
+
+        queue.entries.push_back((entity, pieces.len()));
+        queue.pieces += pieces.len();
+        while queue.pieces > settings.max_pieces {
+            let Some((oldest, count)) = queue.entries.pop_front() else {
+                break;
+            };
+            queue.pieces -= count;
+            commands.entity(oldest).despawn_recursive();
+        }
+    }
+}
+
+pub fn expire_debris(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut queue: ResMut<DebrisQueue>,
+    mut debris: Query<(Entity, &mut Debris)>,
+) {
+    for (entity, mut debris) in debris.iter_mut() {
+        if !debris.timer.tick(time.delta()).finished() {
+            continue;
+        }
+        commands.entity(entity).despawn_recursive();
+
+        let index = queue.entries.iter().position(|(queued, _)| *queued == entity);
+        if let Some((_, count)) = index.and_then(|index| queue.entries.remove(index)) {
+            queue.pieces -= count;
+        }
+    }
+}
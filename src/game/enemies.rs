@@ -0,0 +1,543 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use bevy_tnua::control_helpers::TnuaCrouchEnforcer;
+use bevy_tnua::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::app::AppState;
+use crate::game::health::{apply_damage, DamageEvent, Died, Health};
+use crate::game::movement_events::PlayerFootstep;
+use crate::game::navigation::{NavPath, PathRequest};
+use crate::game::player::{insert_character_physics, spawn_hitbox, walk, PlayerBody};
+use crate::game::simulation::SimulationSet;
+use crate::game::weapons::{apply_weapon_hits, fire_weapons, HitboxMultiplier, WeaponFired, WeaponHit};
+use crate::game::world::GameEntity;
+
+#[derive(Component, Reflect, Deserialize, Debug, Clone)]
+#[reflect(Component)]
+#[serde(default)]
+/// An NPC fighting the players. Place it in Blender or spawn it with `build_enemy`.
+/// It moves with the same controller as the player.
+pub struct Enemy {
+    pub sight_range: f32,
+    /// Full opening angle of the sight cone in degrees.
+    pub sight_angle: f32,
+    /// Multiplier for the range in which noises are heard.
+    pub hearing: f32,
+    pub walk_speed: f32,
+    pub run_speed: f32,
+    pub health: f32,
+    /// It flees once the health drops below this part of the maximum.
+    pub flee_health: f32,
+    pub attack_range: f32,
+    pub attack_damage: f32,
+    /// Shots per second.
+    pub attack_rate: f32,
+    /// Maximum deviation of a shot in radians.
+    pub spread: f32,
+    /// World positions walked in a loop while nothing happens.
+    #[serde(skip)]
+    pub patrol: Vec<Vec3>,
+}
+
+impl Default for Enemy {
+    fn default() -> Self {
+        Enemy {
+            sight_range: 25.0,
+            sight_angle: 120.0,
+            hearing: 1.0,
+            walk_speed: 1.5,
+            run_speed: 3.0,
+            health: 60.0,
+            flee_health: 0.25,
+            attack_range: 15.0,
+            attack_damage: 8.0,
+            attack_rate: 1.5,
+            spread: 0.05,
+            patrol: Vec::new(),
+        }
+    }
+}
+
+#[derive(Resource, Reflect, Debug)]
+#[reflect(Resource)]
+pub struct EnemySettings {
+    /// Distance at which shots can be heard.
+    pub gunshot_noise: f32,
+    /// Distance at which footsteps can be heard.
+    pub footstep_noise: f32,
+    /// Seconds an enemy keeps chasing a target it can't see anymore.
+    pub memory: f32,
+    /// Seconds spent looking around at a noise.
+    pub investigate_time: f32,
+    /// Seconds to wait before patrolling again.
+    pub idle_time: f32,
+    /// Height of the eyes above the body origin.
+    pub eye_height: f32,
+    /// Minimum seconds between two path requests.
+    pub repath_interval: f32,
+    /// Horizontal distance at which a point counts as reached.
+    pub arrive_distance: f32,
+}
+
+impl Default for EnemySettings {
+    fn default() -> Self {
+        EnemySettings {
+            gunshot_noise: 40.0,
+            footstep_noise: 10.0,
+            memory: 5.0,
+            investigate_time: 4.0,
+            idle_time: 3.0,
+            eye_height: 0.4,
+            repath_interval: 0.5,
+            arrive_distance: 0.5,
+        }
+    }
+}
+
+#[derive(Reflect, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnemyState {
+    #[default]
+    Idle,
+    Patrol,
+    /// Walks to something it heard and looks around there.
+    Investigate,
+    /// Follows a target, or walks to where it was seen last.
+    Chase,
+    /// Stands still and shoots at a visible target.
+    Attack,
+    /// Runs away from the target while badly hurt.
+    Flee,
+}
+
+#[derive(Component, Default, Debug)]
+/// Perception and state of an enemy.
+pub struct EnemyBrain {
+    pub state: EnemyState,
+    /// Seconds in the current state.
+    pub state_time: f32,
+    pub target: Option<Entity>,
+    pub target_visible: bool,
+    /// Where the target was seen last, cleared once it is forgotten.
+    pub last_known: Option<Vec3>,
+    /// Seconds since the target was seen.
+    pub last_seen: f32,
+    /// Position of the latest noise, cleared once it was investigated.
+    pub heard: Option<Vec3>,
+    /// Seconds spent at the investigated position.
+    pub searching: f32,
+    pub patrol_index: usize,
+    pub attack_cooldown: f32,
+    /// Where it currently walks to.
+    pub destination: Option<Vec3>,
+    requested: Option<Vec3>,
+    repath_cooldown: f32,
+}
+
+#[derive(Component, Default, Debug)]
+/// Movement input of an enemy, the equivalent of `PlayerBody`.
+pub struct EnemyBody {
+    pub desired_rotation: Quat,
+    pub desired_velocity: Vec3,
+    pub jump: bool,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+/// Something enemies can hear, e.g. shots and footsteps.
+pub struct Noise {
+    pub source: Entity,
+    pub position: Vec3,
+    /// Distance at which it can still be heard.
+    pub range: f32,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct EnemyKilled {
+    pub entity: Entity,
+    pub killer: Option<Entity>,
+}
+
+pub struct EnemiesPlugin;
+
+impl Plugin for EnemiesPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Enemy>()
+            .register_type::<EnemySettings>()
+            .init_resource::<EnemySettings>()
+            .add_event::<Noise>()
+            .add_event::<EnemyKilled>()
+            .add_systems(Update, enemy_replace_proxies)
+            .add_systems(Update, (
+                emit_noises.after(fire_weapons),
+                perceive.after(emit_noises),
+                update_enemy_states.after(perceive),
+                steer_enemies.after(update_enemy_states),
+                enemy_attacks.after(update_enemy_states).before(apply_weapon_hits),
+                kill_enemies.after(apply_damage),
+            ).run_if(in_state(AppState::Game)))
+            .add_systems(FixedUpdate, execute_enemy_move
+                .in_set(SimulationSet::Movement)
+                .run_if(in_state(AppState::Game)));
+    }
+}
+
+pub fn build_enemy(commands: &mut Commands, transform: Transform, enemy: Enemy) -> Entity {
+    commands.spawn((
+        Name::new("Enemy"),
+        GameEntity,
+        TransformBundle::from_transform(transform),
+        VisibilityBundle::default(),
+        enemy,
+    )).id()
+}
+
+pub fn enemy_replace_proxies(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut placeholder: Local<Option<(Handle<Mesh>, Handle<StandardMaterial>)>>,
+    added_enemies: Query<(Entity, &Enemy, &Transform, Option<&Children>), Added<Enemy>>,
+) {
+    for (entity, enemy, transform, children) in added_enemies.iter() {
+        let (yaw, _, _) = transform.rotation.to_euler(EulerRot::YXZ);
+
+        let mut cmd = commands.entity(entity);
+        insert_character_physics(&mut cmd);
+        cmd.insert((
+            EnemyBody {
+                desired_rotation: Quat::from_rotation_y(yaw),
+                ..default()
+            },
+            EnemyBrain::default(),
+            Health::new(enemy.health),
+        ));
+
+        cmd.with_children(spawn_hitbox);
+
+        // Enemies placed in Blender bring their own mesh.
+        if children.is_none() {
+            let (mesh, material) = placeholder.get_or_insert_with(|| (
+                meshes.add(Mesh::from(shape::Capsule {
+                    radius: 0.25,
+                    depth: 0.6,
+                    ..default()
+                })),
+                materials.add(Color::rgb(0.7, 0.15, 0.1).into()),
+            )).clone();
+            cmd.with_children(|parent| {
+                parent.spawn(PbrBundle {
+                    mesh,
+                    material,
+                    transform: Transform::from_xyz(0.0, 0.2, 0.0),
+                    ..default()
+                });
+            });
+        }
+    }
+}
+
+pub fn emit_noises(
+    settings: Res<EnemySettings>,
+    mut fired: EventReader<WeaponFired>,
+    mut footsteps: EventReader<PlayerFootstep>,
+    transforms: Query<&GlobalTransform>,
+    mut noises: EventWriter<Noise>,
+) {
+    for event in fired.read() {
+        noises.send(Noise {
+            source: event.shooter,
+            position: event.origin,
+            range: settings.gunshot_noise,
+        });
+    }
+    for event in footsteps.read() {
+        let Ok(transform) = transforms.get(event.entity) else {
+            continue;
+        };
+        noises.send(Noise {
+            source: event.entity,
+            position: transform.translation(),
+            range: settings.footstep_noise,
+        });
+    }
+}
+
+pub fn perceive(
+    time: Res<Time>,
+    settings: Res<EnemySettings>,
+    rapier_context: Res<RapierContext>,
+    mut noises: EventReader<Noise>,
+    mut damage: EventReader<DamageEvent>,
+    players: Query<(Entity, &GlobalTransform), With<PlayerBody>>,
+    enemy_query: Query<(), With<Enemy>>,
+    parents: Query<&Parent>,
+    mut enemies: Query<(Entity, &Enemy, &mut EnemyBrain, &GlobalTransform)>,
+) {
+    let noises: Vec<Noise> = noises.read().copied().collect();
+    let damage: Vec<DamageEvent> = damage.read().copied().collect();
+
+    for (entity, enemy, mut brain, transform) in enemies.iter_mut() {
+        let eye = transform.translation() + Vec3::Y * settings.eye_height;
+        let forward = transform.forward();
+        let half_angle = (enemy.sight_angle * 0.5).to_radians();
+
+        // the closest player inside the sight cone without anything in between
+        let visible = players
+            .iter()
+            .filter_map(|(player, player_transform)| {
+                let target = player_transform.translation();
+                let to_target = target - eye;
+                let distance = to_target.length();
+                if distance > enemy.sight_range || distance <= f32::EPSILON || forward.angle_between(to_target) > half_angle {
+                    return None;
+                }
+                let filter = QueryFilter::new().exclude_sensors().exclude_rigid_body(entity);
+                let blocked = rapier_context
+                    .cast_ray(eye, to_target / distance, distance, true, filter)
+                    .is_some_and(|(hit, _)| !std::iter::once(hit).chain(parents.iter_ancestors(hit)).any(|hit| hit == player));
+                (!blocked).then_some((player, target, distance))
+            })
+            .min_by(|a, b| a.2.total_cmp(&b.2));
+
+        brain.last_seen += time.delta_seconds();
+        brain.target_visible = visible.is_some();
+        if let Some((player, target, _)) = visible {
+            brain.target = Some(player);
+            brain.last_known = Some(target);
+            brain.last_seen = 0.0;
+        }
+
+        // Enemies don't react to each other, only to the players.
+        for noise in noises.iter().filter(|noise| !enemy_query.contains(noise.source)) {
+            if noise.position.distance(transform.translation()) <= noise.range * enemy.hearing {
+                brain.heard = Some(noise.position);
+                brain.searching = 0.0;
+            }
+        }
+
+        // getting hit reveals where the shot came from
+        for event in damage.iter().filter(|event| event.target == entity) {
+            let Some((source, source_transform)) = event.source.and_then(|source| players.get(source).ok()) else {
+                continue;
+            };
+            brain.target = Some(source);
+            brain.heard = Some(source_transform.translation());
+            brain.searching = 0.0;
+        }
+    }
+}
+
+fn horizontal_distance(a: Vec3, b: Vec3) -> f32 {
+    Vec2::new(a.x - b.x, a.z - b.z).length()
+}
+
+pub fn update_enemy_states(
+    time: Res<Time>,
+    settings: Res<EnemySettings>,
+    targets: Query<&GlobalTransform>,
+    mut enemies: Query<(&Enemy, &mut EnemyBrain, &Health, &GlobalTransform)>,
+) {
+    for (enemy, mut brain, health, transform) in enemies.iter_mut() {
+        let brain = brain.as_mut();
+        let position = transform.translation();
+        brain.state_time += time.delta_seconds();
+        brain.attack_cooldown = (brain.attack_cooldown - time.delta_seconds()).max(0.0);
+
+        // A lost target is searched for where it was seen last.
+        if let Some(last_known) = brain.last_known.filter(|_| !brain.target_visible) {
+            if brain.last_seen > settings.memory || horizontal_distance(position, last_known) <= settings.arrive_distance {
+                brain.last_known = None;
+                brain.heard = Some(last_known);
+                brain.searching = 0.0;
+            }
+        }
+        if let Some(heard) = brain.heard {
+            if horizontal_distance(position, heard) <= settings.arrive_distance {
+                brain.searching += time.delta_seconds();
+            }
+            if brain.searching >= settings.investigate_time {
+                brain.heard = None;
+                brain.searching = 0.0;
+            }
+        }
+
+        let target_position = brain.target.and_then(|target| targets.get(target).ok()).map(|target| target.translation());
+        let in_range = target_position.is_some_and(|target| target.distance(position) <= enemy.attack_range);
+        let next = if brain.last_known.is_some() && health.current <= health.max * enemy.flee_health {
+            EnemyState::Flee
+        } else if brain.target_visible && in_range {
+            EnemyState::Attack
+        } else if brain.last_known.is_some() {
+            EnemyState::Chase
+        } else if brain.heard.is_some() {
+            EnemyState::Investigate
+        } else if !enemy.patrol.is_empty() && (brain.state == EnemyState::Patrol || brain.state_time >= settings.idle_time) {
+            EnemyState::Patrol
+        } else {
+            EnemyState::Idle
+        };
+        if next != brain.state {
+            brain.state = next;
+            brain.state_time = 0.0;
+        }
+
+        brain.destination = match brain.state {
+            EnemyState::Idle | EnemyState::Attack => None,
+            EnemyState::Patrol => {
+                let mut point = enemy.patrol[brain.patrol_index % enemy.patrol.len()];
+                if horizontal_distance(position, point) <= settings.arrive_distance {
+                    brain.patrol_index = (brain.patrol_index + 1) % enemy.patrol.len();
+                    point = enemy.patrol[brain.patrol_index];
+                }
+                Some(point)
+            }
+            EnemyState::Investigate => brain.heard,
+            EnemyState::Chase => if brain.target_visible { target_position } else { brain.last_known },
+            EnemyState::Flee => brain.last_known.map(|threat| {
+                let away = Vec3::new(position.x - threat.x, 0.0, position.z - threat.z).normalize_or_zero();
+                position + away * 10.0
+            }),
+        };
+    }
+}
+
+pub fn steer_enemies(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<EnemySettings>,
+    targets: Query<&GlobalTransform>,
+    mut enemies: Query<(Entity, &Enemy, &mut EnemyBrain, &mut EnemyBody, &GlobalTransform, Option<&mut NavPath>)>,
+) {
+    for (entity, enemy, mut brain, mut body, transform, path) in enemies.iter_mut() {
+        let position = transform.translation();
+        brain.repath_cooldown -= time.delta_seconds();
+
+        let destination = brain.destination.filter(|destination| {
+            horizontal_distance(position, *destination) > settings.arrive_distance
+        });
+        let Some(destination) = destination else {
+            body.desired_velocity = Vec3::ZERO;
+            // keep facing the target while shooting
+            let target = brain.target.filter(|_| brain.state == EnemyState::Attack).and_then(|target| targets.get(target).ok());
+            if let Some(target) = target {
+                let direction = target.translation() - position;
+                body.desired_rotation = Quat::from_rotation_y(f32::atan2(-direction.x, -direction.z));
+            }
+            continue;
+        };
+
+        // Paths are only requested again if the destination moved noticeably.
+        let moved = brain.requested.map_or(true, |requested| requested.distance(destination) > settings.arrive_distance);
+        if moved && brain.repath_cooldown <= 0.0 {
+            commands.entity(entity).insert(PathRequest {
+                start: position,
+                goal: destination,
+            });
+            brain.requested = Some(destination);
+            brain.repath_cooldown = settings.repath_interval;
+        }
+
+        // Without a path (yet) it walks straight to the destination.
+        let mut waypoint = destination;
+        if let Some(mut path) = path {
+            while path.next_point().is_some_and(|point| horizontal_distance(position, point) <= settings.arrive_distance) {
+                path.current += 1;
+            }
+            if let Some(point) = path.next_point() {
+                waypoint = point;
+            }
+        }
+
+        let direction = Vec3::new(waypoint.x - position.x, 0.0, waypoint.z - position.z).normalize_or_zero();
+        let speed = match brain.state {
+            EnemyState::Chase | EnemyState::Flee => enemy.run_speed,
+            _ => enemy.walk_speed,
+        };
+        body.desired_velocity = direction * speed;
+        if direction != Vec3::ZERO {
+            body.desired_rotation = Quat::from_rotation_y(f32::atan2(-direction.x, -direction.z));
+        }
+    }
+}
+
+pub fn enemy_attacks(
+    settings: Res<EnemySettings>,
+    rapier_context: Res<RapierContext>,
+    targets: Query<&GlobalTransform>,
+    multipliers: Query<&HitboxMultiplier>,
+    mut enemies: Query<(Entity, &Enemy, &mut EnemyBrain, &GlobalTransform)>,
+    mut fired: EventWriter<WeaponFired>,
+    mut hits: EventWriter<WeaponHit>,
+) {
+    let mut rng = rand::thread_rng();
+
+    for (entity, enemy, mut brain, transform) in enemies.iter_mut() {
+        if brain.state != EnemyState::Attack || !brain.target_visible || brain.attack_cooldown > 0.0 {
+            continue;
+        }
+        let Some(target) = brain.target.and_then(|target| targets.get(target).ok()) else {
+            continue;
+        };
+        brain.attack_cooldown = 1.0 / enemy.attack_rate.max(f32::EPSILON);
+
+        let origin = transform.translation() + Vec3::Y * settings.eye_height;
+        let deviation = Quat::from_euler(
+            EulerRot::YXZ,
+            rng.gen_range(-enemy.spread..=enemy.spread),
+            rng.gen_range(-enemy.spread..=enemy.spread),
+            0.0,
+        );
+        let direction = deviation * (target.translation() - origin).normalize_or_zero();
+        fired.send(WeaponFired {
+            shooter: entity,
+            origin,
+            direction,
+        });
+
+        // The shots go through the weapon hits, so they deal damage like the player's.
+        let filter = QueryFilter::new().exclude_sensors().exclude_rigid_body(entity);
+        let Some((hit, intersection)) =
+            rapier_context.cast_ray_and_get_normal(origin, direction, enemy.attack_range * 1.5, true, filter)
+        else {
+            continue;
+        };
+        let multiplier = multipliers.get(hit).map_or(1.0, |multiplier| multiplier.0);
+        hits.send(WeaponHit {
+            shooter: entity,
+            entity: hit,
+            point: intersection.point,
+            normal: intersection.normal,
+            distance: intersection.toi,
+            multiplier,
+            damage: enemy.attack_damage * multiplier,
+        });
+    }
+}
+
+pub fn kill_enemies(
+    mut commands: Commands,
+    mut died: EventReader<Died>,
+    enemies: Query<(), With<Enemy>>,
+    mut killed: EventWriter<EnemyKilled>,
+) {
+    for event in died.read() {
+        if !enemies.contains(event.entity) {
+            continue;
+        }
+        commands.entity(event.entity).despawn_recursive();
+        killed.send(EnemyKilled {
+            entity: event.entity,
+            killer: event.killer,
+        });
+    }
+}
+
+pub fn execute_enemy_move(
+    mut enemies: Query<(&mut TnuaController, &mut TnuaCrouchEnforcer, &mut Transform, &EnemyBody)>,
+) {
+    for (mut controller, mut crouch_enforcer, mut transform, body) in enemies.iter_mut() {
+        transform.rotation = body.desired_rotation;
+        walk(&mut controller, &mut crouch_enforcer, body.desired_velocity, body.jump, false);
+    }
+}
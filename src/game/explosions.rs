@@ -0,0 +1,187 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_rapier3d::prelude::*;
+use serde::Deserialize;
+
+use crate::app::AppState;
+use crate::game::health::{apply_damage, find_health_owner, DamageEvent, DamageKind, Health};
+use crate::game::network::has_authority;
+use crate::game::projectiles::ProjectileDetonated;
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+/// Explosion stats, e.g. part of a `ProjectileDefinition`.
+pub struct ExplosionDefinition {
+    pub radius: f32,
+    /// Impulse at the center, falling off to zero at the radius.
+    pub impulse: f32,
+    /// Damage at the center, falling off to zero at the radius.
+    pub damage: f32,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct Explosion {
+    pub origin: Vec3,
+    pub source: Option<Entity>,
+    /// The projectile which exploded. Its collider is only disabled in the next tick, so it is
+    /// left out of the queries.
+    pub projectile: Option<Entity>,
+    pub definition: ExplosionDefinition,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+/// Sent for every body (or static collider) in range which can be seen from the explosion.
+pub struct ExplosionHit {
+    pub entity: Entity,
+    /// `1.0` at the center, `0.0` at the radius.
+    pub falloff: f32,
+    pub origin: Vec3,
+    pub source: Option<Entity>,
+    pub damage: f32,
+}
+
+pub struct ExplosionsPlugin;
+
+impl Plugin for ExplosionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Explosion>()
+            .add_event::<ExplosionHit>()
+            .add_systems(Update, (
+                detonate_projectiles,
+                explode.after(detonate_projectiles),
+                apply_explosion_damage.after(explode).before(apply_damage),
+            ).run_if(has_authority).run_if(in_state(AppState::Game)));
+    }
+}
+
+pub fn detonate_projectiles(mut detonations: EventReader<ProjectileDetonated>, mut explosions: EventWriter<Explosion>) {
+    for detonation in detonations.read() {
+        if let Some(definition) = detonation.explosion {
+            explosions.send(Explosion {
+                origin: detonation.point,
+                source: Some(detonation.shooter),
+                projectile: Some(detonation.projectile),
+                definition,
+            });
+        }
+    }
+}
+
+pub fn explode(
+    mut commands: Commands,
+    rapier_context: Res<RapierContext>,
+    mut explosions: EventReader<Explosion>,
+    transforms: Query<&GlobalTransform>,
+    rigid_bodies: Query<&RigidBody>,
+    mut impulses: Query<&mut ExternalImpulse>,
+    mut hits: EventWriter<ExplosionHit>,
+) {
+    for explosion in explosions.read() {
+        let radius = explosion.definition.radius;
+        let mut filter = QueryFilter::new().exclude_sensors();
+        if let Some(projectile) = explosion.projectile {
+            filter = filter.exclude_collider(projectile).exclude_rigid_body(projectile);
+        }
+
+        let mut colliders = Vec::new();
+        rapier_context.intersections_with_shape(
+            explosion.origin,
+            Quat::IDENTITY,
+            &Collider::ball(radius),
+            filter,
+            |entity| {
+                colliders.push(entity);
+                true
+            },
+        );
+
+        // Several colliders can belong to the same body, it should only be pushed and hit once,
+        // from the point of its colliders which is closest to the center.
+        let mut closest: HashMap<Entity, (Entity, Vec3)> = HashMap::default();
+        for collider in colliders {
+            let entity = rapier_context.collider_parent(collider).unwrap_or(collider);
+            let only_collider = QueryFilter::new().predicate(&|other| other == collider);
+            let Some((_, projection)) = rapier_context.project_point(explosion.origin, true, only_collider) else {
+                continue;
+            };
+            let distance = projection.point.distance(explosion.origin);
+            match closest.get(&entity) {
+                Some((_, point)) if point.distance(explosion.origin) <= distance => {}
+                _ => {
+                    closest.insert(entity, (collider, projection.point));
+                }
+            }
+        }
+
+        for (entity, (collider, point)) in closest {
+            let offset = point - explosion.origin;
+            let falloff = (1.0 - offset.length() / radius).clamp(0.0, 1.0);
+            // The center is inside the collider, push away from the body instead.
+            let direction = match offset.try_normalize() {
+                Some(direction) => direction,
+                None => transforms.get(entity).map_or(Vec3::ZERO, |transform| {
+                    (transform.translation() - explosion.origin).normalize_or_zero()
+                }),
+            };
+
+            if matches!(rigid_bodies.get(entity), Ok(RigidBody::Dynamic)) {
+                let impulse = direction * explosion.definition.impulse * falloff;
+                match impulses.get_mut(entity) {
+                    Ok(mut external_impulse) => external_impulse.impulse += impulse,
+                    Err(_) => {
+                        commands.entity(entity).insert(ExternalImpulse {
+                            impulse,
+                            ..default()
+                        });
+                    }
+                }
+            }
+
+            // Only things which are directly visible from the center get hit.
+            let visible = rapier_context
+                .cast_ray(
+                    explosion.origin,
+                    offset.normalize_or_zero(),
+                    offset.length(),
+                    true,
+                    filter,
+                )
+                .map_or(true, |(blocker, _)| {
+                    blocker == collider || rapier_context.collider_parent(blocker) == Some(entity)
+                });
+            if visible {
+                hits.send(ExplosionHit {
+                    entity,
+                    falloff,
+                    origin: explosion.origin,
+                    source: explosion.source,
+                    damage: explosion.definition.damage,
+                });
+            }
+        }
+    }
+}
+
+pub fn apply_explosion_damage(
+    mut hits: EventReader<ExplosionHit>,
+    health_query: Query<(), With<Health>>,
+    parents: Query<&Parent>,
+    mut damage: EventWriter<DamageEvent>,
+) {
+    for hit in hits.read() {
+        let Some(target) = find_health_owner(hit.entity, &health_query, &parents) else {
+            continue;
+        };
+        let amount = hit.damage * hit.falloff;
+        if amount <= 0.0 {
+            continue;
+        }
+
+        damage.send(DamageEvent {
+            target,
+            source: hit.source,
+            amount,
+            kind: DamageKind::Explosion,
+            hit_point: Some(hit.origin),
+        });
+    }
+}
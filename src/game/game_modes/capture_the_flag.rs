@@ -0,0 +1,387 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::app::AppState;
+use crate::game::game_modes::{
+    game_mode_is, GameModeRules, GameModes, MatchPhase, MatchRoster, MatchStatus, Participant, Team,
+};
+use crate::game::network::has_authority;
+use crate::game::player::PlayerBody;
+use crate::game::world::GameEntity;
+
+pub const CAPTURE_THE_FLAG: &str = "capture_the_flag";
+
+#[derive(Component, Reflect, Default, Debug)]
+#[reflect(Component)]
+/// Where the flag of a team stands, placed in Blender. The flag is spawned on top of it.
+/// A level needs one for each team to be played in this game mode, `World.glb` has none yet.
+pub struct FlagBase {
+    pub team: u8,
+}
+
+#[derive(Component, Debug)]
+pub struct Flag {
+    pub team: u8,
+    pub home: Vec3,
+    pub carrier: Option<Entity>,
+    /// Runs while the flag lies somewhere, it goes home when it runs out.
+    pub dropped: Option<Timer>,
+}
+
+impl Flag {
+    pub fn is_home(&self) -> bool {
+        self.carrier.is_none() && self.dropped.is_none()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// A flag as clients see it, replicated with the `MatchStatus`.
+pub struct FlagStatus {
+    pub team: u8,
+    pub position: Vec3,
+    pub carrier: Option<Participant>,
+    pub home: bool,
+}
+
+#[derive(Component, Debug)]
+/// Shows a `FlagStatus` on clients, which don't run the flags themselves.
+pub struct FlagView {
+    pub team: u8,
+}
+
+#[derive(Resource, Reflect, Debug)]
+#[reflect(Resource)]
+pub struct CaptureTheFlagSettings {
+    /// Distance between a player and a flag to take, return or capture it.
+    pub touch_radius: f32,
+    /// Seconds a dropped flag lies around before it goes home.
+    pub return_time: f32,
+    /// Height of a carried flag above the carrier's feet.
+    pub carry_height: f32,
+    /// Points of the player for a capture, the team gets one.
+    pub capture_points: i32,
+    pub return_points: i32,
+}
+
+impl Default for CaptureTheFlagSettings {
+    fn default() -> Self {
+        CaptureTheFlagSettings {
+            touch_radius: 1.2,
+            return_time: 20.0,
+            carry_height: 1.0,
+            capture_points: 5,
+            return_points: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlagEventKind {
+    Taken,
+    Dropped,
+    Returned,
+    Captured,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct FlagEvent {
+    /// Team of the flag.
+    pub team: u8,
+    /// Who did it, `None` if a dropped flag returned on its own or the carrier died.
+    pub participant: Option<Participant>,
+    pub kind: FlagEventKind,
+}
+
+/// Each team has a flag at its base. Taking the enemy flag to the own one, while that one is at
+/// home, scores a point for the team.
+pub struct CaptureTheFlagPlugin;
+
+impl Plugin for CaptureTheFlagPlugin {
+    fn build(&self, app: &mut App) {
+        GameModes::register(app, CAPTURE_THE_FLAG, GameModeRules {
+            name: "Capture the Flag".to_string(),
+            teams: 2,
+            score_limit: 3,
+            time_limit: 900.0,
+            rounds: 1,
+        });
+        app.register_type::<FlagBase>()
+            .register_type::<CaptureTheFlagSettings>()
+            .init_resource::<CaptureTheFlagSettings>()
+            .add_event::<FlagEvent>()
+            .add_systems(OnEnter(MatchPhase::InProgress), reset_flags
+                .run_if(game_mode_is(CAPTURE_THE_FLAG))
+                .run_if(has_authority))
+            .add_systems(Update, (
+                spawn_flags,
+                warn_without_flag_bases.run_if(in_state(MatchPhase::InProgress)),
+                touch_flags.after(spawn_flags).run_if(in_state(MatchPhase::InProgress)),
+                carry_flags.after(touch_flags),
+                return_dropped_flags,
+            ).run_if(game_mode_is(CAPTURE_THE_FLAG)).run_if(has_authority).run_if(in_state(AppState::Game)))
+            .add_systems(Update, (show_replicated_flags, replicate_flag_events)
+                .run_if(resource_changed::<MatchStatus>())
+                .run_if(not(has_authority))
+                .run_if(in_state(AppState::Game)));
+    }
+}
+
+// Once, otherwise the mode silently plays without flags.
+fn warn_without_flag_bases(bases: Query<(), With<FlagBase>>, mut warned: Local<bool>) {
+    if bases.is_empty() && !*warned {
+        warn!("the level has no `FlagBase`, there are no flags to capture");
+        *warned = true;
+    }
+}
+
+fn team_color(team: u8) -> Color {
+    match team {
+        1 => Color::rgb(0.8, 0.15, 0.1),
+        2 => Color::rgb(0.1, 0.3, 0.8),
+        _ => Color::WHITE,
+    }
+}
+
+pub fn spawn_flags(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    bases: Query<(&FlagBase, &GlobalTransform)>,
+    flags: Query<&Flag>,
+) {
+    for (base, transform) in bases.iter() {
+        if flags.iter().any(|flag| flag.team == base.team) {
+            continue;
+        }
+        let home = transform.translation();
+        commands.spawn((
+            Flag {
+                team: base.team,
+                home,
+                carrier: None,
+                dropped: None,
+            },
+            flag_bundle(base.team, home, &mut meshes, &mut materials),
+        ));
+    }
+}
+
+fn flag_bundle(
+    team: u8,
+    position: Vec3,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) -> (Name, GameEntity, PbrBundle) {
+    (
+        Name::new(format!("Flag {}", team)),
+        GameEntity,
+        PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::Box::new(0.1, 1.5, 0.1))),
+            material: materials.add(team_color(team).into()),
+            transform: Transform::from_translation(position),
+            ..default()
+        },
+    )
+}
+
+/// What the server sends in `MatchStatus::flags`, empty in the other game modes.
+pub fn flag_statuses(
+    flags: &Query<(&Flag, &Transform)>,
+    participants: &Query<&Participant>,
+) -> Vec<FlagStatus> {
+    let mut statuses: Vec<FlagStatus> = flags
+        .iter()
+        .map(|(flag, transform)| FlagStatus {
+            team: flag.team,
+            position: transform.translation,
+            carrier: flag.carrier.and_then(|carrier| participants.get(carrier).ok()).copied(),
+            home: flag.is_home(),
+        })
+        .collect();
+    statuses.sort_by_key(|status| status.team);
+    statuses
+}
+
+pub fn show_replicated_flags(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    status: Res<MatchStatus>,
+    mut views: Query<(Entity, &FlagView, &mut Transform)>,
+) {
+    for (entity, view, _) in views.iter() {
+        if !status.flags.iter().any(|flag| flag.team == view.team) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+    for flag in status.flags.iter() {
+        match views.iter_mut().find(|(_, view, _)| view.team == flag.team) {
+            Some((_, _, mut transform)) => transform.translation = flag.position,
+            None => {
+                commands.spawn((
+                    FlagView { team: flag.team },
+                    flag_bundle(flag.team, flag.position, &mut meshes, &mut materials),
+                ));
+            }
+        }
+    }
+}
+
+// Clients don't run the flags, they tell what happened from the changes of the replicated ones.
+pub fn replicate_flag_events(
+    status: Res<MatchStatus>,
+    mut last: Local<(MatchPhase, Vec<FlagStatus>)>,
+    mut flag_events: EventWriter<FlagEvent>,
+) {
+    // The flags go home at the start of a round, that's no capture.
+    if status.phase == MatchPhase::InProgress && last.0 == MatchPhase::InProgress {
+        for flag in status.flags.iter() {
+            let Some(previous) = last.1.iter().find(|previous| previous.team == flag.team) else {
+                continue;
+            };
+            if let Some(event) = flag_event_between(previous, flag) {
+                flag_events.send(event);
+            }
+        }
+    }
+    *last = (status.phase, status.flags.clone());
+}
+
+fn flag_event_between(previous: &FlagStatus, flag: &FlagStatus) -> Option<FlagEvent> {
+    let (participant, kind) = match (previous.carrier, flag.carrier) {
+        (None, Some(carrier)) => (Some(carrier), FlagEventKind::Taken),
+        (Some(carrier), None) if flag.home => (Some(carrier), FlagEventKind::Captured),
+        (Some(_), None) => (None, FlagEventKind::Dropped),
+        (None, None) if !previous.home && flag.home => (None, FlagEventKind::Returned),
+        _ => return None,
+    };
+    Some(FlagEvent {
+        team: flag.team,
+        participant,
+        kind,
+    })
+}
+
+pub fn reset_flags(mut flags: Query<(&mut Flag, &mut Transform)>) {
+    for (mut flag, mut transform) in flags.iter_mut() {
+        flag.carrier = None;
+        flag.dropped = None;
+        transform.translation = flag.home;
+    }
+}
+
+pub fn touch_flags(
+    settings: Res<CaptureTheFlagSettings>,
+    mut roster: ResMut<MatchRoster>,
+    players: Query<(Entity, &GlobalTransform, &Participant, &Team), With<PlayerBody>>,
+    mut flags: Query<(Entity, &mut Flag, &mut Transform)>,
+    mut flag_events: EventWriter<FlagEvent>,
+) {
+    for (player, player_transform, participant, team) in players.iter() {
+        if team.0 == 0 {
+            continue;
+        }
+        let position = player_transform.translation();
+        let carried: Vec<Entity> = flags
+            .iter()
+            .filter(|(_, flag, _)| flag.carrier == Some(player))
+            .map(|(entity, _, _)| entity)
+            .collect();
+
+        let mut at_own_flag = false;
+        for (_, mut flag, mut transform) in flags.iter_mut() {
+            if flag.carrier.is_some() || transform.translation.distance(position) > settings.touch_radius {
+                continue;
+            }
+
+            let kind = if flag.team != team.0 {
+                flag.carrier = Some(player);
+                flag.dropped = None;
+                FlagEventKind::Taken
+            } else if !flag.is_home() {
+                flag.dropped = None;
+                transform.translation = flag.home;
+                roster.add_score(*participant, settings.return_points);
+                FlagEventKind::Returned
+            } else {
+                at_own_flag = true;
+                continue;
+            };
+            flag_events.send(FlagEvent {
+                team: flag.team,
+                participant: Some(*participant),
+                kind,
+            });
+        }
+
+        // Only scores while the own flag is at home, so it has to be returned first.
+        if !at_own_flag {
+            continue;
+        }
+        for entity in carried {
+            let Ok((_, mut flag, mut transform)) = flags.get_mut(entity) else {
+                continue;
+            };
+            flag.carrier = None;
+            transform.translation = flag.home;
+            roster.add_score(*participant, settings.capture_points);
+            roster.add_team_score(team.0, 1);
+            flag_events.send(FlagEvent {
+                team: flag.team,
+                participant: Some(*participant),
+                kind: FlagEventKind::Captured,
+            });
+        }
+    }
+}
+
+pub fn carry_flags(
+    settings: Res<CaptureTheFlagSettings>,
+    carriers: Query<&GlobalTransform, With<PlayerBody>>,
+    mut flags: Query<(&mut Flag, &mut Transform)>,
+    mut flag_events: EventWriter<FlagEvent>,
+) {
+    for (mut flag, mut transform) in flags.iter_mut() {
+        let Some(carrier) = flag.carrier else {
+            continue;
+        };
+        match carriers.get(carrier) {
+            Ok(carrier_transform) => {
+                transform.translation = carrier_transform.translation() + Vec3::Y * settings.carry_height;
+            }
+            // The carrier died, the flag falls where it was last seen.
+            Err(_) => {
+                flag.carrier = None;
+                flag.dropped = Some(Timer::from_seconds(settings.return_time, TimerMode::Once));
+                transform.translation.y -= settings.carry_height;
+                flag_events.send(FlagEvent {
+                    team: flag.team,
+                    participant: None,
+                    kind: FlagEventKind::Dropped,
+                });
+            }
+        }
+    }
+}
+
+pub fn return_dropped_flags(
+    time: Res<Time>,
+    mut flags: Query<(&mut Flag, &mut Transform)>,
+    mut flag_events: EventWriter<FlagEvent>,
+) {
+    for (mut flag, mut transform) in flags.iter_mut() {
+        let Some(timer) = flag.dropped.as_mut() else {
+            continue;
+        };
+        if !timer.tick(time.delta()).finished() {
+            continue;
+        }
+        flag.dropped = None;
+        transform.translation = flag.home;
+        flag_events.send(FlagEvent {
+            team: flag.team,
+            participant: None,
+            kind: FlagEventKind::Returned,
+        });
+    }
+}
@@ -0,0 +1,39 @@
+use bevy::prelude::*;
+
+use crate::app::AppState;
+use crate::game::game_modes::{count_kills, game_mode_is, GameModeRules, GameModes, MatchPhase, MatchRoster};
+use crate::game::kills::PlayerKilled;
+use crate::game::network::has_authority;
+
+pub const DEATHMATCH: &str = "deathmatch";
+
+/// Everyone against everyone, every kill is a point and every suicide costs one.
+pub struct DeathmatchPlugin;
+
+impl Plugin for DeathmatchPlugin {
+    fn build(&self, app: &mut App) {
+        GameModes::register(app, DEATHMATCH, GameModeRules {
+            name: "Deathmatch".to_string(),
+            teams: 0,
+            score_limit: 20,
+            time_limit: 600.0,
+            rounds: 1,
+        });
+        app.add_systems(Update, score_deathmatch_kills
+            .after(count_kills)
+            .run_if(game_mode_is(DEATHMATCH))
+            .run_if(has_authority)
+            .run_if(in_state(MatchPhase::InProgress))
+            .run_if(in_state(AppState::Game)));
+    }
+}
+
+pub fn score_deathmatch_kills(mut roster: ResMut<MatchRoster>, mut killed: EventReader<PlayerKilled>) {
+    for event in killed.read() {
+        match event.killer {
+            Some(killer) if killer != event.victim => roster.add_score(killer, 1),
+            // killed themselves or by the level
+            _ => roster.add_score(event.victim, -1),
+        }
+    }
+}
@@ -0,0 +1,853 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::app::AppState;
+use crate::game::game_modes::capture_the_flag::{flag_statuses, CaptureTheFlagPlugin, Flag, FlagStatus};
+use crate::game::game_modes::deathmatch::DeathmatchPlugin;
+use crate::game::game_modes::team_deathmatch::TeamDeathmatchPlugin;
+use crate::game::enemies::EnemyKilled;
+use crate::game::health::Health;
+use crate::game::kills::{detect_kills, KillRecord, PlayerKilled, RecentKills};
+use crate::game::network::{has_authority, is_offline};
+use crate::game::player::PlayerBody;
+use crate::game::respawn::{choose_spawn, PlayerSpawn};
+
+pub mod capture_the_flag;
+pub mod deathmatch;
+pub mod team_deathmatch;
+
+#[derive(States, Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Debug, Hash, Default)]
+pub enum MatchPhase {
+    /// No game mode is played, e.g. in the survival mode or outside of the game.
+    #[default]
+    Inactive,
+    /// Waiting for enough players, who have to ready up.
+    Lobby,
+    /// Everyone can play, but nothing counts yet.
+    Warmup,
+    InProgress,
+    /// Shows the winner of the round before the next one starts.
+    RoundEnd,
+    /// Shows the winner of the match before going back to the lobby.
+    Intermission,
+}
+
+#[derive(Component, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Who a player body belongs to. Bodies are replaced on every respawn, the scores stay with this.
+pub enum Participant {
+    /// The player of a single player game.
+    Local,
+    /// A network client, by its id.
+    Client(u32),
+    /// A bot, by its slot.
+    Bot(u32),
+}
+
+#[derive(Component, Reflect, Debug, Default, Clone, Copy, PartialEq, Eq, Deref)]
+#[reflect(Component)]
+/// Team of a player body, `0` is no team. Matches the `team` of the `PlayerSpawn`s.
+pub struct Team(pub u8);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchWinner {
+    Team(u8),
+    Participant(Participant),
+}
+
+#[derive(Reflect, Debug, Clone)]
+/// What a game mode plugin registers in `GameModes`.
+pub struct GameModeRules {
+    /// Shown to the players, e.g. "Team Deathmatch".
+    pub name: String,
+    /// Number of teams, `0` for everyone against everyone.
+    pub teams: u8,
+    /// A round ends as soon as a team (or a player without teams) has this many points. No limit if zero.
+    pub score_limit: i32,
+    /// Seconds a round lasts at most. No limit if zero.
+    pub time_limit: f32,
+    /// Rounds of a match, the one who won the most rounds wins the match.
+    pub rounds: u32,
+}
+
+impl Default for GameModeRules {
+    fn default() -> Self {
+        GameModeRules {
+            name: String::new(),
+            teams: 0,
+            score_limit: 0,
+            time_limit: 0.0,
+            rounds: 1,
+        }
+    }
+}
+
+#[derive(Resource, Reflect, Debug, Default)]
+#[reflect(Resource)]
+/// All game modes by their id, filled by the game mode plugins.
+pub struct GameModes(pub HashMap<String, GameModeRules>);
+
+impl GameModes {
+    /// Called by the game mode plugins, `MatchPlugin` has to be added before.
+    pub fn register(app: &mut App, id: &str, rules: GameModeRules) {
+        app.world.resource_mut::<GameModes>().0.insert(id.to_string(), rules);
+    }
+}
+
+#[derive(Resource, Debug, Default, Clone, PartialEq, Eq)]
+/// Id of the game mode which is played, chosen with `--mode <id>`.
+/// Without one the game is the single player survival mode.
+pub struct ActiveGameMode(pub Option<String>);
+
+impl ActiveGameMode {
+    /// Removes `--mode <id>` from the arguments, so that the rest can be parsed by others.
+    pub fn take_from_args(args: &mut Vec<String>) -> Result<Self, String> {
+        let Some(index) = args.iter().position(|arg| arg == "--mode") else {
+            return Ok(ActiveGameMode(None));
+        };
+        args.remove(index);
+        if index >= args.len() {
+            return Err("--mode needs a game mode, e.g. `deathmatch`".to_string());
+        }
+        Ok(ActiveGameMode(Some(args.remove(index))))
+    }
+}
+
+/// Run condition for the systems of a game mode plugin.
+pub fn game_mode_is(id: &'static str) -> impl FnMut(Res<ActiveGameMode>) -> bool + Clone {
+    move |active: Res<ActiveGameMode>| active.0.as_deref() == Some(id)
+}
+
+#[derive(Resource, Reflect, Debug)]
+#[reflect(Resource)]
+/// Durations of the match phases, the same for all game modes.
+pub struct MatchSettings {
+    /// Wait in the lobby until every human player is ready. Otherwise the warmup starts right away.
+    pub require_ready: bool,
+    /// Players, including bots, needed to leave the lobby.
+    pub min_players: u32,
+    pub warmup: f32,
+    pub round_end: f32,
+    pub intermission: f32,
+}
+
+impl Default for MatchSettings {
+    fn default() -> Self {
+        MatchSettings {
+            require_ready: true,
+            min_players: 1,
+            warmup: 10.0,
+            round_end: 5.0,
+            intermission: 10.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ParticipantInfo {
+    pub name: String,
+    pub team: u8,
+    /// Bots are always ready.
+    pub ready: bool,
+    pub bot: bool,
+    /// Points for the game mode in the running round, e.g. kills minus suicides.
+    pub score: i32,
+    /// Points of the earlier rounds of the match.
+    pub earlier_score: i32,
+    pub kills: u32,
+    pub deaths: u32,
+    pub assists: u32,
+    /// Round trip time in milliseconds, only known of network clients.
+    pub ping: Option<u32>,
+}
+
+#[derive(Resource, Debug, Default)]
+/// Everyone taking part in the match and the scores. Only the side with the authority keeps it.
+pub struct MatchRoster {
+    pub participants: HashMap<Participant, ParticipantInfo>,
+    /// Scores of the teams `1..=teams`, starting with team `1`.
+    pub team_scores: Vec<i32>,
+}
+
+impl MatchRoster {
+    pub fn teams(&self) -> u8 {
+        self.team_scores.len() as u8
+    }
+
+    /// Adds the participant if it's new and returns its team.
+    /// `preferred` is used without teams or if it is a valid team, otherwise the smallest team is joined.
+    pub fn join(&mut self, participant: Participant, name: &str, preferred: u8) -> u8 {
+        if let Some(info) = self.participants.get(&participant) {
+            return info.team;
+        }
+
+        let teams = self.teams();
+        let team = if teams == 0 || (1..=teams).contains(&preferred) {
+            preferred
+        } else {
+            (1..=teams)
+                .min_by_key(|team| self.participants.values().filter(|info| info.team == *team).count())
+                .unwrap_or(0)
+        };
+        let bot = matches!(participant, Participant::Bot(_));
+        self.participants.insert(participant, ParticipantInfo {
+            name: name.to_string(),
+            team,
+            ready: bot,
+            bot,
+            ..default()
+        });
+        team
+    }
+
+    pub fn leave(&mut self, participant: Participant) {
+        self.participants.remove(&participant);
+    }
+
+    pub fn team(&self, participant: Participant) -> u8 {
+        self.participants.get(&participant).map_or(0, |info| info.team)
+    }
+
+    pub fn set_ready(&mut self, participant: Participant, ready: bool) {
+        if let Some(info) = self.participants.get_mut(&participant) {
+            info.ready = ready;
+        }
+    }
+
+    pub fn add_score(&mut self, participant: Participant, points: i32) {
+        if let Some(info) = self.participants.get_mut(&participant) {
+            info.score += points;
+        }
+    }
+
+    pub fn add_team_score(&mut self, team: u8, points: i32) {
+        if let Some(score) = self.team_scores.get_mut((team as usize).wrapping_sub(1)) {
+            *score += points;
+        }
+    }
+
+    /// The team with the most points, or the player without teams. `None` on a draw.
+    pub fn leader(&self) -> Option<MatchWinner> {
+        if self.teams() > 0 {
+            let best = *self.team_scores.iter().max()?;
+            let mut leaders = (1..=self.teams()).filter(|team| self.team_scores[*team as usize - 1] == best);
+            let leader = leaders.next()?;
+            return leaders.next().is_none().then_some(MatchWinner::Team(leader));
+        }
+
+        let best = self.participants.values().map(|info| info.score).max()?;
+        let mut leaders = self.participants.iter().filter(|(_, info)| info.score == best);
+        let (leader, _) = leaders.next()?;
+        leaders.next().is_none().then_some(MatchWinner::Participant(*leader))
+    }
+
+    /// The highest score, of a team or of a player without teams.
+    pub fn best_score(&self) -> i32 {
+        if self.teams() > 0 {
+            self.team_scores.iter().copied().max().unwrap_or(0)
+        } else {
+            self.participants.values().map(|info| info.score).max().unwrap_or(0)
+        }
+    }
+
+    fn reset_scores(&mut self) {
+        self.team_scores.iter_mut().for_each(|score| *score = 0);
+        for info in self.participants.values_mut() {
+            info.score = 0;
+            info.earlier_score = 0;
+            info.kills = 0;
+            info.deaths = 0;
+            info.assists = 0;
+        }
+    }
+
+    /// Round scores start at zero again, the score limit counts per round.
+    fn next_round(&mut self) {
+        self.team_scores.iter_mut().for_each(|score| *score = 0);
+        for info in self.participants.values_mut() {
+            info.earlier_score += info.score;
+            info.score = 0;
+        }
+    }
+
+    /// Everyone for the scoreboard, sorted by team and then by score.
+    pub fn scoreboard(&self) -> Vec<ScoreboardEntry> {
+        let mut entries: Vec<ScoreboardEntry> = self.participants
+            .iter()
+            .map(|(participant, info)| ScoreboardEntry {
+                participant: *participant,
+                name: info.name.clone(),
+                team: info.team,
+                score: info.earlier_score + info.score,
+                kills: info.kills,
+                deaths: info.deaths,
+                assists: info.assists,
+                ping: info.ping,
+                bot: info.bot,
+            })
+            .collect();
+        entries.sort_by(|a, b| {
+            a.team.cmp(&b.team)
+                .then(b.score.cmp(&a.score))
+                .then(b.kills.cmp(&a.kills))
+                .then(a.deaths.cmp(&b.deaths))
+                .then(a.name.cmp(&b.name))
+        });
+        entries
+    }
+}
+
+#[derive(Resource, Debug, Default)]
+/// The running match, only on the side with the authority.
+pub struct CurrentMatch {
+    pub rules: GameModeRules,
+    /// Starts at `1` with the first round.
+    pub round: u32,
+    /// Runs out at the end of the phase, `None` if the phase has no time limit.
+    pub timer: Option<Timer>,
+    pub round_winners: Vec<Option<MatchWinner>>,
+}
+
+impl CurrentMatch {
+    /// Whoever won the most rounds, `None` on a draw.
+    pub fn winner(&self) -> Option<MatchWinner> {
+        let wins = |winner: &MatchWinner| self.round_winners.iter().filter(|other| other.as_ref() == Some(winner)).count();
+        let best = self.round_winners.iter().flatten().map(wins).max()?;
+        let mut winners: Vec<MatchWinner> = Vec::new();
+        for winner in self.round_winners.iter().flatten() {
+            if wins(winner) == best && !winners.contains(winner) {
+                winners.push(*winner);
+            }
+        }
+        (winners.len() == 1).then(|| winners[0])
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// A line of the scoreboard.
+pub struct ScoreboardEntry {
+    pub participant: Participant,
+    pub name: String,
+    pub team: u8,
+    /// Points of the whole match.
+    pub score: i32,
+    pub kills: u32,
+    pub deaths: u32,
+    pub assists: u32,
+    /// Milliseconds, `None` offline and for bots.
+    pub ping: Option<u32>,
+    pub bot: bool,
+}
+
+/// Name of a participant as shown to the players, also if it isn't in the list (anymore).
+pub fn participant_name(participant: Participant, players: &[ScoreboardEntry]) -> String {
+    if let Some(entry) = players.iter().find(|entry| entry.participant == participant) {
+        return entry.name.clone();
+    }
+    match participant {
+        Participant::Local => "Player".to_string(),
+        Participant::Client(id) => format!("Client {}", id),
+        Participant::Bot(slot) => format!("Bot {}", slot + 1),
+    }
+}
+
+pub fn describe_winner(winner: Option<MatchWinner>, players: &[ScoreboardEntry]) -> String {
+    match winner {
+        Some(MatchWinner::Team(team)) => format!("Team {} wins", team),
+        Some(MatchWinner::Participant(Participant::Local)) => "You win".to_string(),
+        Some(MatchWinner::Participant(participant)) => format!("{} wins", participant_name(participant, players)),
+        None => "Draw".to_string(),
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+/// What players see of the match. Kept up to date by the side with the authority and replicated to the clients.
+pub struct MatchStatus {
+    /// Name of the game mode, empty without one.
+    pub mode: String,
+    pub phase: MatchPhase,
+    pub round: u32,
+    pub rounds: u32,
+    /// Seconds until the phase ends.
+    pub remaining: Option<f32>,
+    pub score_limit: i32,
+    /// Scores of the teams `1..=teams`.
+    pub team_scores: Vec<i32>,
+    /// Winner of the last round, or of the match during the intermission.
+    pub winner: Option<MatchWinner>,
+    /// Also filled without a game mode, e.g. for the scoreboard of the survival mode.
+    pub players: Vec<ScoreboardEntry>,
+    /// The last kills, oldest first.
+    pub kills: Vec<KillRecord>,
+    /// Flags of capture the flag, clients show them where the server has them.
+    pub flags: Vec<FlagStatus>,
+}
+
+#[derive(Resource, Debug, Clone, Default)]
+/// How the last game went, for the results screen in `AppState::GameOver`.
+pub struct MatchResults {
+    /// Name of the game mode, empty in the survival mode.
+    pub mode: String,
+    /// Set if the match was played to the end, otherwise the game was lost or left.
+    pub finished: bool,
+    pub winner: Option<MatchWinner>,
+    pub team_scores: Vec<i32>,
+    pub players: Vec<ScoreboardEntry>,
+}
+
+#[derive(Resource, Debug, Default)]
+/// Toggled with `Enter` in the lobby. Offline it goes straight into the roster, clients send it to the server.
+pub struct LocalReady(pub bool);
+
+#[derive(Event, Debug, Clone, Copy)]
+/// Ends the running round. Sent on the time and score limit, game modes can send it for their own win conditions.
+pub struct EndRound {
+    pub winner: Option<MatchWinner>,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct MatchEnded {
+    pub winner: Option<MatchWinner>,
+}
+
+/// Lobby, warmup, rounds and intermission of the game modes. The game modes add their scoring
+/// on top, usually only while the match is `MatchPhase::InProgress`.
+pub struct MatchPlugin;
+
+impl Plugin for MatchPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<MatchPhase>()
+            .register_type::<Team>()
+            .register_type::<GameModes>()
+            .register_type::<MatchSettings>()
+            .init_resource::<GameModes>()
+            .init_resource::<ActiveGameMode>()
+            .init_resource::<MatchSettings>()
+            .init_resource::<MatchRoster>()
+            .init_resource::<CurrentMatch>()
+            .init_resource::<MatchStatus>()
+            .init_resource::<LocalReady>()
+            .init_resource::<MatchResults>()
+            .add_event::<EndRound>()
+            .add_event::<MatchEnded>()
+            .add_systems(OnEnter(AppState::Game), (reset_results, start_match.run_if(has_authority)))
+            .add_systems(OnExit(AppState::Game), (record_results.before(stop_match), stop_match))
+            .add_systems(OnEnter(MatchPhase::Warmup), start_warmup.run_if(has_authority))
+            .add_systems(OnEnter(MatchPhase::InProgress), (start_round, reset_players).run_if(has_authority))
+            .add_systems(OnEnter(MatchPhase::RoundEnd), start_round_end.run_if(has_authority))
+            .add_systems(OnEnter(MatchPhase::Intermission), start_intermission.run_if(has_authority))
+            .add_systems(Update, (
+                count_kills.after(detect_kills),
+                toggle_ready.run_if(in_state(MatchPhase::Lobby)),
+                apply_local_ready.run_if(is_offline).run_if(resource_changed::<LocalReady>()),
+                run_lobby.after(apply_local_ready).run_if(in_state(MatchPhase::Lobby)),
+                run_match_clock,
+                check_score_limit.run_if(in_state(MatchPhase::InProgress)),
+                end_rounds
+                    .after(run_match_clock)
+                    .after(check_score_limit)
+                    .run_if(in_state(MatchPhase::InProgress)),
+                update_match_status.after(end_rounds).after(count_kills),
+                record_winner,
+                finish_offline_match.after(record_winner).run_if(is_offline),
+            ).run_if(has_authority).run_if(in_state(AppState::Game)))
+            .add_systems(Update, (
+                toggle_ready.run_if(in_state(MatchPhase::Lobby)),
+                follow_match_status,
+            ).run_if(not(has_authority)).run_if(in_state(AppState::Game)))
+            .add_plugins((DeathmatchPlugin, TeamDeathmatchPlugin, CaptureTheFlagPlugin));
+    }
+}
+
+pub fn start_match(
+    active: Res<ActiveGameMode>,
+    modes: Res<GameModes>,
+    mut current: ResMut<CurrentMatch>,
+    mut roster: ResMut<MatchRoster>,
+    mut next_phase: ResMut<NextState<MatchPhase>>,
+) {
+    let Some(id) = active.0.as_deref() else {
+        return;
+    };
+    let Some(rules) = modes.0.get(id) else {
+        let mut known: Vec<&str> = modes.0.keys().map(String::as_str).collect();
+        known.sort();
+        error!("unknown game mode `{}`, known are: {}", id, known.join(", "));
+        return;
+    };
+
+    info!("starting a {} match", rules.name);
+    *current = CurrentMatch {
+        rules: rules.clone(),
+        ..default()
+    };
+    *roster = MatchRoster {
+        team_scores: vec![0; rules.teams as usize],
+        ..default()
+    };
+    next_phase.set(MatchPhase::Lobby);
+}
+
+pub fn stop_match(
+    mut current: ResMut<CurrentMatch>,
+    mut roster: ResMut<MatchRoster>,
+    mut status: ResMut<MatchStatus>,
+    mut local_ready: ResMut<LocalReady>,
+    mut next_phase: ResMut<NextState<MatchPhase>>,
+) {
+    *current = CurrentMatch::default();
+    *roster = MatchRoster::default();
+    *status = MatchStatus::default();
+    local_ready.0 = false;
+    next_phase.set(MatchPhase::Inactive);
+}
+
+pub fn reset_results(mut results: ResMut<MatchResults>) {
+    *results = MatchResults::default();
+}
+
+pub fn record_winner(mut match_ended: EventReader<MatchEnded>, mut results: ResMut<MatchResults>) {
+    for event in match_ended.read() {
+        results.finished = true;
+        results.winner = event.winner;
+    }
+}
+
+// also on clients, where the status comes from the server
+pub fn record_results(status: Res<MatchStatus>, mut results: ResMut<MatchResults>) {
+    results.mode = status.mode.clone();
+    results.team_scores = status.team_scores.clone();
+    results.players = status.players.clone();
+}
+
+/// Single player matches end with the results screen. Servers go back to the lobby for the next one,
+/// their clients see the results on the scoreboard during the intermission.
+pub fn finish_offline_match(
+    mut match_ended: EventReader<MatchEnded>,
+    mut app_state_next_state: ResMut<NextState<AppState>>,
+) {
+    if match_ended.read().count() > 0 {
+        app_state_next_state.set(AppState::GameOver);
+    }
+}
+
+pub fn start_warmup(
+    settings: Res<MatchSettings>,
+    mut current: ResMut<CurrentMatch>,
+    mut roster: ResMut<MatchRoster>,
+    mut local_ready: ResMut<LocalReady>,
+) {
+    current.round = 0;
+    current.round_winners.clear();
+    current.timer = Some(Timer::from_seconds(settings.warmup.max(0.0), TimerMode::Once));
+    roster.reset_scores();
+    // everyone has to ready up again after the match
+    local_ready.0 = false;
+    for info in roster.participants.values_mut() {
+        info.ready = info.bot;
+    }
+}
+
+pub fn start_round(mut current: ResMut<CurrentMatch>, mut roster: ResMut<MatchRoster>) {
+    current.round += 1;
+    let time_limit = current.rules.time_limit;
+    current.timer = (time_limit > 0.0).then(|| Timer::from_seconds(time_limit, TimerMode::Once));
+    if current.round == 1 {
+        // kills of the warmup don't count
+        roster.reset_scores();
+    } else {
+        roster.next_round();
+    }
+}
+
+pub fn start_round_end(settings: Res<MatchSettings>, mut current: ResMut<CurrentMatch>) {
+    current.timer = Some(Timer::from_seconds(settings.round_end.max(0.0), TimerMode::Once));
+}
+
+pub fn start_intermission(settings: Res<MatchSettings>, mut current: ResMut<CurrentMatch>) {
+    current.timer = Some(Timer::from_seconds(settings.intermission.max(0.0), TimerMode::Once));
+}
+
+/// Every round starts with everyone healed at the spawns of their team.
+pub fn reset_players(
+    spawns: Query<(&PlayerSpawn, &GlobalTransform)>,
+    mut players: Query<(&mut Transform, &mut Velocity, &mut Health, Option<&Team>), With<PlayerBody>>,
+) {
+    // Everyone moves at once, so the spawns already given out count as occupied.
+    let mut occupied = Vec::new();
+    for (mut transform, mut velocity, mut health, team) in players.iter_mut() {
+        if let Some(spawn) = choose_spawn(team.map_or(0, |team| team.0), &spawns, &occupied) {
+            occupied.push(spawn.translation);
+            *transform = spawn;
+        }
+        *velocity = Velocity::zero();
+        health.current = health.max;
+    }
+}
+
+// Only kills of the running round count, the warmup is for practice. Without a game mode everything counts.
+pub fn count_kills(
+    phase: Res<State<MatchPhase>>,
+    mut roster: ResMut<MatchRoster>,
+    mut killed: EventReader<PlayerKilled>,
+    mut enemies_killed: EventReader<EnemyKilled>,
+    participants: Query<&Participant>,
+) {
+    if !matches!(phase.get(), MatchPhase::InProgress | MatchPhase::Inactive) {
+        killed.clear();
+        enemies_killed.clear();
+        return;
+    }
+
+    for event in killed.read() {
+        if let Some(info) = roster.participants.get_mut(&event.victim) {
+            info.deaths += 1;
+        }
+        if let Some(info) = event.killer.filter(|killer| *killer != event.victim).and_then(|killer| roster.participants.get_mut(&killer)) {
+            info.kills += 1;
+        }
+        for assist in event.assists.iter() {
+            if let Some(info) = roster.participants.get_mut(assist) {
+                info.assists += 1;
+            }
+        }
+    }
+    for event in enemies_killed.read() {
+        let killer = event.killer.and_then(|killer| participants.get(killer).ok());
+        if let Some(info) = killer.and_then(|killer| roster.participants.get_mut(killer)) {
+            info.kills += 1;
+        }
+    }
+}
+
+pub fn toggle_ready(keyboard: Res<Input<KeyCode>>, mut local_ready: ResMut<LocalReady>) {
+    if keyboard.just_pressed(KeyCode::Return) {
+        local_ready.0 = !local_ready.0;
+    }
+}
+
+pub fn apply_local_ready(local_ready: Res<LocalReady>, mut roster: ResMut<MatchRoster>) {
+    roster.set_ready(Participant::Local, local_ready.0);
+}
+
+pub fn run_lobby(
+    settings: Res<MatchSettings>,
+    roster: Res<MatchRoster>,
+    mut next_phase: ResMut<NextState<MatchPhase>>,
+) {
+    let enough_players = roster.participants.len() >= settings.min_players.max(1) as usize;
+    let everyone_ready = !settings.require_ready || roster.participants.values().all(|info| info.ready);
+    if enough_players && everyone_ready {
+        next_phase.set(MatchPhase::Warmup);
+    }
+}
+
+pub fn run_match_clock(
+    time: Res<Time>,
+    phase: Res<State<MatchPhase>>,
+    roster: Res<MatchRoster>,
+    mut current: ResMut<CurrentMatch>,
+    mut next_phase: ResMut<NextState<MatchPhase>>,
+    mut end_round: EventWriter<EndRound>,
+    mut match_ended: EventWriter<MatchEnded>,
+) {
+    let Some(timer) = current.timer.as_mut() else {
+        return;
+    };
+    if !timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    match phase.get() {
+        MatchPhase::Warmup => next_phase.set(MatchPhase::InProgress),
+        MatchPhase::InProgress => end_round.send(EndRound { winner: roster.leader() }),
+        MatchPhase::RoundEnd if current.round < current.rules.rounds => next_phase.set(MatchPhase::InProgress),
+        MatchPhase::RoundEnd => {
+            let winner = current.winner();
+            info!("the match ended, winner: {:?}", winner);
+            match_ended.send(MatchEnded { winner });
+            next_phase.set(MatchPhase::Intermission);
+        }
+        MatchPhase::Intermission => next_phase.set(MatchPhase::Lobby),
+        MatchPhase::Inactive | MatchPhase::Lobby => {}
+    }
+}
+
+pub fn check_score_limit(current: Res<CurrentMatch>, roster: Res<MatchRoster>, mut end_round: EventWriter<EndRound>) {
+    let limit = current.rules.score_limit;
+    if limit > 0 && roster.best_score() >= limit {
+        end_round.send(EndRound { winner: roster.leader() });
+    }
+}
+
+pub fn end_rounds(
+    mut current: ResMut<CurrentMatch>,
+    mut end_round: EventReader<EndRound>,
+    mut next_phase: ResMut<NextState<MatchPhase>>,
+) {
+    // e.g. the time ran out while the score limit was reached, the first one wins
+    let Some(event) = end_round.read().next().copied() else {
+        return;
+    };
+    end_round.clear();
+
+    info!("round {} ended, winner: {:?}", current.round, event.winner);
+    current.round_winners.push(event.winner);
+    next_phase.set(MatchPhase::RoundEnd);
+}
+
+pub fn update_match_status(
+    phase: Res<State<MatchPhase>>,
+    current: Res<CurrentMatch>,
+    roster: Res<MatchRoster>,
+    recent_kills: Res<RecentKills>,
+    flags: Query<(&Flag, &Transform)>,
+    participants: Query<&Participant>,
+    mut status: ResMut<MatchStatus>,
+) {
+    let winner = match phase.get() {
+        MatchPhase::RoundEnd => current.round_winners.last().copied().flatten(),
+        MatchPhase::Intermission => current.winner(),
+        _ => None,
+    };
+    let updated = MatchStatus {
+        mode: current.rules.name.clone(),
+        phase: *phase.get(),
+        round: current.round,
+        rounds: current.rules.rounds,
+        remaining: current.timer.as_ref().map(Timer::remaining_secs),
+        score_limit: current.rules.score_limit,
+        team_scores: roster.team_scores.clone(),
+        winner,
+        players: roster.scoreboard(),
+        kills: recent_kills.records.iter().cloned().collect(),
+        flags: flag_statuses(&flags, &participants),
+    };
+    status.set_if_neq(updated);
+}
+
+/// Clients only get the status from the server, the phase follows it.
+pub fn follow_match_status(
+    status: Res<MatchStatus>,
+    phase: Res<State<MatchPhase>>,
+    mut next_phase: ResMut<NextState<MatchPhase>>,
+) {
+    if status.phase != *phase.get() {
+        next_phase.set(status.phase);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roster(teams: u8) -> MatchRoster {
+        MatchRoster {
+            team_scores: vec![0; teams as usize],
+            ..default()
+        }
+    }
+
+    fn round_winners(winners: &[Option<MatchWinner>]) -> CurrentMatch {
+        CurrentMatch {
+            round_winners: winners.to_vec(),
+            ..default()
+        }
+    }
+
+    #[test]
+    fn join_fills_the_smallest_team() {
+        let mut roster = roster(2);
+        assert_eq!(roster.join(Participant::Local, "Player", 0), 1);
+        assert_eq!(roster.join(Participant::Bot(0), "Bot 1", 0), 2);
+        assert_eq!(roster.join(Participant::Bot(1), "Bot 2", 3), 1);
+        // a valid team is kept, also if it gets bigger
+        assert_eq!(roster.join(Participant::Client(1), "Client 1", 1), 1);
+        // joining again keeps the team
+        assert_eq!(roster.join(Participant::Bot(0), "Bot 1", 1), 2);
+
+        assert_eq!(roster.participants.len(), 4);
+        assert!(roster.participants[&Participant::Bot(1)].ready);
+        assert!(!roster.participants[&Participant::Local].ready);
+    }
+
+    #[test]
+    fn join_without_teams_keeps_the_preferred_team() {
+        let mut roster = roster(0);
+        assert_eq!(roster.join(Participant::Local, "Player", 0), 0);
+        assert_eq!(roster.join(Participant::Bot(0), "Bot 1", 2), 2);
+    }
+
+    #[test]
+    fn leader_is_none_on_a_draw() {
+        let mut roster = roster(0);
+        assert_eq!(roster.leader(), None);
+        roster.join(Participant::Local, "Player", 0);
+        roster.join(Participant::Bot(0), "Bot 1", 0);
+        roster.add_score(Participant::Local, 2);
+        roster.add_score(Participant::Bot(0), 2);
+        assert_eq!(roster.leader(), None);
+        roster.add_score(Participant::Bot(0), 1);
+        assert_eq!(roster.leader(), Some(MatchWinner::Participant(Participant::Bot(0))));
+        assert_eq!(roster.best_score(), 3);
+    }
+
+    #[test]
+    fn leader_of_teams_ignores_the_players() {
+        let mut roster = roster(2);
+        roster.join(Participant::Local, "Player", 1);
+        roster.add_score(Participant::Local, 5);
+        assert_eq!(roster.leader(), None);
+        roster.add_team_score(2, 1);
+        // team 0 and unknown teams are ignored
+        roster.add_team_score(0, 3);
+        roster.add_team_score(3, 3);
+        assert_eq!(roster.leader(), Some(MatchWinner::Team(2)));
+        assert_eq!(roster.best_score(), 1);
+    }
+
+    #[test]
+    fn scoreboard_is_sorted_and_sums_the_rounds() {
+        let mut roster = roster(2);
+        roster.join(Participant::Local, "Player", 1);
+        roster.join(Participant::Bot(0), "Bot 1", 2);
+        roster.join(Participant::Bot(1), "Bot 2", 1);
+        roster.add_score(Participant::Local, 1);
+        roster.add_score(Participant::Bot(1), 3);
+        roster.next_round();
+        roster.add_score(Participant::Local, 1);
+
+        // the score limit counts per round, the scoreboard the whole match
+        assert_eq!(roster.best_score(), 0);
+        assert_eq!(roster.participants[&Participant::Local].score, 1);
+        let entries: Vec<(Participant, u8, i32)> = roster
+            .scoreboard()
+            .iter()
+            .map(|entry| (entry.participant, entry.team, entry.score))
+            .collect();
+        assert_eq!(entries, vec![
+            (Participant::Bot(1), 1, 3),
+            (Participant::Local, 1, 2),
+            (Participant::Bot(0), 2, 0),
+        ]);
+    }
+
+    #[test]
+    fn winner_won_the_most_rounds() {
+        let red = Some(MatchWinner::Team(1));
+        let blue = Some(MatchWinner::Team(2));
+        assert_eq!(round_winners(&[]).winner(), None);
+        assert_eq!(round_winners(&[red, blue, red]).winner(), red);
+        assert_eq!(round_winners(&[None, blue, None]).winner(), blue);
+    }
+
+    #[test]
+    fn winner_is_none_on_a_draw() {
+        let red = Some(MatchWinner::Team(1));
+        let blue = Some(MatchWinner::Team(2));
+        assert_eq!(round_winners(&[None, None]).winner(), None);
+        assert_eq!(round_winners(&[red, blue]).winner(), None);
+        assert_eq!(round_winners(&[red, None, blue]).winner(), None);
+    }
+}
@@ -0,0 +1,45 @@
+use bevy::prelude::*;
+
+use crate::app::AppState;
+use crate::game::game_modes::{count_kills, game_mode_is, GameModeRules, GameModes, MatchPhase, MatchRoster};
+use crate::game::kills::PlayerKilled;
+use crate::game::network::has_authority;
+
+pub const TEAM_DEATHMATCH: &str = "team_deathmatch";
+
+/// Two teams, every kill of an opponent is a point for the team. Killing a teammate costs one.
+pub struct TeamDeathmatchPlugin;
+
+impl Plugin for TeamDeathmatchPlugin {
+    fn build(&self, app: &mut App) {
+        GameModes::register(app, TEAM_DEATHMATCH, GameModeRules {
+            name: "Team Deathmatch".to_string(),
+            teams: 2,
+            score_limit: 50,
+            time_limit: 600.0,
+            rounds: 1,
+        });
+        app.add_systems(Update, score_team_kills
+            .after(count_kills)
+            .run_if(game_mode_is(TEAM_DEATHMATCH))
+            .run_if(has_authority)
+            .run_if(in_state(MatchPhase::InProgress))
+            .run_if(in_state(AppState::Game)));
+    }
+}
+
+pub fn score_team_kills(mut roster: ResMut<MatchRoster>, mut killed: EventReader<PlayerKilled>) {
+    for event in killed.read() {
+        let victim_team = roster.team(event.victim);
+        let Some(killer) = event.killer.filter(|killer| *killer != event.victim) else {
+            // suicides only cost the player, the team is not punished for them
+            roster.add_score(event.victim, -1);
+            continue;
+        };
+
+        let killer_team = roster.team(killer);
+        let points = if killer_team == victim_team { -1 } else { 1 };
+        roster.add_score(killer, points);
+        roster.add_team_score(killer_team, points);
+    }
+}
@@ -0,0 +1,237 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::app::AppState;
+use crate::game::movement_events::PlayerFallDamage;
+use crate::game::movement_volumes::Oxygen;
+use crate::game::network::has_authority;
+use crate::game::player::PlayerBody;
+use crate::game::respawn::{handle_player_death, Dead, PlayerDied};
+
+#[derive(Reflect, Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DamageKind {
+    #[default]
+    Generic,
+    Bullet,
+    Projectile,
+    Explosion,
+    Fall,
+    Drowning,
+    Fire,
+}
+
+impl DamageKind {
+    /// Armor only protects against damage from the outside.
+    pub fn bypasses_armor(&self) -> bool {
+        matches!(self, DamageKind::Fall | DamageKind::Drowning)
+    }
+}
+
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Health::new(100.0)
+    }
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Health { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0.0
+    }
+}
+
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+pub struct Armor {
+    pub current: f32,
+    pub max: f32,
+    /// Part of the damage taken by the armor instead of the health.
+    pub absorption: f32,
+}
+
+impl Default for Armor {
+    fn default() -> Self {
+        Armor {
+            current: 0.0,
+            max: 100.0,
+            absorption: 0.66,
+        }
+    }
+}
+
+#[derive(Component, Reflect, Default, Debug, Deref, DerefMut)]
+#[reflect(Component)]
+/// Damage multipliers per damage kind, e.g. `0.5` halves all damage of that kind.
+pub struct Resistances(pub HashMap<DamageKind, f32>);
+
+#[derive(Component, Reflect, Default, Debug)]
+#[reflect(Component)]
+/// Damages everything with `Health` inside of it. Add it to a `*_sensor` object in Blender.
+pub struct DamageZone {
+    pub damage_per_second: f32,
+    pub kind: DamageKind,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct DamageEvent {
+    pub target: Entity,
+    pub source: Option<Entity>,
+    pub amount: f32,
+    pub kind: DamageKind,
+    pub hit_point: Option<Vec3>,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct Died {
+    pub entity: Entity,
+    pub killer: Option<Entity>,
+    pub kind: DamageKind,
+}
+
+/// Hitboxes are often children of the entity which has the health.
+pub fn find_health_owner(
+    entity: Entity,
+    health_query: &Query<(), With<Health>>,
+    parents: &Query<&Parent>,
+) -> Option<Entity> {
+    std::iter::once(entity)
+        .chain(parents.iter_ancestors(entity))
+        .find(|entity| health_query.contains(*entity))
+}
+
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Health>()
+            .register_type::<Armor>()
+            .register_type::<Resistances>()
+            .register_type::<DamageZone>()
+            .add_event::<DamageEvent>()
+            .add_event::<Died>()
+            // Clients get health from the server.
+            .add_systems(Update, (
+                (damage_zones, fall_damage, drowning_damage).before(apply_damage),
+                apply_damage,
+                kill_players.after(apply_damage).before(handle_player_death),
+            ).run_if(has_authority).run_if(in_state(AppState::Game)));
+    }
+}
+
+pub fn apply_damage(
+    mut damage_events: EventReader<DamageEvent>,
+    mut targets: Query<(&mut Health, Option<&mut Armor>, Option<&Resistances>)>,
+    mut died: EventWriter<Died>,
+) {
+    for event in damage_events.read() {
+        let Ok((mut health, armor, resistances)) = targets.get_mut(event.target) else {
+            continue;
+        };
+        if health.is_dead() {
+            continue;
+        }
+
+        let mut amount = event.amount;
+        if let Some(factor) = resistances.and_then(|resistances| resistances.get(&event.kind)) {
+            amount *= factor;
+        }
+
+        if let Some(mut armor) = armor.filter(|_| !event.kind.bypasses_armor()) {
+            let absorbed = (amount * armor.absorption).min(armor.current);
+            armor.current -= absorbed;
+            amount -= absorbed;
+        }
+
+        health.current = (health.current - amount).max(0.0);
+        if health.is_dead() {
+            died.send(Died {
+                entity: event.target,
+                killer: event.source,
+                kind: event.kind,
+            });
+        }
+    }
+}
+
+pub fn damage_zones(
+    time: Res<Time>,
+    rapier_context: Res<RapierContext>,
+    zones: Query<(Entity, &DamageZone)>,
+    targets: Query<Entity, With<Health>>,
+    mut damage: EventWriter<DamageEvent>,
+) {
+    for (zone_entity, zone) in zones.iter() {
+        for target in targets.iter() {
+            if rapier_context.intersection_pair(zone_entity, target) != Some(true) {
+                continue;
+            }
+            damage.send(DamageEvent {
+                target,
+                source: Some(zone_entity),
+                amount: zone.damage_per_second * time.delta_seconds(),
+                kind: zone.kind,
+                hit_point: None,
+            });
+        }
+    }
+}
+
+pub fn fall_damage(mut fall_damage: EventReader<PlayerFallDamage>, mut damage: EventWriter<DamageEvent>) {
+    for event in fall_damage.read() {
+        damage.send(DamageEvent {
+            target: event.entity,
+            source: None,
+            amount: event.amount,
+            kind: DamageKind::Fall,
+            hit_point: None,
+        });
+    }
+}
+
+pub fn drowning_damage(
+    time: Res<Time>,
+    player_query: Query<(Entity, &Oxygen)>,
+    mut damage: EventWriter<DamageEvent>,
+) {
+    for (entity, oxygen) in player_query.iter() {
+        if oxygen.is_depleted() {
+            damage.send(DamageEvent {
+                target: entity,
+                source: None,
+                amount: oxygen.drowning_damage * time.delta_seconds(),
+                kind: DamageKind::Drowning,
+                hit_point: None,
+            });
+        }
+    }
+}
+
+// a dead player goes through the respawn flow, which ends in `AppState::GameOver` without lives
+pub fn kill_players(
+    mut commands: Commands,
+    mut died: EventReader<Died>,
+    player_query: Query<(), (With<PlayerBody>, Without<Dead>)>,
+    mut player_died: EventWriter<PlayerDied>,
+) {
+    let mut killed = Vec::new();
+    for event in died.read() {
+        // e.g. damage and falling out of the world in the same frame, only the first death counts
+        if player_query.contains(event.entity) && !killed.contains(&event.entity) {
+            commands.entity(event.entity).insert(Dead);
+            killed.push(event.entity);
+            player_died.send(PlayerDied { entity: event.entity });
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use bevy::prelude::*;
+
+use crate::game::game_modes::{participant_name, MatchStatus, ScoreboardEntry};
+use crate::game::health::DamageKind;
+use crate::game::hud::notifications::NotificationLog;
+use crate::game::hud::styles::HudTheme;
+use crate::game::kills::PlayerKilled;
+
+#[derive(Component, Debug)]
+/// The last kills at the top right, the entries fade out like notifications.
+pub struct KillFeed;
+
+pub fn describe_kill(kill: &PlayerKilled, players: &[ScoreboardEntry]) -> String {
+    let victim = participant_name(kill.victim, players);
+    let Some(killer) = kill.killer.filter(|killer| *killer != kill.victim) else {
+        let how = match kill.kind {
+            None => "fell out of the world",
+            Some(DamageKind::Fall) => "fell to their death",
+            Some(DamageKind::Drowning) => "drowned",
+            Some(DamageKind::Fire) => "burned",
+            Some(DamageKind::Explosion) => "blew themselves up",
+            Some(DamageKind::Generic | DamageKind::Bullet | DamageKind::Projectile) => "died",
+        };
+        return format!("{} {}", victim, how);
+    };
+
+    let killers: Vec<String> = std::iter::once(killer)
+        .chain(kill.assists.iter().copied())
+        .map(|participant| participant_name(participant, players))
+        .collect();
+    let how = match kill.kind {
+        Some(DamageKind::Bullet) => "shot",
+        Some(DamageKind::Explosion) => "blew up",
+        Some(DamageKind::Fire) => "burned",
+        _ => "killed",
+    };
+    format!("{} {} {}", killers.join(" + "), how, victim)
+}
+
+pub fn show_kills(
+    theme: Res<HudTheme>,
+    status: Res<MatchStatus>,
+    mut killed: EventReader<PlayerKilled>,
+    mut log: ResMut<NotificationLog<KillFeed>>,
+) {
+    for kill in killed.read() {
+        log.push(describe_kill(kill, &status.players), theme.kill_feed_duration, theme.max_kill_feed);
+    }
+}
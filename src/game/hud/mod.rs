@@ -0,0 +1,264 @@
+use bevy::prelude::*;
+
+use crate::app::{AppState, Headless};
+use crate::game::aim::{update_aim, AimState};
+use crate::game::hud::kill_feed::{show_kills, KillFeed};
+use crate::game::hud::notifications::{
+    clear_notification_log, expire_notification_log, fade_notifications, notify_controls, notify_flags,
+    notify_match_phases, notify_pickups, notify_waves, show_notifications, update_notification_area, HudNotification,
+    NotificationArea, NotificationLog,
+};
+use crate::game::hud::prompts::{prompt_ready_up, prompt_reload, update_prompt_area, ContextPrompts, PromptArea};
+use crate::game::hud::scoreboard::{show_scoreboard, update_scoreboard, Scoreboard, ScoreboardRows};
+use crate::game::hud::styles::{
+    corner_style, get_hud_text_style, HudTheme, CROSSHAIR_STYLE, HUD_STYLE, KILL_FEED_STYLE, NOTIFICATION_AREA_STYLE,
+    PANEL_STYLE, PROMPT_AREA_STYLE, SCOREBOARD_AREA_STYLE, SCOREBOARD_STYLE,
+};
+use crate::game::hud::widgets::{
+    build_widget, read_bound_values, update_bars, update_counters, HudAnchor, HudBinding, HudWidgets,
+};
+use crate::game::player::LocalPlayer;
+use crate::main_menu::elements::new_text_label;
+
+pub mod kill_feed;
+pub mod notifications;
+pub mod prompts;
+pub mod scoreboard;
+pub mod styles;
+pub mod widgets;
+
+#[derive(Component, Debug)]
+pub struct Hud;
+
+#[derive(Component, Debug)]
+pub struct Crosshair;
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        // Game code shows prompts and notifications on the dedicated server too, they just go nowhere.
+        app.register_type::<HudTheme>()
+            .register_type::<HudWidgets>()
+            .register_type::<HudBinding>()
+            .init_resource::<HudTheme>()
+            .init_resource::<HudWidgets>()
+            .init_resource::<ContextPrompts>()
+            .init_resource::<NotificationLog<NotificationArea>>()
+            .init_resource::<NotificationLog<KillFeed>>()
+            .add_event::<HudNotification>();
+
+        if app.world.contains_resource::<Headless>() {
+            return;
+        }
+        app.add_systems(OnEnter(AppState::Game), (spawn_hud, notify_controls))
+            .add_systems(OnExit(AppState::Game), (
+                despawn_hud,
+                clear_notification_log::<NotificationArea>,
+                clear_notification_log::<KillFeed>,
+            ))
+            .add_systems(Update, (
+                rebuild_hud.run_if(resource_changed::<HudTheme>().or_else(resource_changed::<HudWidgets>())),
+                read_bound_values.after(rebuild_hud),
+                (update_bars, update_counters).after(read_bound_values),
+                show_crosshair.after(update_aim),
+                (notify_pickups, notify_waves, notify_match_phases, notify_flags).before(show_notifications),
+                (show_notifications, show_kills).before(expire_notification_log::<NotificationArea>),
+                (expire_notification_log::<NotificationArea>, expire_notification_log::<KillFeed>)
+                    .after(show_notifications)
+                    .after(show_kills),
+                update_notification_area::<NotificationArea>
+                    .after(expire_notification_log::<NotificationArea>)
+                    .after(rebuild_hud),
+                update_notification_area::<KillFeed>.after(expire_notification_log::<KillFeed>).after(rebuild_hud),
+                fade_notifications
+                    .after(update_notification_area::<NotificationArea>)
+                    .after(update_notification_area::<KillFeed>),
+                show_scoreboard.after(rebuild_hud),
+                update_scoreboard.after(rebuild_hud),
+                (prompt_ready_up, prompt_reload).before(update_prompt_area),
+                update_prompt_area.after(rebuild_hud),
+            ).run_if(in_state(AppState::Game)));
+    }
+}
+
+pub fn spawn_hud(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    theme: Res<HudTheme>,
+    widgets: Res<HudWidgets>,
+) {
+    build_hud(&mut commands, &asset_server, &theme, &widgets);
+}
+
+pub fn despawn_hud(mut commands: Commands, hud_query: Query<Entity, With<Hud>>) {
+    for hud_entity in hud_query.iter() {
+        commands.entity(hud_entity).despawn_recursive();
+    }
+}
+
+// the theme and widgets may be changed at any time, e.g. in the editor
+pub fn rebuild_hud(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    theme: Res<HudTheme>,
+    widgets: Res<HudWidgets>,
+    hud_query: Query<Entity, With<Hud>>,
+    mut ran: Local<bool>,
+) {
+    // The first run always counts as a change, but `spawn_hud` has just built it.
+    if !*ran {
+        *ran = true;
+        return;
+    }
+    for hud_entity in hud_query.iter() {
+        commands.entity(hud_entity).despawn_recursive();
+    }
+    build_hud(&mut commands, &asset_server, &theme, &widgets);
+}
+
+pub fn build_hud(commands: &mut Commands, asset_server: &AssetServer, theme: &HudTheme, widgets: &HudWidgets) -> Entity {
+    commands
+        .spawn((
+            Name::new("Hud"),
+            Hud,
+            NodeBundle {
+                style: HUD_STYLE,
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            build_crosshair(parent, theme);
+
+            for anchor in [HudAnchor::TopLeft, HudAnchor::TopRight, HudAnchor::BottomLeft, HudAnchor::BottomRight] {
+                parent
+                    .spawn((
+                        Name::new(format!("HudCorner {:?}", anchor)),
+                        NodeBundle {
+                            style: corner_style(anchor, theme.margin),
+                            ..default()
+                        },
+                    ))
+                    .with_children(|parent| {
+                        for widget in widgets.0.iter().filter(|widget| widget.anchor == anchor) {
+                            build_widget(parent, widget, theme, asset_server);
+                        }
+                    });
+            }
+
+            parent.spawn((
+                Name::new("Notifications"),
+                NotificationArea,
+                NodeBundle {
+                    style: NOTIFICATION_AREA_STYLE,
+                    ..default()
+                },
+            ));
+
+            parent.spawn((
+                Name::new("KillFeed"),
+                KillFeed,
+                NodeBundle {
+                    style: KILL_FEED_STYLE,
+                    ..default()
+                },
+            ));
+
+            parent
+                .spawn((
+                    Name::new("Scoreboard"),
+                    Scoreboard,
+                    NodeBundle {
+                        style: SCOREBOARD_AREA_STYLE,
+                        visibility: Visibility::Hidden,
+                        ..default()
+                    },
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        ScoreboardRows,
+                        NodeBundle {
+                            style: SCOREBOARD_STYLE,
+                            background_color: theme.scoreboard_background.into(),
+                            ..default()
+                        },
+                    ));
+                });
+
+            parent
+                .spawn((
+                    Name::new("ContextPrompt"),
+                    PromptArea,
+                    NodeBundle {
+                        style: PROMPT_AREA_STYLE,
+                        visibility: Visibility::Hidden,
+                        ..default()
+                    },
+                ))
+                .with_children(|parent| {
+                    parent.spawn(NodeBundle {
+                        style: PANEL_STYLE,
+                        background_color: theme.panel_color.into(),
+                        ..default()
+                    }).with_children(|parent| {
+                        parent.spawn(new_text_label("", get_hud_text_style(theme, asset_server)));
+                    });
+                });
+        })
+        .id()
+}
+
+// four lines around the center, with a gap in the middle
+fn build_crosshair(parent: &mut ChildBuilder, theme: &HudTheme) {
+    let offset = theme.crosshair_gap + theme.crosshair_size / 2.0;
+    let line = |x: f32, y: f32, horizontal: bool| {
+        let (width, height) = if horizontal {
+            (theme.crosshair_size, theme.crosshair_thickness)
+        } else {
+            (theme.crosshair_thickness, theme.crosshair_size)
+        };
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Px(width),
+                height: Val::Px(height),
+                left: Val::Px(x - width / 2.0),
+                top: Val::Px(y - height / 2.0),
+                ..default()
+            },
+            background_color: theme.crosshair_color.into(),
+            ..default()
+        }
+    };
+
+    parent
+        .spawn((
+            Name::new("Crosshair"),
+            Crosshair,
+            NodeBundle {
+                style: CROSSHAIR_STYLE,
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            // a zero sized center, the lines are positioned relative to it
+            parent.spawn(NodeBundle::default()).with_children(|center| {
+                center.spawn(line(-offset, 0.0, true));
+                center.spawn(line(offset, 0.0, true));
+                center.spawn(line(0.0, -offset, false));
+                center.spawn(line(0.0, offset, false));
+            });
+        });
+}
+
+pub fn show_crosshair(
+    aim_state: Res<AimState>,
+    players: Query<(), With<LocalPlayer>>,
+    mut crosshairs: Query<&mut Visibility, With<Crosshair>>,
+) {
+    // The scope has its own crosshair.
+    let visible = !players.is_empty() && !(aim_state.scoped && aim_state.blend >= 1.0);
+    for mut visibility in crosshairs.iter_mut() {
+        *visibility = if visible { Visibility::Inherited } else { Visibility::Hidden };
+    }
+}
@@ -0,0 +1,245 @@
+use std::marker::PhantomData;
+
+use bevy::prelude::*;
+
+use crate::game::game_modes::capture_the_flag::{FlagEvent, FlagEventKind};
+use crate::game::game_modes::{describe_winner, participant_name, MatchPhase, MatchStatus};
+use crate::game::hud::styles::{get_hud_small_text_style, HudTheme, PANEL_STYLE};
+use crate::game::pickups::PickedUp;
+use crate::game::player::LocalPlayer;
+use crate::game::waves::{EncounterCompleted, WaveCleared, WaveStarted};
+use crate::main_menu::elements::new_text_label;
+
+#[derive(Event, Debug, Clone)]
+/// Shows a message at the top of the screen for a while.
+pub struct HudNotification {
+    pub text: String,
+    /// Seconds it stays, `HudTheme::notification_duration` if not set.
+    pub duration: Option<f32>,
+}
+
+impl HudNotification {
+    pub fn new(text: impl Into<String>) -> Self {
+        HudNotification {
+            text: text.into(),
+            duration: None,
+        }
+    }
+
+    pub fn with_duration(mut self, duration: f32) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct NotificationArea;
+
+#[derive(Component, Debug)]
+pub struct NotificationEntry {
+    pub remaining: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ShownNotification {
+    pub text: String,
+    /// Seconds until it disappears.
+    pub remaining: f32,
+}
+
+#[derive(Resource, Debug)]
+/// What the area with the marker `A` shows, oldest first. Kept outside the UI, so that nothing is
+/// lost when the HUD is rebuilt.
+pub struct NotificationLog<A> {
+    pub entries: Vec<ShownNotification>,
+    marker: PhantomData<A>,
+}
+
+impl<A> Default for NotificationLog<A> {
+    fn default() -> Self {
+        NotificationLog {
+            entries: Vec::new(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<A> NotificationLog<A> {
+    /// Adds an entry at the end and drops the oldest ones above `max`.
+    pub fn push(&mut self, text: impl Into<String>, duration: f32, max: usize) {
+        self.entries.push(ShownNotification {
+            text: text.into(),
+            remaining: duration,
+        });
+        let excess = self.entries.len().saturating_sub(max);
+        self.entries.drain(..excess);
+    }
+}
+
+pub fn show_notifications(
+    theme: Res<HudTheme>,
+    mut notifications: EventReader<HudNotification>,
+    mut log: ResMut<NotificationLog<NotificationArea>>,
+) {
+    for notification in notifications.read() {
+        let duration = notification.duration.unwrap_or(theme.notification_duration);
+        log.push(notification.text.clone(), duration, theme.max_notifications);
+    }
+}
+
+pub fn clear_notification_log<A: Component>(mut log: ResMut<NotificationLog<A>>) {
+    log.entries.clear();
+}
+
+// The countdown alone doesn't count as a change, only entries running out rebuild the area.
+pub fn expire_notification_log<A: Component>(time: Res<Time>, mut log: ResMut<NotificationLog<A>>) {
+    let delta = time.delta_seconds();
+    for entry in log.bypass_change_detection().entries.iter_mut() {
+        entry.remaining -= delta;
+    }
+    if log.entries.iter().any(|entry| entry.remaining <= 0.0) {
+        log.entries.retain(|entry| entry.remaining > 0.0);
+    }
+}
+
+/// Builds the entries of the area again whenever the log changes or the HUD was rebuilt.
+pub fn update_notification_area<A: Component>(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    theme: Res<HudTheme>,
+    log: Res<NotificationLog<A>>,
+    areas: Query<(Entity, Ref<A>)>,
+) {
+    for (area, marker) in areas.iter() {
+        if !log.is_changed() && !marker.is_added() {
+            continue;
+        }
+        commands.entity(area).despawn_descendants();
+        for shown in log.entries.iter() {
+            let entry = spawn_notification_entry(&mut commands, &theme, &asset_server, &shown.text, shown.remaining);
+            commands.entity(area).add_child(entry);
+        }
+    }
+}
+
+/// A panel with a line of text, which fades out in `fade_notifications`.
+pub fn spawn_notification_entry(
+    commands: &mut Commands,
+    theme: &HudTheme,
+    asset_server: &AssetServer,
+    text: &str,
+    remaining: f32,
+) -> Entity {
+    commands.spawn((
+        Name::new("Notification"),
+        NotificationEntry { remaining },
+        NodeBundle {
+            style: PANEL_STYLE,
+            background_color: theme.panel_color.into(),
+            ..default()
+        },
+    )).with_children(|parent| {
+        parent.spawn(new_text_label(text, get_hud_small_text_style(theme, asset_server)));
+    }).id()
+}
+
+// The entries are removed with their log, this only fades them out before.
+pub fn fade_notifications(
+    time: Res<Time>,
+    theme: Res<HudTheme>,
+    mut entries: Query<(&mut NotificationEntry, &mut BackgroundColor, &Children)>,
+    mut texts: Query<&mut Text>,
+) {
+    for (mut entry, mut background, children) in entries.iter_mut() {
+        entry.remaining -= time.delta_seconds();
+        let alpha = (entry.remaining / theme.notification_fade.max(f32::EPSILON)).clamp(0.0, 1.0);
+        background.0.set_a(theme.panel_color.a() * alpha);
+        for child in children.iter() {
+            if let Ok(mut text) = texts.get_mut(*child) {
+                for section in text.sections.iter_mut() {
+                    section.style.color.set_a(theme.text_color.a() * alpha);
+                }
+            }
+        }
+    }
+}
+
+pub fn notify_controls(mut notifications: EventWriter<HudNotification>) {
+    notifications.send(HudNotification::new("Press `Q` key to switch angular state").with_duration(8.0));
+}
+
+pub fn notify_pickups(
+    mut picked_up: EventReader<PickedUp>,
+    local_players: Query<(), With<LocalPlayer>>,
+    mut notifications: EventWriter<HudNotification>,
+) {
+    for event in picked_up.read() {
+        if !local_players.contains(event.collector) {
+            continue;
+        }
+        let item = event.item.strip_prefix("ammo:").unwrap_or(&event.item);
+        notifications.send(HudNotification::new(format!("+{} {}", event.quantity, item)));
+    }
+}
+
+pub fn notify_waves(
+    mut started: EventReader<WaveStarted>,
+    mut cleared: EventReader<WaveCleared>,
+    mut completed: EventReader<EncounterCompleted>,
+    mut notifications: EventWriter<HudNotification>,
+) {
+    for event in started.read() {
+        notifications.send(HudNotification::new(format!("Wave {}: {}", event.index + 1, event.name)));
+    }
+    for event in cleared.read() {
+        notifications.send(HudNotification::new(format!("Wave {} cleared", event.index + 1)));
+    }
+    for _ in completed.read() {
+        notifications.send(HudNotification::new("All waves cleared").with_duration(8.0));
+    }
+}
+
+// follows the replicated status, so that it works on clients too
+pub fn notify_match_phases(
+    status: Res<MatchStatus>,
+    mut last_phase: Local<MatchPhase>,
+    mut notifications: EventWriter<HudNotification>,
+) {
+    if status.phase == *last_phase {
+        return;
+    }
+    *last_phase = status.phase;
+
+    let text = match status.phase {
+        MatchPhase::Inactive => return,
+        MatchPhase::Lobby => format!("{}: waiting for players", status.mode),
+        MatchPhase::Warmup => "Warmup".to_string(),
+        MatchPhase::InProgress if status.rounds > 1 => format!("Round {} of {}", status.round, status.rounds),
+        MatchPhase::InProgress => "Fight!".to_string(),
+        MatchPhase::RoundEnd => format!("Round over: {}", describe_winner(status.winner, &status.players)),
+        MatchPhase::Intermission => format!("Match over: {}", describe_winner(status.winner, &status.players)),
+    };
+    notifications.send(HudNotification::new(text));
+}
+
+// Flag events are only sent where the flags are known, clients replicate them from the status.
+pub fn notify_flags(
+    status: Res<MatchStatus>,
+    mut flag_events: EventReader<FlagEvent>,
+    mut notifications: EventWriter<HudNotification>,
+) {
+    for event in flag_events.read() {
+        let flag = format!("the flag of team {}", event.team);
+        let who = event.participant.map(|participant| participant_name(participant, &status.players));
+        let text = match (event.kind, who) {
+            (FlagEventKind::Taken, Some(who)) => format!("{} took {}", who, flag),
+            (FlagEventKind::Dropped, Some(who)) => format!("{} dropped {}", who, flag),
+            (FlagEventKind::Returned, Some(who)) => format!("{} returned {}", who, flag),
+            (FlagEventKind::Captured, Some(who)) => format!("{} captured {}", who, flag),
+            (FlagEventKind::Taken | FlagEventKind::Dropped, None) => format!("The carrier of {} is down", flag),
+            (FlagEventKind::Returned, None) => format!("The flag of team {} returned", event.team),
+            (FlagEventKind::Captured, None) => format!("The flag of team {} was captured", event.team),
+        };
+        notifications.send(HudNotification::new(text));
+    }
+}
@@ -0,0 +1,81 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use crate::game::game_modes::{LocalReady, MatchPhase, MatchStatus};
+use crate::game::player::LocalPlayer;
+use crate::game::weapons::Weapon;
+
+#[derive(Resource, Debug, Default)]
+/// Hints about what the player can do right now, e.g. "[E] Open door".
+/// Only the one with the highest priority is shown.
+pub struct ContextPrompts {
+    prompts: HashMap<&'static str, (i32, String)>,
+}
+
+impl ContextPrompts {
+    /// Sets the prompt of a source, replacing its previous one.
+    pub fn show(&mut self, source: &'static str, priority: i32, text: impl Into<String>) {
+        let text = text.into();
+        if self.prompts.get(source).is_some_and(|current| current.0 == priority && current.1 == text) {
+            return;
+        }
+        self.prompts.insert(source, (priority, text));
+    }
+
+    pub fn hide(&mut self, source: &'static str) {
+        self.prompts.remove(source);
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.prompts
+            .values()
+            .max_by_key(|(priority, _)| *priority)
+            .map(|(_, text)| text.as_str())
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct PromptArea;
+
+pub fn update_prompt_area(
+    prompts: Res<ContextPrompts>,
+    mut areas: Query<(&mut Visibility, &Children), With<PromptArea>>,
+    mut texts: Query<&mut Text>,
+) {
+    let prompt = prompts.current();
+    for (mut visibility, children) in areas.iter_mut() {
+        *visibility = if prompt.is_some() { Visibility::Inherited } else { Visibility::Hidden };
+        let Some(prompt) = prompt else {
+            continue;
+        };
+        for child in children.iter() {
+            let Ok(mut text) = texts.get_mut(*child) else {
+                continue;
+            };
+            if text.sections[0].value != prompt {
+                text.sections[0].value = prompt.to_string();
+            }
+        }
+    }
+}
+
+pub fn prompt_ready_up(status: Res<MatchStatus>, local_ready: Res<LocalReady>, mut prompts: ResMut<ContextPrompts>) {
+    if status.phase != MatchPhase::Lobby {
+        prompts.hide("ready");
+    } else if local_ready.0 {
+        prompts.show("ready", 10, "Ready, waiting for the other players. [Enter] Not ready");
+    } else {
+        prompts.show("ready", 10, "[Enter] Ready up");
+    }
+}
+
+pub fn prompt_reload(weapons: Query<&Weapon, With<LocalPlayer>>, mut prompts: ResMut<ContextPrompts>) {
+    let empty = weapons
+        .get_single()
+        .is_ok_and(|weapon| weapon.magazine == 0 && weapon.reloading.is_none() && !weapon.holstering);
+    if empty {
+        prompts.show("reload", 5, "[R] Reload");
+    } else {
+        prompts.hide("reload");
+    }
+}
@@ -0,0 +1,129 @@
+use bevy::prelude::*;
+
+use crate::game::game_modes::{describe_winner, MatchPhase, MatchStatus, MatchWinner, ScoreboardEntry};
+use crate::game::hud::styles::{
+    get_hud_small_text_style, get_hud_text_style, scoreboard_cell_style, HudTheme, SCOREBOARD_ROW_STYLE,
+};
+use crate::main_menu::elements::new_text_label;
+
+#[derive(Component, Debug)]
+/// Shown while `Tab` is held and between the rounds. During the intermission it is the results
+/// screen of network games, offline matches go to `AppState::GameOver` instead.
+pub struct Scoreboard;
+
+#[derive(Component, Debug)]
+/// The part of the scoreboard which is rebuilt when the scores change.
+pub struct ScoreboardRows;
+
+pub fn show_scoreboard(
+    keyboard: Res<Input<KeyCode>>,
+    status: Res<MatchStatus>,
+    mut scoreboards: Query<&mut Visibility, With<Scoreboard>>,
+) {
+    let between_rounds = matches!(status.phase, MatchPhase::RoundEnd | MatchPhase::Intermission);
+    let visible = keyboard.pressed(KeyCode::Tab) || between_rounds;
+    for mut visibility in scoreboards.iter_mut() {
+        let wanted = if visible { Visibility::Inherited } else { Visibility::Hidden };
+        if *visibility != wanted {
+            *visibility = wanted;
+        }
+    }
+}
+
+// The status changes every frame while a clock runs, so only the lines are compared.
+pub fn update_scoreboard(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    theme: Res<HudTheme>,
+    status: Res<MatchStatus>,
+    rows: Query<(Entity, Ref<ScoreboardRows>)>,
+    mut shown: Local<(String, Option<Option<MatchWinner>>, Vec<i32>, Vec<ScoreboardEntry>)>,
+) {
+    let Ok((rows, marker)) = rows.get_single() else {
+        return;
+    };
+    let result = (status.phase == MatchPhase::Intermission).then_some(status.winner);
+    let current = (status.mode.clone(), result, status.team_scores.clone(), status.players.clone());
+    if !marker.is_added() && *shown == current {
+        return;
+    }
+    *shown = current;
+
+    commands.entity(rows).despawn_descendants();
+    commands.entity(rows).with_children(|parent| {
+        build_scoreboard_rows(parent, &status, &theme, &asset_server);
+    });
+}
+
+/// The title and the players.
+pub fn build_scoreboard_rows(parent: &mut ChildBuilder, status: &MatchStatus, theme: &HudTheme, asset_server: &AssetServer) {
+    let mut title = if status.mode.is_empty() { "Scoreboard".to_string() } else { status.mode.clone() };
+    if status.phase == MatchPhase::Intermission {
+        title = format!("{}: {}", title, describe_winner(status.winner, &status.players));
+    }
+    parent.spawn(new_text_label(&title, get_hud_text_style(theme, asset_server)));
+    build_player_rows(parent, &status.players, &status.team_scores, theme, asset_server);
+}
+
+/// A header and a line per player, grouped by team. Also the table of the results screen.
+pub fn build_player_rows(
+    parent: &mut ChildBuilder,
+    players: &[ScoreboardEntry],
+    team_scores: &[i32],
+    theme: &HudTheme,
+    asset_server: &AssetServer,
+) {
+    // pings are only known in network games
+    let network = players.iter().any(|entry| entry.ping.is_some());
+    let mut header = vec!["Name", "Score", "Kills", "Deaths", "Assists"];
+    if network {
+        header.push("Ping");
+    }
+    spawn_row(parent, &header, theme, asset_server);
+
+    let teams = team_scores.len() as u8;
+    for team in 0..=teams {
+        let players: Vec<&ScoreboardEntry> = players.iter().filter(|entry| entry.team == team).collect();
+        if team > 0 {
+            let score = team_scores[team as usize - 1];
+            parent.spawn(new_text_label(&format!("Team {}: {}", team, score), get_hud_small_text_style(theme, asset_server)));
+        } else if players.is_empty() {
+            continue;
+        }
+
+        for entry in players {
+            let mut cells = vec![
+                entry.name.clone(),
+                entry.score.to_string(),
+                entry.kills.to_string(),
+                entry.deaths.to_string(),
+                entry.assists.to_string(),
+            ];
+            if network {
+                cells.push(match entry.ping {
+                    _ if entry.bot => "BOT".to_string(),
+                    Some(ping) => ping.to_string(),
+                    None => "-".to_string(),
+                });
+            }
+            spawn_row(parent, &cells, theme, asset_server);
+        }
+    }
+}
+
+fn spawn_row(parent: &mut ChildBuilder, cells: &[impl AsRef<str>], theme: &HudTheme, asset_server: &AssetServer) {
+    parent.spawn(NodeBundle {
+        style: SCOREBOARD_ROW_STYLE,
+        ..default()
+    }).with_children(|row| {
+        for (index, cell) in cells.iter().enumerate() {
+            let width = if index == 0 { theme.scoreboard_name_width } else { theme.scoreboard_column_width };
+            row.spawn(NodeBundle {
+                style: scoreboard_cell_style(width, index > 0),
+                ..default()
+            }).with_children(|cell_node| {
+                cell_node.spawn(new_text_label(cell.as_ref(), get_hud_small_text_style(theme, asset_server)));
+            });
+        }
+    });
+}
@@ -0,0 +1,201 @@
+use bevy::prelude::*;
+
+use crate::game::hud::widgets::HudAnchor;
+
+pub const HUD_STYLE: Style = {
+    let mut style = Style::DEFAULT;
+    style.position_type = PositionType::Absolute;
+    style.width = Val::Percent(100.0);
+    style.height = Val::Percent(100.0);
+    style
+};
+
+pub const CROSSHAIR_STYLE: Style = {
+    let mut style = HUD_STYLE;
+    style.justify_content = JustifyContent::Center;
+    style.align_items = AlignItems::Center;
+    style
+};
+
+pub const NOTIFICATION_AREA_STYLE: Style = {
+    let mut style = Style::DEFAULT;
+    style.position_type = PositionType::Absolute;
+    style.width = Val::Percent(100.0);
+    style.top = Val::Percent(12.0);
+    style.flex_direction = FlexDirection::Column;
+    style.align_items = AlignItems::Center;
+    style.row_gap = Val::Px(4.0);
+    style
+};
+
+pub const PROMPT_AREA_STYLE: Style = {
+    let mut style = Style::DEFAULT;
+    style.position_type = PositionType::Absolute;
+    style.width = Val::Percent(100.0);
+    style.bottom = Val::Percent(35.0);
+    style.justify_content = JustifyContent::Center;
+    style
+};
+
+pub const KILL_FEED_STYLE: Style = {
+    let mut style = Style::DEFAULT;
+    style.position_type = PositionType::Absolute;
+    style.top = Val::Percent(20.0);
+    style.right = Val::Px(16.0);
+    style.flex_direction = FlexDirection::Column;
+    style.align_items = AlignItems::FlexEnd;
+    style.row_gap = Val::Px(4.0);
+    style
+};
+
+pub const SCOREBOARD_AREA_STYLE: Style = {
+    let mut style = Style::DEFAULT;
+    style.position_type = PositionType::Absolute;
+    style.width = Val::Percent(100.0);
+    style.top = Val::Percent(15.0);
+    style.justify_content = JustifyContent::Center;
+    style
+};
+
+pub const SCOREBOARD_STYLE: Style = {
+    let mut style = Style::DEFAULT;
+    style.flex_direction = FlexDirection::Column;
+    style.padding = UiRect::all(Val::Px(16.0));
+    style.row_gap = Val::Px(4.0);
+    style
+};
+
+pub const SCOREBOARD_ROW_STYLE: Style = {
+    let mut style = Style::DEFAULT;
+    style.align_items = AlignItems::Center;
+    style
+};
+
+pub const PANEL_STYLE: Style = {
+    let mut style = Style::DEFAULT;
+    style.padding = UiRect::axes(Val::Px(10.0), Val::Px(4.0));
+    style
+};
+
+pub const WIDGET_STYLE: Style = {
+    let mut style = Style::DEFAULT;
+    style.align_items = AlignItems::Center;
+    style.column_gap = Val::Px(8.0);
+    style
+};
+
+/// A column of the scoreboard, numbers are right aligned.
+pub fn scoreboard_cell_style(width: f32, numeric: bool) -> Style {
+    Style {
+        width: Val::Px(width),
+        justify_content: if numeric { JustifyContent::FlexEnd } else { JustifyContent::FlexStart },
+        ..default()
+    }
+}
+
+pub fn corner_style(anchor: HudAnchor, margin: f32) -> Style {
+    let margin = Val::Px(margin);
+    let (top, bottom) = match anchor {
+        HudAnchor::TopLeft | HudAnchor::TopRight => (margin, Val::Auto),
+        HudAnchor::BottomLeft | HudAnchor::BottomRight => (Val::Auto, margin),
+    };
+    let (left, right, align_items) = match anchor {
+        HudAnchor::TopLeft | HudAnchor::BottomLeft => (margin, Val::Auto, AlignItems::FlexStart),
+        HudAnchor::TopRight | HudAnchor::BottomRight => (Val::Auto, margin, AlignItems::FlexEnd),
+    };
+    Style {
+        position_type: PositionType::Absolute,
+        top,
+        bottom,
+        left,
+        right,
+        flex_direction: FlexDirection::Column,
+        align_items,
+        row_gap: Val::Px(6.0),
+        ..default()
+    }
+}
+
+#[derive(Resource, Reflect, Debug, Clone)]
+#[reflect(Resource)]
+/// Colors, sizes and font of the HUD. It is rebuilt when this changes.
+pub struct HudTheme {
+    pub font: String,
+    pub font_size: f32,
+    /// For labels and notifications.
+    pub small_font_size: f32,
+    pub text_color: Color,
+    /// Background of notifications and prompts.
+    pub panel_color: Color,
+    pub bar_width: f32,
+    pub bar_height: f32,
+    pub bar_background: Color,
+    /// Fill of bars which don't have their own color.
+    pub bar_color: Color,
+    pub crosshair_color: Color,
+    /// Length of each of the four lines.
+    pub crosshair_size: f32,
+    pub crosshair_thickness: f32,
+    /// Space between the center and the lines.
+    pub crosshair_gap: f32,
+    /// Distance of the widgets from the screen edges.
+    pub margin: f32,
+    /// Seconds a notification is shown, unless it asks for something else.
+    pub notification_duration: f32,
+    /// Seconds notifications take to fade out at the end.
+    pub notification_fade: f32,
+    /// Older notifications are removed when there are more.
+    pub max_notifications: usize,
+    /// Seconds a kill stays in the kill feed.
+    pub kill_feed_duration: f32,
+    /// Older kills are removed from the feed when there are more.
+    pub max_kill_feed: usize,
+    pub scoreboard_background: Color,
+    /// Width of the name column, the numbers use `scoreboard_column_width`.
+    pub scoreboard_name_width: f32,
+    pub scoreboard_column_width: f32,
+}
+
+impl Default for HudTheme {
+    fn default() -> Self {
+        HudTheme {
+            font: "fonts/FiraSans-Bold.ttf".to_string(),
+            font_size: 28.0,
+            small_font_size: 18.0,
+            text_color: Color::WHITE,
+            panel_color: Color::rgba(0.0, 0.0, 0.0, 0.5),
+            bar_width: 200.0,
+            bar_height: 12.0,
+            bar_background: Color::rgba(0.0, 0.0, 0.0, 0.5),
+            bar_color: Color::rgb(0.9, 0.9, 0.9),
+            crosshair_color: Color::rgba(1.0, 1.0, 1.0, 0.8),
+            crosshair_size: 8.0,
+            crosshair_thickness: 2.0,
+            crosshair_gap: 4.0,
+            margin: 16.0,
+            notification_duration: 4.0,
+            notification_fade: 0.5,
+            max_notifications: 5,
+            kill_feed_duration: 6.0,
+            max_kill_feed: 5,
+            scoreboard_background: Color::rgba(0.0, 0.0, 0.0, 0.75),
+            scoreboard_name_width: 220.0,
+            scoreboard_column_width: 80.0,
+        }
+    }
+}
+
+pub fn get_hud_text_style(theme: &HudTheme, asset_server: &AssetServer) -> TextStyle {
+    TextStyle {
+        font: asset_server.load(&theme.font),
+        font_size: theme.font_size,
+        color: theme.text_color,
+    }
+}
+
+pub fn get_hud_small_text_style(theme: &HudTheme, asset_server: &AssetServer) -> TextStyle {
+    TextStyle {
+        font_size: theme.small_font_size,
+        ..get_hud_text_style(theme, asset_server)
+    }
+}
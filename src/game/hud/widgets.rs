@@ -0,0 +1,328 @@
+use bevy::prelude::*;
+use bevy::reflect::{GetPath, TypeRegistry};
+
+use crate::game::hud::styles::{get_hud_small_text_style, get_hud_text_style, HudTheme, WIDGET_STYLE};
+use crate::game::player::LocalPlayer;
+use crate::main_menu::elements::new_text_label;
+
+#[derive(Reflect, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HudAnchor {
+    TopLeft,
+    TopRight,
+    #[default]
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(Reflect, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HudWidgetKind {
+    /// Fills up to `max_field`, or up to `1` without it.
+    #[default]
+    Bar,
+    /// Shows the value as text, see `HudWidget::format`.
+    Counter,
+}
+
+#[derive(Component, Reflect, Debug, Default, Clone)]
+#[reflect(Component)]
+/// A number in a reflected component of the local player, e.g. `Health` and `current`.
+pub struct HudBinding {
+    /// Type name of the component, short (`Health`) or with the module path.
+    pub component: String,
+    /// Path of the field in the component, e.g. `current` or `slots[0].magazine`.
+    pub field: String,
+    /// Path of the field with the maximum value, in the same component.
+    pub max_field: Option<String>,
+}
+
+impl HudBinding {
+    pub fn new(component: &str, field: &str, max_field: Option<&str>) -> Self {
+        HudBinding {
+            component: component.to_string(),
+            field: field.to_string(),
+            max_field: max_field.map(str::to_string),
+        }
+    }
+
+    /// The value and the maximum, `None` if the entity doesn't have the component.
+    pub fn read(&self, world: &World, registry: &TypeRegistry, entity: Entity) -> Option<(f32, Option<f32>)> {
+        let registration = registry
+            .get_with_short_type_path(&self.component)
+            .or_else(|| registry.get_with_type_path(&self.component))?;
+        let component = registration.data::<ReflectComponent>()?.reflect(world.get_entity(entity)?)?;
+
+        let number = |path: &str| component.reflect_path(path).ok().and_then(as_number);
+        let value = number(&self.field)?;
+        Some((value, self.max_field.as_deref().and_then(number)))
+    }
+}
+
+fn as_number(value: &dyn Reflect) -> Option<f32> {
+    let any = value.as_any();
+    any.downcast_ref::<f32>().copied()
+        .or_else(|| any.downcast_ref::<f64>().map(|value| *value as f32))
+        .or_else(|| any.downcast_ref::<u32>().map(|value| *value as f32))
+        .or_else(|| any.downcast_ref::<i32>().map(|value| *value as f32))
+        .or_else(|| any.downcast_ref::<u64>().map(|value| *value as f32))
+        .or_else(|| any.downcast_ref::<usize>().map(|value| *value as f32))
+        .or_else(|| any.downcast_ref::<u8>().map(|value| *value as f32))
+        .or_else(|| any.downcast_ref::<bool>().map(|value| if *value { 1.0 } else { 0.0 }))
+}
+
+#[derive(Reflect, Debug, Clone)]
+pub struct HudWidget {
+    pub kind: HudWidgetKind,
+    /// Shown in front of the value, may be empty.
+    pub label: String,
+    pub binding: HudBinding,
+    /// Text of counters, `{value}` and `{max}` are replaced.
+    pub format: String,
+    pub anchor: HudAnchor,
+    /// Fill of bars, `HudTheme::bar_color` if not set.
+    pub color: Option<Color>,
+    /// Hides bars which are full and counters at their maximum, e.g. the oxygen above water.
+    pub hide_when_full: bool,
+}
+
+impl Default for HudWidget {
+    fn default() -> Self {
+        HudWidget {
+            kind: HudWidgetKind::Bar,
+            label: String::new(),
+            binding: HudBinding::default(),
+            format: "{value}".to_string(),
+            anchor: HudAnchor::BottomLeft,
+            color: None,
+            hide_when_full: false,
+        }
+    }
+}
+
+#[derive(Resource, Reflect, Debug, Clone)]
+#[reflect(Resource)]
+/// The value widgets of the HUD, in the order they are stacked in their corner.
+pub struct HudWidgets(pub Vec<HudWidget>);
+
+impl Default for HudWidgets {
+    fn default() -> Self {
+        HudWidgets(vec![
+            HudWidget {
+                label: "Oxygen".to_string(),
+                binding: HudBinding::new("Oxygen", "remaining", Some("max")),
+                color: Some(Color::rgb(0.3, 0.7, 0.9)),
+                hide_when_full: true,
+                ..default()
+            },
+            HudWidget {
+                label: "Armor".to_string(),
+                binding: HudBinding::new("Armor", "current", Some("max")),
+                color: Some(Color::rgb(0.3, 0.5, 0.9)),
+                ..default()
+            },
+            HudWidget {
+                label: "Health".to_string(),
+                binding: HudBinding::new("Health", "current", Some("max")),
+                color: Some(Color::rgb(0.85, 0.2, 0.2)),
+                ..default()
+            },
+            HudWidget {
+                kind: HudWidgetKind::Counter,
+                binding: HudBinding::new("Weapon", "magazine", None),
+                anchor: HudAnchor::BottomRight,
+                ..default()
+            },
+        ])
+    }
+}
+
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq)]
+/// Last value read through the `HudBinding` of the widget.
+pub struct BoundValue {
+    pub value: Option<f32>,
+    pub max: Option<f32>,
+}
+
+impl BoundValue {
+    /// Between `0` and `1`, measured against the maximum if there is one.
+    pub fn ratio(&self) -> Option<f32> {
+        let value = self.value?;
+        let ratio = match self.max {
+            Some(max) if max > 0.0 => value / max,
+            Some(_) => 0.0,
+            None => value,
+        };
+        Some(ratio.clamp(0.0, 1.0))
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct HudBar {
+    pub fill: Entity,
+    pub hide_when_full: bool,
+}
+
+#[derive(Component, Debug)]
+pub struct HudCounter {
+    pub text: Entity,
+    pub format: String,
+    pub hide_when_full: bool,
+}
+
+pub fn build_widget(parent: &mut ChildBuilder, widget: &HudWidget, theme: &HudTheme, asset_server: &AssetServer) {
+    let mut row = parent.spawn((
+        Name::new(format!("HudWidget {}", widget.label)),
+        NodeBundle {
+            style: WIDGET_STYLE,
+            ..default()
+        },
+        widget.binding.clone(),
+        BoundValue::default(),
+    ));
+
+    let mut value_entity = Entity::PLACEHOLDER;
+    row.with_children(|row| {
+        if !widget.label.is_empty() {
+            row.spawn(new_text_label(&widget.label, get_hud_small_text_style(theme, asset_server)));
+        }
+        match widget.kind {
+            HudWidgetKind::Bar => {
+                row.spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Px(theme.bar_width),
+                        height: Val::Px(theme.bar_height),
+                        ..default()
+                    },
+                    background_color: theme.bar_background.into(),
+                    ..default()
+                }).with_children(|bar| {
+                    value_entity = bar.spawn(NodeBundle {
+                        style: Style {
+                            width: Val::Percent(0.0),
+                            height: Val::Percent(100.0),
+                            ..default()
+                        },
+                        background_color: widget.color.unwrap_or(theme.bar_color).into(),
+                        ..default()
+                    }).id();
+                });
+            }
+            HudWidgetKind::Counter => {
+                value_entity = row.spawn(new_text_label("", get_hud_text_style(theme, asset_server))).id();
+            }
+        }
+    });
+
+    match widget.kind {
+        HudWidgetKind::Bar => row.insert(HudBar {
+            fill: value_entity,
+            hide_when_full: widget.hide_when_full,
+        }),
+        HudWidgetKind::Counter => row.insert(HudCounter {
+            text: value_entity,
+            format: widget.format.clone(),
+            hide_when_full: widget.hide_when_full,
+        }),
+    };
+}
+
+// Exclusive, reading any registered component needs the whole world.
+pub fn read_bound_values(world: &mut World) {
+    let player = world.query_filtered::<Entity, With<LocalPlayer>>().iter(world).next();
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let registry = registry.read();
+
+    let mut widgets = world.query::<(Entity, &HudBinding, &BoundValue)>();
+    let changed: Vec<(Entity, BoundValue)> = widgets
+        .iter(world)
+        .filter_map(|(entity, binding, current)| {
+            let read = player.and_then(|player| binding.read(world, &registry, player));
+            let value = BoundValue {
+                value: read.map(|(value, _)| value),
+                max: read.and_then(|(_, max)| max),
+            };
+            (value != *current).then_some((entity, value))
+        })
+        .collect();
+
+    for (entity, value) in changed {
+        if let Some(mut current) = world.get_mut::<BoundValue>(entity) {
+            *current = value;
+        }
+    }
+}
+
+pub fn update_bars(
+    mut bars: Query<(&HudBar, &BoundValue, &mut Visibility), Changed<BoundValue>>,
+    mut styles: Query<&mut Style>,
+) {
+    for (bar, value, mut visibility) in bars.iter_mut() {
+        let ratio = value.ratio();
+        let hidden = ratio.map_or(true, |ratio| bar.hide_when_full && ratio >= 1.0);
+        *visibility = if hidden { Visibility::Hidden } else { Visibility::Inherited };
+        if let Ok(mut style) = styles.get_mut(bar.fill) {
+            style.width = Val::Percent(ratio.unwrap_or(0.0) * 100.0);
+        }
+    }
+}
+
+pub fn update_counters(
+    mut counters: Query<(&HudCounter, &BoundValue, &mut Visibility), Changed<BoundValue>>,
+    mut texts: Query<&mut Text>,
+) {
+    for (counter, value, mut visibility) in counters.iter_mut() {
+        let full = value.max.is_some() && value.ratio() == Some(1.0);
+        let hidden = value.value.is_none() || (counter.hide_when_full && full);
+        *visibility = if hidden { Visibility::Hidden } else { Visibility::Inherited };
+
+        let (Some(number), Ok(mut text)) = (value.value, texts.get_mut(counter.text)) else {
+            continue;
+        };
+        text.sections[0].value = counter.format
+            .replace("{value}", &format!("{:.0}", number))
+            .replace("{max}", &value.max.map(|max| format!("{:.0}", max)).unwrap_or_default());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::health::Health;
+
+    use super::*;
+
+    fn world() -> World {
+        let mut world = World::new();
+        let registry = AppTypeRegistry::default();
+        registry.write().register::<Health>();
+        world.insert_resource(registry);
+        world
+    }
+
+    fn read(world: &World, binding: &HudBinding, entity: Entity) -> Option<(f32, Option<f32>)> {
+        let registry = world.resource::<AppTypeRegistry>().read();
+        binding.read(world, &registry, entity)
+    }
+
+    #[test]
+    fn reads_the_current_health() {
+        let mut world = world();
+        let player = world.spawn(Health { current: 40.0, max: 120.0 }).id();
+
+        let short = HudBinding::new("Health", "current", Some("max"));
+        assert_eq!(read(&world, &short, player), Some((40.0, Some(120.0))));
+        let full = HudBinding::new(Health::type_path(), "current", None);
+        assert_eq!(read(&world, &full, player), Some((40.0, None)));
+    }
+
+    #[test]
+    fn reads_nothing_without_the_component_or_field() {
+        let mut world = world();
+        let player = world.spawn(Health::new(100.0)).id();
+        let other = world.spawn_empty().id();
+
+        assert_eq!(read(&world, &HudBinding::new("Health", "current", None), other), None);
+        assert_eq!(read(&world, &HudBinding::new("Health", "missing", None), player), None);
+        assert_eq!(read(&world, &HudBinding::new("Oxygen", "remaining", None), player), None);
+        // a broken maximum still shows the value
+        assert_eq!(read(&world, &HudBinding::new("Health", "current", Some("missing")), player), Some((100.0, None)));
+    }
+}
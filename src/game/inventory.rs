@@ -0,0 +1,272 @@
+use bevy::input::mouse::MouseWheel;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_common_assets::ron::RonAssetPlugin;
+use serde::Deserialize;
+
+use crate::app::AppState;
+use crate::game::aim::AimState;
+use crate::game::player::{LocalPlayer, PlayerBody};
+use crate::game::weapons::{fire_weapons, Weapon, WeaponAssets, WeaponDefinition};
+
+#[derive(Asset, TypePath, Deserialize, Debug, Clone)]
+/// Starting equipment, loaded from `assets/loadouts/*.loadout.ron`.
+pub struct Loadout {
+    pub id: String,
+    /// Weapon ids, one slot each.
+    pub weapons: Vec<String>,
+    #[serde(default)]
+    pub ammo: HashMap<String, u32>,
+    #[serde(default)]
+    pub carry_limits: HashMap<String, u32>,
+}
+
+#[derive(Resource, Reflect, Debug, Deref, DerefMut)]
+#[reflect(Resource)]
+/// The loadout new players get. Can be changed by the level or the game mode.
+pub struct ActiveLoadout(pub String);
+
+impl Default for ActiveLoadout {
+    fn default() -> Self {
+        ActiveLoadout("default".to_string())
+    }
+}
+
+#[derive(Component, Reflect, Default, Debug)]
+#[reflect(Component)]
+/// Place it anywhere in a level in Blender to use another loadout on it.
+pub struct LevelLoadout {
+    pub loadout: String,
+}
+
+#[derive(Reflect, Default, Debug, Clone)]
+pub struct InventorySlot {
+    pub weapon: String,
+    /// Rounds left in the magazine while the weapon is not in use.
+    pub magazine: u32,
+}
+
+#[derive(Reflect, Default, Debug, Clone)]
+pub struct WeaponSwitch {
+    pub target: usize,
+    /// Seconds until the current weapon is put away.
+    pub remaining: f32,
+}
+
+#[derive(Component, Reflect, Default, Debug)]
+#[reflect(Component)]
+pub struct Inventory {
+    pub slots: Vec<InventorySlot>,
+    pub active: usize,
+    pub switching: Option<WeaponSwitch>,
+    /// Reserve ammo per ammo type.
+    pub ammo: HashMap<String, u32>,
+    /// Maximum reserve ammo per ammo type, unlimited if missing.
+    pub carry_limits: HashMap<String, u32>,
+    /// Everything else which was picked up, e.g. keys.
+    pub items: HashMap<String, u32>,
+}
+
+impl Inventory {
+    pub fn ammo_count(&self, ammo_type: &str) -> u32 {
+        self.ammo.get(ammo_type).copied().unwrap_or(0)
+    }
+
+    /// Removes up to `wanted` rounds from the reserve and returns how many were taken.
+    pub fn take_ammo(&mut self, ammo_type: &str, wanted: u32) -> u32 {
+        let Some(reserve) = self.ammo.get_mut(ammo_type) else {
+            return 0;
+        };
+        let taken = wanted.min(*reserve);
+        *reserve -= taken;
+        taken
+    }
+
+    /// Adds ammo up to the carry limit and returns how much was accepted.
+    pub fn add_ammo(&mut self, ammo_type: &str, amount: u32) -> u32 {
+        let limit = self.carry_limits.get(ammo_type).copied().unwrap_or(u32::MAX);
+        let reserve = self.ammo.entry(ammo_type.to_string()).or_insert(0);
+        let accepted = amount.min(limit.saturating_sub(*reserve));
+        *reserve += accepted;
+        accepted
+    }
+
+    pub fn add_item(&mut self, item: &str, quantity: u32) {
+        *self.items.entry(item.to_string()).or_insert(0) += quantity;
+    }
+
+    pub fn has_item(&self, item: &str) -> bool {
+        self.items.get(item).is_some_and(|quantity| *quantity > 0)
+    }
+
+    pub fn select(&mut self, slot: usize) {
+        if slot >= self.slots.len() || slot == self.active {
+            return;
+        }
+        // changing the target while holstering keeps the remaining holster time
+        match &mut self.switching {
+            Some(switch) => switch.target = slot,
+            None => self.switching = Some(WeaponSwitch { target: slot, remaining: 0.0 }),
+        }
+    }
+
+    pub fn select_next(&mut self, offset: isize) {
+        if self.slots.is_empty() {
+            return;
+        }
+        let current = self.switching.as_ref().map_or(self.active, |switch| switch.target);
+        let len = self.slots.len() as isize;
+        self.select((current as isize + offset).rem_euclid(len) as usize);
+    }
+}
+
+pub struct InventoryPlugin;
+
+impl Plugin for InventoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(RonAssetPlugin::<Loadout>::new(&["loadout.ron"]))
+            .register_type::<Inventory>()
+            .register_type::<InventorySlot>()
+            .register_type::<WeaponSwitch>()
+            .register_type::<LevelLoadout>()
+            .register_type::<ActiveLoadout>()
+            .init_resource::<ActiveLoadout>()
+            .add_systems(OnExit(AppState::Game), reset_loadout)
+            .add_systems(Update, (
+                apply_level_loadout,
+                equip_loadout.after(apply_level_loadout),
+                apply_inventory_controls,
+                switch_weapons.after(apply_inventory_controls).before(fire_weapons),
+            ).run_if(in_state(AppState::Game)));
+    }
+}
+
+// the next level may not have a `LevelLoadout`
+pub fn reset_loadout(mut active_loadout: ResMut<ActiveLoadout>) {
+    *active_loadout = ActiveLoadout::default();
+}
+
+pub fn apply_level_loadout(
+    mut active_loadout: ResMut<ActiveLoadout>,
+    level_loadouts: Query<&LevelLoadout, Added<LevelLoadout>>,
+) {
+    for level_loadout in level_loadouts.iter() {
+        active_loadout.0 = level_loadout.loadout.clone();
+    }
+}
+
+pub fn equip_loadout(
+    mut commands: Commands,
+    active_loadout: Res<ActiveLoadout>,
+    weapon_assets: Res<WeaponAssets>,
+    loadouts: Res<Assets<Loadout>>,
+    definitions: Res<Assets<WeaponDefinition>>,
+    added_players: Query<Entity, (Added<PlayerBody>, Without<Inventory>)>,
+) {
+    for entity in added_players.iter() {
+        let Some(loadout) = weapon_assets.find_loadout(&loadouts, &active_loadout) else {
+            warn!("loadout `{}` not found", active_loadout.0);
+            continue;
+        };
+
+        let slots: Vec<_> = loadout.weapons
+            .iter()
+            .filter_map(|id| {
+                let handle = weapon_assets.find(&definitions, id)?;
+                let definition = definitions.get(&handle)?;
+                Some(InventorySlot {
+                    weapon: id.clone(),
+                    magazine: definition.magazine_size,
+                })
+            })
+            .collect();
+
+        let mut cmd = commands.entity(entity);
+        if let Some(first) = slots.first() {
+            if let Some(handle) = weapon_assets.find(&definitions, &first.weapon) {
+                cmd.insert(Weapon::new(handle, first.magazine));
+            }
+        }
+        cmd.insert(Inventory {
+            slots,
+            active: 0,
+            switching: None,
+            ammo: loadout.ammo.clone(),
+            carry_limits: loadout.carry_limits.clone(),
+            items: HashMap::default(),
+        });
+    }
+}
+
+const SLOT_KEYS: [KeyCode; 9] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+pub fn apply_inventory_controls(
+    keyboard: Res<Input<KeyCode>>,
+    aim_state: Res<AimState>,
+    mut wheel: EventReader<MouseWheel>,
+    mut inventories: Query<&mut Inventory, With<LocalPlayer>>,
+) {
+    let scroll: f32 = wheel.read().map(|event| event.y).sum();
+    // the mouse wheel changes the zoom level of scopes instead
+    let scroll = if aim_state.scoped { 0.0 } else { scroll };
+
+    for mut inventory in inventories.iter_mut() {
+        if let Some(slot) = SLOT_KEYS.iter().position(|key| keyboard.just_pressed(*key)) {
+            inventory.select(slot);
+        } else if scroll > 0.0 {
+            inventory.select_next(-1);
+        } else if scroll < 0.0 {
+            inventory.select_next(1);
+        }
+    }
+}
+
+// holsters the current weapon, then draws the selected one
+pub fn switch_weapons(
+    time: Res<Time>,
+    weapon_assets: Res<WeaponAssets>,
+    definitions: Res<Assets<WeaponDefinition>>,
+    mut inventories: Query<(&mut Inventory, &mut Weapon)>,
+) {
+    for (mut inventory, mut weapon) in inventories.iter_mut() {
+        let Some(mut switch) = inventory.switching.take() else {
+            continue;
+        };
+
+        // A new switch starts with the holster time of the current weapon.
+        if switch.remaining <= 0.0 && !weapon.holstering {
+            switch.remaining = definitions.get(&weapon.definition).map_or(0.0, |definition| definition.holster_time);
+            weapon.holstering = true;
+            weapon.reloading = None;
+        }
+
+        switch.remaining -= time.delta_seconds();
+        if switch.remaining > 0.0 {
+            inventory.switching = Some(switch);
+            continue;
+        }
+
+        let Some(handle) = weapon_assets.find(&definitions, &inventory.slots[switch.target].weapon) else {
+            weapon.holstering = false;
+            continue;
+        };
+        let active = inventory.active;
+        inventory.slots[active].magazine = weapon.magazine;
+        inventory.active = switch.target;
+
+        weapon.magazine = inventory.slots[switch.target].magazine;
+        weapon.cooldown = definitions.get(&handle).map_or(0.0, |definition| definition.draw_time);
+        weapon.definition = handle;
+        weapon.holstering = false;
+    }
+}
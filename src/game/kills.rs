@@ -0,0 +1,237 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+
+use crate::app::AppState;
+use crate::game::game_modes::Participant;
+use crate::game::health::{apply_damage, kill_players, DamageEvent, DamageKind, Died};
+use crate::game::network::has_authority;
+use crate::game::respawn::{kill_out_of_bounds, PlayerDied};
+
+#[derive(Event, Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// A player was killed, sent in every match phase. Clients get the ones of the server through
+/// `MatchStatus::kills`.
+pub struct PlayerKilled {
+    pub victim: Participant,
+    /// `None` if nobody is to blame, e.g. for fall damage.
+    pub killer: Option<Participant>,
+    /// Everyone else who damaged the victim shortly before, see `KillSettings::assist_window`.
+    pub assists: Vec<Participant>,
+    /// `None` for deaths without damage, e.g. falling out of the level.
+    pub kind: Option<DamageKind>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KillRecord {
+    /// Counts up, so that clients can tell which kills they have seen.
+    pub id: u32,
+    pub kill: PlayerKilled,
+}
+
+/// Kills kept in `RecentKills`, enough for the kill feed.
+pub const RECENT_KILLS: usize = 5;
+
+#[derive(Resource, Reflect, Debug)]
+#[reflect(Resource)]
+pub struct KillSettings {
+    /// Seconds damage counts as an assist.
+    pub assist_window: f32,
+}
+
+impl Default for KillSettings {
+    fn default() -> Self {
+        KillSettings { assist_window: 10.0 }
+    }
+}
+
+#[derive(Resource, Debug, Default)]
+/// Who damaged which player body when, only on the side with the authority.
+pub struct DamageHistory(pub HashMap<Entity, Vec<(Participant, f32)>>);
+
+#[derive(Resource, Debug, Default)]
+/// The last kills, replicated to the clients with the `MatchStatus`.
+pub struct RecentKills {
+    next_id: u32,
+    pub records: VecDeque<KillRecord>,
+}
+
+pub struct KillsPlugin;
+
+impl Plugin for KillsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<KillSettings>()
+            .init_resource::<KillSettings>()
+            .init_resource::<DamageHistory>()
+            .init_resource::<RecentKills>()
+            .add_event::<PlayerKilled>()
+            .add_systems(OnExit(AppState::Game), reset_kills)
+            .add_systems(Update, (
+                record_damage.before(apply_damage),
+                detect_kills.after(kill_players).after(kill_out_of_bounds).after(record_damage),
+                record_kills.after(detect_kills),
+            ).run_if(has_authority).run_if(in_state(AppState::Game)));
+    }
+}
+
+pub fn reset_kills(mut history: ResMut<DamageHistory>, mut recent: ResMut<RecentKills>) {
+    history.0.clear();
+    *recent = RecentKills::default();
+}
+
+pub fn record_damage(
+    time: Res<Time>,
+    settings: Res<KillSettings>,
+    mut history: ResMut<DamageHistory>,
+    mut damage: EventReader<DamageEvent>,
+    participants: Query<&Participant>,
+) {
+    let now = time.elapsed_seconds();
+    // also forgets bodies which were despawned without dying, e.g. of disconnected clients
+    history.0.retain(|_, attackers| {
+        attackers.retain(|(_, at)| now - at <= settings.assist_window);
+        !attackers.is_empty()
+    });
+
+    for event in damage.read() {
+        if !participants.contains(event.target) {
+            continue;
+        }
+        let Some(attacker) = event.source.and_then(|source| participants.get(source).ok()).copied() else {
+            continue;
+        };
+        let attackers = history.0.entry(event.target).or_default();
+        attackers.retain(|(other, _)| *other != attacker);
+        attackers.push((attacker, now));
+    }
+}
+
+pub fn detect_kills(
+    mut history: ResMut<DamageHistory>,
+    mut died: EventReader<Died>,
+    mut player_died: EventReader<PlayerDied>,
+    participants: Query<&Participant>,
+    mut killed: EventWriter<PlayerKilled>,
+) {
+    let mut victims: HashSet<Entity> = HashSet::default();
+    let mut kill = |entity: Entity, killer: Option<Entity>, kind: Option<DamageKind>| {
+        let Ok(victim) = participants.get(entity).copied() else {
+            return;
+        };
+        // e.g. killed twice in the same frame
+        if !victims.insert(entity) {
+            return;
+        }
+        let killer = killer.and_then(|killer| participants.get(killer).ok()).copied();
+        let assists = history.0
+            .remove(&entity)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(attacker, _)| attacker)
+            .filter(|attacker| *attacker != victim && Some(*attacker) != killer)
+            .collect();
+        killed.send(PlayerKilled { victim, killer, assists, kind });
+    };
+
+    for event in died.read() {
+        kill(event.entity, event.killer, Some(event.kind));
+    }
+    // Players who died without damage, the others were already handled above.
+    for event in player_died.read() {
+        kill(event.entity, None, None);
+    }
+}
+
+pub fn record_kills(mut recent: ResMut<RecentKills>, mut killed: EventReader<PlayerKilled>) {
+    for event in killed.read() {
+        let id = recent.next_id;
+        recent.next_id += 1;
+        recent.records.push_back(KillRecord { id, kill: event.clone() });
+        while recent.records.len() > RECENT_KILLS {
+            recent.records.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::event::Events;
+
+    use super::*;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.init_resource::<DamageHistory>()
+            .add_event::<Died>()
+            .add_event::<PlayerDied>()
+            .add_event::<PlayerKilled>()
+            .add_systems(Update, detect_kills);
+        app
+    }
+
+    fn kills(app: &App) -> Vec<PlayerKilled> {
+        let events = app.world.resource::<Events<PlayerKilled>>();
+        events.get_reader().read(events).cloned().collect()
+    }
+
+    #[test]
+    fn kills_have_the_other_attackers_as_assists() {
+        let mut app = app();
+        let victim = app.world.spawn(Participant::Bot(0)).id();
+        let killer = app.world.spawn(Participant::Local).id();
+        app.world.resource_mut::<DamageHistory>().0.insert(victim, vec![
+            (Participant::Bot(1), 1.0),
+            (Participant::Bot(0), 2.0),
+            (Participant::Local, 3.0),
+        ]);
+        app.world.send_event(Died { entity: victim, killer: Some(killer), kind: DamageKind::Bullet });
+        app.update();
+
+        assert_eq!(kills(&app), vec![PlayerKilled {
+            victim: Participant::Bot(0),
+            killer: Some(Participant::Local),
+            assists: vec![Participant::Bot(1)],
+            kind: Some(DamageKind::Bullet),
+        }]);
+        assert!(app.world.resource::<DamageHistory>().0.is_empty());
+    }
+
+    #[test]
+    fn victims_die_once_per_frame() {
+        let mut app = app();
+        let victim = app.world.spawn(Participant::Client(1)).id();
+        let killer = app.world.spawn(Participant::Bot(0)).id();
+        let other = app.world.spawn(Participant::Bot(1)).id();
+        app.world.send_event(Died { entity: victim, killer: Some(killer), kind: DamageKind::Explosion });
+        app.world.send_event(Died { entity: victim, killer: Some(other), kind: DamageKind::Bullet });
+        app.world.send_event(PlayerDied { entity: victim });
+        // not a player, e.g. an enemy
+        let enemy = app.world.spawn_empty().id();
+        app.world.send_event(Died { entity: enemy, killer: Some(killer), kind: DamageKind::Bullet });
+        app.update();
+
+        assert_eq!(kills(&app), vec![PlayerKilled {
+            victim: Participant::Client(1),
+            killer: Some(Participant::Bot(0)),
+            assists: Vec::new(),
+            kind: Some(DamageKind::Explosion),
+        }]);
+    }
+
+    #[test]
+    fn deaths_without_damage_have_no_killer() {
+        let mut app = app();
+        let victim = app.world.spawn(Participant::Local).id();
+        app.world.resource_mut::<DamageHistory>().0.insert(victim, vec![(Participant::Bot(0), 1.0)]);
+        app.world.send_event(PlayerDied { entity: victim });
+        app.update();
+
+        assert_eq!(kills(&app), vec![PlayerKilled {
+            victim: Participant::Local,
+            killer: None,
+            assists: vec![Participant::Bot(0)],
+            kind: None,
+        }]);
+    }
+}
@@ -0,0 +1,562 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_rapier3d::prelude::*;
+
+use crate::app::AppState;
+use crate::game::inventory::Inventory;
+use crate::game::pickups::Interacted;
+use crate::game::player::PlayerBody;
+use crate::game::simulation::SimulationSet;
+use crate::game::world::world_ready;
+
+/// Signals sent through more entities than this in one frame are dropped, to stop endless loops.
+const MAX_SIGNALS_PER_FRAME: usize = 256;
+
+#[derive(Reflect, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignalKind {
+    /// Toggles doors and levers, presses buttons, counts counters and starts timers.
+    #[default]
+    Trigger,
+    /// Opens doors, turns levers on and starts timers.
+    Enable,
+    /// Closes doors, turns levers off, stops timers and resets counters.
+    Disable,
+    /// Keeps players from using doors, buttons and levers.
+    Lock,
+    Unlock,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+/// Send it to drive logic entities from code, e.g. from an encounter script.
+pub struct LogicSignal {
+    pub target: Entity,
+    pub kind: SignalKind,
+    /// Whoever started the chain, e.g. the player pressing a button.
+    pub activator: Option<Entity>,
+}
+
+#[derive(Component, Reflect, Default, Debug)]
+#[reflect(Component)]
+/// Names of the nodes which receive the signals of this entity, e.g. `["door_1", "alarm_relay"]`.
+pub struct LogicTargets(pub Vec<String>);
+
+#[derive(Component, Debug)]
+/// `LogicTargets` resolved to entities once the world is loaded.
+pub struct LogicLinks(pub Vec<Entity>);
+
+#[derive(Reflect, Debug, Clone, Copy, PartialEq)]
+pub enum DoorKind {
+    /// Moves by `offset` (in local space) when opening.
+    Sliding { offset: Vec3 },
+    /// Rotates around its origin, so place the origin on the hinge in Blender.
+    Hinged { axis: Vec3, angle: f32 },
+}
+
+impl Default for DoorKind {
+    fn default() -> Self {
+        DoorKind::Hinged {
+            axis: Vec3::Y,
+            angle: 90f32.to_radians(),
+        }
+    }
+}
+
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+/// Add `Interactable` as well to open it with `E`.
+pub struct Door {
+    pub kind: DoorKind,
+    /// Seconds to open or close completely.
+    pub move_time: f32,
+    /// Locked doors can't be used by players, but still react to signals.
+    pub locked: bool,
+    /// Item which has to be in the inventory to use the door, see `Pickup`.
+    pub key: Option<String>,
+    pub start_open: bool,
+}
+
+impl Default for Door {
+    fn default() -> Self {
+        Door {
+            kind: DoorKind::default(),
+            move_time: 1.0,
+            locked: false,
+            key: None,
+            start_open: false,
+        }
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct DoorState {
+    closed: Transform,
+    /// `0.0` closed, `1.0` open.
+    progress: f32,
+    pub open: bool,
+    pub locked: bool,
+}
+
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+/// Sends `Trigger` to its targets when used. Add `Interactable` to use it with `E`.
+pub struct LogicButton {
+    /// Only works a single time.
+    pub once: bool,
+    /// Seconds until it can be used again.
+    pub cooldown: f32,
+    /// Locked buttons can't be pressed by players, but still react to signals.
+    pub locked: bool,
+}
+
+impl Default for LogicButton {
+    fn default() -> Self {
+        LogicButton {
+            once: false,
+            cooldown: 1.0,
+            locked: false,
+        }
+    }
+}
+
+#[derive(Component, Reflect, Default, Debug)]
+#[reflect(Component)]
+/// Sends `Enable` or `Disable` to its targets when switched. Add `Interactable` to use it with `E`.
+pub struct Lever {
+    pub on: bool,
+    pub locked: bool,
+}
+
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+/// Sends `Trigger` to its targets after being triggered `count` times.
+pub struct Counter {
+    pub count: u32,
+    pub current: u32,
+    /// Start counting from zero again after firing.
+    pub repeat: bool,
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Counter {
+            count: 1,
+            current: 0,
+            repeat: false,
+        }
+    }
+}
+
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+/// Sends `Trigger` to its targets `delay` seconds after being started.
+pub struct LogicTimer {
+    pub delay: f32,
+    /// Start again after firing.
+    pub repeat: bool,
+    /// Start as soon as the level is loaded.
+    pub autostart: bool,
+}
+
+impl Default for LogicTimer {
+    fn default() -> Self {
+        LogicTimer {
+            delay: 1.0,
+            repeat: false,
+            autostart: false,
+        }
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct LogicTimerState {
+    timer: Option<Timer>,
+}
+
+#[derive(Component, Reflect, Default, Debug)]
+#[reflect(Component)]
+/// Forwards every signal to its targets, optionally delayed.
+pub struct Relay {
+    pub delay: f32,
+}
+
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+/// Sends `Trigger` to its targets when a player enters it. Add it to a `*_sensor` object in Blender.
+pub struct TriggerVolume {
+    pub once: bool,
+}
+
+impl Default for TriggerVolume {
+    fn default() -> Self {
+        TriggerVolume { once: true }
+    }
+}
+
+#[derive(Component, Default, Debug)]
+pub struct TriggerVolumeState {
+    inside: bool,
+    fired: bool,
+}
+
+#[derive(Component, Default, Debug)]
+pub struct LogicButtonState {
+    fired: bool,
+    /// Seconds until it can be used again.
+    cooldown: f32,
+    pub locked: bool,
+}
+
+#[derive(Resource, Default, Debug)]
+/// Signals waiting for their delay.
+pub struct LogicQueue {
+    delayed: Vec<(f32, LogicSignal)>,
+}
+
+pub struct LogicPlugin;
+
+impl Plugin for LogicPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<LogicTargets>()
+            .register_type::<Door>()
+            .register_type::<LogicButton>()
+            .register_type::<Lever>()
+            .register_type::<Counter>()
+            .register_type::<LogicTimer>()
+            .register_type::<Relay>()
+            .register_type::<TriggerVolume>()
+            .init_resource::<LogicQueue>()
+            .add_event::<LogicSignal>()
+            .add_systems(Update, logic_replace_proxies)
+            .add_systems(Update, (
+                // All nodes of the level have to exist before their names can be looked up.
+                resolve_logic_links.run_if(world_ready),
+                use_logic_entities,
+                trigger_volumes,
+                tick_logic_timers,
+                run_logic
+                    .after(resolve_logic_links)
+                    .after(use_logic_entities)
+                    .after(trigger_volumes)
+                    .after(tick_logic_timers),
+            ).run_if(in_state(AppState::Game)))
+            .add_systems(FixedUpdate, move_doors
+                .in_set(SimulationSet::Movement)
+                .run_if(in_state(AppState::Game)));
+    }
+}
+
+// adds the runtime state to the logic stand-ins
+pub fn logic_replace_proxies(
+    mut commands: Commands,
+    added_doors: Query<(Entity, &Door, &Transform), Added<Door>>,
+    added_timers: Query<Entity, Added<LogicTimer>>,
+    added_buttons: Query<(Entity, &LogicButton), Added<LogicButton>>,
+    added_volumes: Query<Entity, Added<TriggerVolume>>,
+) {
+    for (entity, door, transform) in added_doors.iter() {
+        let progress = if door.start_open { 1.0 } else { 0.0 };
+        commands.entity(entity).insert((
+            RigidBody::KinematicPositionBased,
+            door_transform(door.kind, *transform, progress),
+            DoorState {
+                closed: *transform,
+                progress,
+                open: door.start_open,
+                locked: door.locked,
+            },
+        ));
+    }
+    for entity in added_timers.iter() {
+        commands.entity(entity).insert(LogicTimerState { timer: None });
+    }
+    for (entity, button) in added_buttons.iter() {
+        commands.entity(entity).insert(LogicButtonState {
+            locked: button.locked,
+            ..default()
+        });
+    }
+    for entity in added_volumes.iter() {
+        commands.entity(entity).insert(TriggerVolumeState::default());
+    }
+}
+
+pub fn resolve_logic_links(
+    mut commands: Commands,
+    unresolved: Query<(Entity, &LogicTargets, Option<&Name>), Without<LogicLinks>>,
+    names: Query<(Entity, &Name)>,
+    timers: Query<(Entity, &LogicTimer)>,
+    mut signals: EventWriter<LogicSignal>,
+) {
+    if unresolved.is_empty() {
+        return;
+    }
+    let by_name: HashMap<&str, Entity> = names.iter().map(|(entity, name)| (name.as_str(), entity)).collect();
+    for (entity, targets, name) in unresolved.iter() {
+        let links = targets.0
+            .iter()
+            .filter_map(|target| {
+                let found = by_name.get(target.as_str()).copied();
+                if found.is_none() {
+                    warn!("logic target `{}` of {:?} not found", target, name.map_or("unnamed", |name| name.as_str()));
+                }
+                found
+            })
+            .collect();
+        commands.entity(entity).insert(LogicLinks(links));
+
+        if timers.get(entity).is_ok_and(|(_, timer)| timer.autostart) {
+            signals.send(LogicSignal {
+                target: entity,
+                kind: SignalKind::Enable,
+                activator: None,
+            });
+        }
+    }
+}
+
+// players use buttons, levers and doors with `E`
+pub fn use_logic_entities(
+    mut interacted: EventReader<Interacted>,
+    doors: Query<(&Door, &DoorState)>,
+    buttons: Query<(&LogicButton, &LogicButtonState)>,
+    levers: Query<&Lever>,
+    inventories: Query<&Inventory>,
+    mut signals: EventWriter<LogicSignal>,
+) {
+    for event in interacted.read() {
+        let usable = if let Ok((door, state)) = doors.get(event.entity) {
+            let has_key = door.key.as_ref().map_or(true, |key| {
+                inventories.get(event.interactor).is_ok_and(|inventory| inventory.has_item(key))
+            });
+            !state.locked && has_key
+        } else if let Ok((button, state)) = buttons.get(event.entity) {
+            !state.locked && state.cooldown <= 0.0 && !(button.once && state.fired)
+        } else if let Ok(lever) = levers.get(event.entity) {
+            !lever.locked
+        } else {
+            false
+        };
+
+        if usable {
+            signals.send(LogicSignal {
+                target: event.entity,
+                kind: SignalKind::Trigger,
+                activator: Some(event.interactor),
+            });
+        }
+    }
+}
+
+pub fn trigger_volumes(
+    rapier_context: Res<RapierContext>,
+    players: Query<Entity, With<PlayerBody>>,
+    mut volumes: Query<(Entity, &TriggerVolume, &mut TriggerVolumeState)>,
+    mut signals: EventWriter<LogicSignal>,
+) {
+    for (entity, volume, mut state) in volumes.iter_mut() {
+        let player_inside = players
+            .iter()
+            .find(|player| rapier_context.intersection_pair(entity, *player) == Some(true));
+
+        // only entering the volume triggers it, staying inside does not
+        if let (Some(player), false) = (player_inside, state.inside) {
+            if !(volume.once && state.fired) {
+                state.fired = true;
+                signals.send(LogicSignal {
+                    target: entity,
+                    kind: SignalKind::Trigger,
+                    activator: Some(player),
+                });
+            }
+        }
+        state.inside = player_inside.is_some();
+    }
+}
+
+pub fn tick_logic_timers(
+    time: Res<Time>,
+    mut timers: Query<(&mut LogicTimerState, Option<&LogicLinks>)>,
+    mut buttons: Query<&mut LogicButtonState>,
+    mut signals: EventWriter<LogicSignal>,
+) {
+    for mut state in buttons.iter_mut() {
+        state.cooldown = (state.cooldown - time.delta_seconds()).max(0.0);
+    }
+
+    for (mut state, links) in timers.iter_mut() {
+        let Some(timer) = state.timer.as_mut() else {
+            continue;
+        };
+        if !timer.tick(time.delta()).just_finished() {
+            continue;
+        }
+        if timer.mode() == TimerMode::Once {
+            state.timer = None;
+        }
+        for &target in links.iter().flat_map(|links| links.0.iter()) {
+            signals.send(LogicSignal {
+                target,
+                kind: SignalKind::Trigger,
+                activator: None,
+            });
+        }
+    }
+}
+
+/// Delivers all signals, following chains like trigger → relay → door within the same frame.
+pub fn run_logic(
+    time: Res<Time>,
+    mut queue: ResMut<LogicQueue>,
+    mut incoming: EventReader<LogicSignal>,
+    links: Query<&LogicLinks>,
+    mut doors: Query<&mut DoorState>,
+    mut buttons: Query<(&LogicButton, &mut LogicButtonState)>,
+    mut levers: Query<&mut Lever>,
+    mut counters: Query<&mut Counter>,
+    mut timers: Query<(&LogicTimer, &mut LogicTimerState)>,
+    relays: Query<&Relay>,
+    volumes: Query<(), With<TriggerVolume>>,
+) {
+    let mut pending: Vec<LogicSignal> = incoming.read().copied().collect();
+
+    // delayed signals whose time has come
+    let delta = time.delta_seconds();
+    queue.delayed.retain_mut(|(delay, signal)| {
+        *delay -= delta;
+        if *delay <= 0.0 {
+            pending.push(*signal);
+            return false;
+        }
+        true
+    });
+
+    let mut delivered = 0;
+    while let Some(signal) = pending.pop() {
+        delivered += 1;
+        if delivered > MAX_SIGNALS_PER_FRAME {
+            warn!("logic signal limit reached, dropping {} signals", pending.len() + 1);
+            break;
+        }
+
+        let target = signal.target;
+        let mut send = |kind: SignalKind, delay: f32, pending: &mut Vec<LogicSignal>| {
+            let Ok(links) = links.get(target) else {
+                return;
+            };
+            for &linked in links.0.iter() {
+                let signal = LogicSignal {
+                    target: linked,
+                    kind,
+                    activator: signal.activator,
+                };
+                if delay > 0.0 {
+                    queue.delayed.push((delay, signal));
+                } else {
+                    pending.push(signal);
+                }
+            }
+        };
+
+        if let Ok(mut door) = doors.get_mut(target) {
+            match signal.kind {
+                SignalKind::Trigger => door.open = !door.open,
+                SignalKind::Enable => door.open = true,
+                SignalKind::Disable => door.open = false,
+                SignalKind::Lock => door.locked = true,
+                SignalKind::Unlock => door.locked = false,
+            }
+        }
+
+        if let Ok((button, mut state)) = buttons.get_mut(target) {
+            match signal.kind {
+                SignalKind::Trigger => {
+                    state.fired = true;
+                    state.cooldown = button.cooldown;
+                    send(SignalKind::Trigger, 0.0, &mut pending);
+                }
+                SignalKind::Enable => state.fired = false,
+                SignalKind::Lock => state.locked = true,
+                SignalKind::Unlock => state.locked = false,
+                SignalKind::Disable => {}
+            }
+        }
+
+        if let Ok(mut lever) = levers.get_mut(target) {
+            let on = match signal.kind {
+                SignalKind::Trigger => Some(!lever.on),
+                SignalKind::Enable => Some(true),
+                SignalKind::Disable => Some(false),
+                SignalKind::Lock => {
+                    lever.locked = true;
+                    None
+                }
+                SignalKind::Unlock => {
+                    lever.locked = false;
+                    None
+                }
+            };
+            if let Some(on) = on.filter(|on| *on != lever.on) {
+                lever.on = on;
+                send(if on { SignalKind::Enable } else { SignalKind::Disable }, 0.0, &mut pending);
+            }
+        }
+
+        if let Ok(mut counter) = counters.get_mut(target) {
+            match signal.kind {
+                SignalKind::Trigger if counter.current < counter.count => {
+                    counter.current += 1;
+                    if counter.current == counter.count {
+                        send(SignalKind::Trigger, 0.0, &mut pending);
+                        if counter.repeat {
+                            counter.current = 0;
+                        }
+                    }
+                }
+                SignalKind::Disable => counter.current = 0,
+                _ => {}
+            }
+        }
+
+        if let Ok((timer, mut state)) = timers.get_mut(target) {
+            // running out is handled by `tick_logic_timers`
+            match (signal.kind, &state.timer) {
+                (SignalKind::Trigger | SignalKind::Enable, None) => {
+                    let mode = if timer.repeat { TimerMode::Repeating } else { TimerMode::Once };
+                    state.timer = Some(Timer::from_seconds(timer.delay, mode));
+                }
+                (SignalKind::Disable, Some(_)) => state.timer = None,
+                _ => {}
+            }
+        }
+
+        if let Ok(relay) = relays.get(target) {
+            send(signal.kind, relay.delay, &mut pending);
+        }
+
+        if volumes.contains(target) && signal.kind == SignalKind::Trigger {
+            send(SignalKind::Trigger, 0.0, &mut pending);
+        }
+    }
+}
+
+pub fn move_doors(time: Res<Time>, mut doors: Query<(&Door, &mut DoorState, &mut Transform)>) {
+    for (door, mut state, mut transform) in doors.iter_mut() {
+        let target = if state.open { 1.0 } else { 0.0 };
+        let step = time.delta_seconds() / door.move_time.max(f32::EPSILON);
+        let progress = state.progress + (target - state.progress).clamp(-step, step);
+        if progress == state.progress {
+            continue;
+        }
+        state.progress = progress;
+        *transform = door_transform(door.kind, state.closed, progress);
+    }
+}
+
+fn door_transform(kind: DoorKind, closed: Transform, progress: f32) -> Transform {
+    match kind {
+        DoorKind::Sliding { offset } => closed.with_translation(closed.translation + closed.rotation * offset * progress),
+        DoorKind::Hinged { axis, angle } => {
+            closed.with_rotation(closed.rotation * Quat::from_axis_angle(axis.normalize_or_zero(), angle * progress))
+        }
+    }
+}
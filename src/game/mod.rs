@@ -1,31 +1,90 @@
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
-use bevy_tnua::control_helpers::TnuaCrouchEnforcerPlugin;
-use bevy_tnua::prelude::*;
-use bevy_tnua_rapier3d::TnuaRapier3dPlugin;
 
-use crate::app::AppState;
-use crate::game::player::{apply_controls, apply_mouse, execute_move, spawn_player, toggle_cursor_lock};
+use crate::app::{AppState, Headless};
+use crate::game::aim::AimPlugin;
+use crate::game::bots::BotsPlugin;
+use crate::game::destructibles::DestructiblesPlugin;
+use crate::game::enemies::EnemiesPlugin;
+use crate::game::explosions::ExplosionsPlugin;
+use crate::game::game_modes::MatchPlugin;
+use crate::game::health::HealthPlugin;
+use crate::game::hud::HudPlugin;
+use crate::game::inventory::InventoryPlugin;
+use crate::game::kills::KillsPlugin;
+use crate::game::logic::LogicPlugin;
+use crate::game::movement_events::MovementEventsPlugin;
+use crate::game::movement_volumes::MovementVolumesPlugin;
+use crate::game::navigation::NavigationPlugin;
+use crate::game::pickups::PickupsPlugin;
+use crate::game::platforms::PlatformsPlugin;
+use crate::game::player::{apply_controls, apply_mouse, toggle_cursor_lock};
+use crate::game::projectiles::ProjectilesPlugin;
+use crate::game::respawn::RespawnPlugin;
+use crate::game::simulation::SimulationPlugin;
+use crate::game::view_model::ViewModelPlugin;
+use crate::game::waves::WavesPlugin;
+use crate::game::weapons::WeaponsPlugin;
 
 mod world;
 mod player;
+mod movement_volumes;
+mod movement_events;
+mod platforms;
+mod respawn;
+mod health;
+mod kills;
+mod weapons;
+mod inventory;
+mod projectiles;
+mod explosions;
+mod view_model;
+mod aim;
+mod pickups;
+mod logic;
+mod destructibles;
+mod navigation;
+mod enemies;
+mod waves;
+mod bots;
+pub mod hud;
+pub mod game_modes;
+pub mod network;
+mod simulation;
 
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
         app
-            .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
-            .add_plugins(RapierDebugRenderPlugin::default())
+            // Rapier and tnua run in fixed ticks, see `SimulationPlugin`.
+            .add_plugins(SimulationPlugin)
 
-            .add_plugins((TnuaRapier3dPlugin, TnuaControllerPlugin, TnuaCrouchEnforcerPlugin))
-            .add_systems(OnEnter(AppState::Game), (world::spawn_world, spawn_player))
+            .add_plugins((MovementVolumesPlugin, PlatformsPlugin, MovementEventsPlugin))
+            // The player is spawned by `RespawnPlugin` once the world is loaded.
+            .add_plugins((RespawnPlugin, HealthPlugin, KillsPlugin))
+            .add_plugins((WeaponsPlugin, InventoryPlugin, ProjectilesPlugin, ExplosionsPlugin))
+            .add_plugins((ViewModelPlugin, AimPlugin))
+            .add_plugins((PickupsPlugin, LogicPlugin, DestructiblesPlugin))
+            .add_plugins((NavigationPlugin, EnemiesPlugin, WavesPlugin))
+            // Bots fill `PlayerBody` like `apply_controls` does, so they move through `execute_move` too.
+            .add_plugins(BotsPlugin)
+            // Lobby and rounds of the multiplayer modes, the game modes plug into it.
+            .add_plugins(MatchPlugin)
+            .add_plugins(HudPlugin)
+            .add_systems(OnEnter(AppState::Game), world::spawn_world)
+            // The level is loaded again, so that the next game starts with a fresh one.
+            .add_systems(OnExit(AppState::Game), world::despawn_game_entities)
+            // Input is sampled every frame, `execute_move` consumes it in the next tick.
             .add_systems(Update, (
                 toggle_cursor_lock,
-                apply_controls.before(execute_move),
-                apply_mouse.before(execute_move),
-                execute_move.in_set(TnuaUserControlsSystemSet),
+                apply_controls,
+                apply_mouse,
             ).run_if(in_state(AppState::Game)));
+
+        // Needs the renderer, the dedicated server has none.
+        if !app.world.contains_resource::<Headless>() {
+            app.add_plugins(RapierDebugRenderPlugin::default());
+        }
     }
 }
-
@@ -0,0 +1,202 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use bevy_tnua::TnuaProximitySensor;
+
+use crate::app::AppState;
+use crate::game::movement_volumes::{MovementMode, MovementVolumeState};
+use crate::game::player::PlayerBody;
+use crate::game::simulation::SimulationSet;
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PlayerLanded {
+    pub entity: Entity,
+    /// Downwards speed right before touching the ground.
+    pub fall_speed: f32,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PlayerJumped {
+    pub entity: Entity,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PlayerStartedCrouch {
+    pub entity: Entity,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PlayerStoppedCrouch {
+    pub entity: Entity,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PlayerFootstep {
+    pub entity: Entity,
+    /// The entity the player is walking on.
+    pub ground: Entity,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PlayerFallDamage {
+    pub entity: Entity,
+    pub amount: f32,
+}
+
+#[derive(Resource, Reflect, Debug)]
+#[reflect(Resource)]
+pub struct MovementEventSettings {
+    /// The player counts as grounded if the ground sensor hits something closer than this.
+    pub grounded_distance: f32,
+    /// Horizontal distance between two footsteps.
+    pub stride_length: f32,
+    pub fall_damage: FallDamageCurve,
+}
+
+impl Default for MovementEventSettings {
+    fn default() -> Self {
+        MovementEventSettings {
+            grounded_distance: 0.6,
+            stride_length: 1.6,
+            fall_damage: FallDamageCurve::default(),
+        }
+    }
+}
+
+#[derive(Reflect, Debug, Clone, Copy)]
+/// Maps the impact speed to damage: nothing up to `safe_speed`,
+/// rising with `exponent` to `lethal_damage` at `lethal_speed`.
+pub struct FallDamageCurve {
+    pub safe_speed: f32,
+    pub lethal_speed: f32,
+    pub lethal_damage: f32,
+    pub exponent: f32,
+}
+
+impl Default for FallDamageCurve {
+    fn default() -> Self {
+        FallDamageCurve {
+            safe_speed: 9.0,
+            lethal_speed: 25.0,
+            lethal_damage: 100.0,
+            exponent: 2.0,
+        }
+    }
+}
+
+impl FallDamageCurve {
+    pub fn damage(&self, fall_speed: f32) -> f32 {
+        if fall_speed <= self.safe_speed {
+            return 0.0;
+        }
+        let ratio = (fall_speed - self.safe_speed) / (self.lethal_speed - self.safe_speed).max(f32::EPSILON);
+        ratio.powf(self.exponent) * self.lethal_damage
+    }
+}
+
+#[derive(Component, Default, Debug)]
+/// What the player did in the last tick, to detect changes.
+pub struct MovementTracker {
+    grounded: bool,
+    crouching: bool,
+    fall_speed: f32,
+    stride: f32,
+    last_position: Vec3,
+}
+
+pub struct MovementEventsPlugin;
+
+impl Plugin for MovementEventsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<MovementEventSettings>()
+            .init_resource::<MovementEventSettings>()
+            .add_event::<PlayerLanded>()
+            .add_event::<PlayerJumped>()
+            .add_event::<PlayerStartedCrouch>()
+            .add_event::<PlayerStoppedCrouch>()
+            .add_event::<PlayerFootstep>()
+            .add_event::<PlayerFallDamage>()
+            .add_systems(FixedUpdate, (
+                track_movement,
+                apply_fall_damage.after(track_movement),
+            ).in_set(SimulationSet::Record).run_if(in_state(AppState::Game)));
+    }
+}
+
+// derives the movement events from the tnua ground sensor after each tick
+pub fn track_movement(
+    settings: Res<MovementEventSettings>,
+    mut player_query: Query<(
+        Entity,
+        &mut MovementTracker,
+        &PlayerBody,
+        &TnuaProximitySensor,
+        &Velocity,
+        &Transform,
+        &MovementVolumeState,
+    )>,
+    mut landed: EventWriter<PlayerLanded>,
+    mut jumped: EventWriter<PlayerJumped>,
+    mut started_crouch: EventWriter<PlayerStartedCrouch>,
+    mut stopped_crouch: EventWriter<PlayerStoppedCrouch>,
+    mut footsteps: EventWriter<PlayerFootstep>,
+) {
+    for (entity, mut tracker, body, sensor, velocity, transform, volume_state) in player_query.iter_mut() {
+        let ground = sensor.output.as_ref()
+            .filter(|output| output.proximity <= settings.grounded_distance)
+            .map(|output| output.entity);
+        let grounded = ground.is_some();
+        let walking = volume_state.mode == MovementMode::Walk;
+
+        if grounded && !tracker.grounded && walking {
+            landed.send(PlayerLanded {
+                entity,
+                fall_speed: tracker.fall_speed,
+            });
+        }
+        // only jumps the controller executed, holding the button in the air doesn't count
+        if !grounded && tracker.grounded && body.jumping && velocity.linvel.y > 0.0 {
+            jumped.send(PlayerJumped { entity });
+        }
+
+        if body.crouch && !tracker.crouching {
+            started_crouch.send(PlayerStartedCrouch { entity });
+        } else if !body.crouch && tracker.crouching {
+            stopped_crouch.send(PlayerStoppedCrouch { entity });
+        }
+
+        // Remember the impact speed while airborne, the landing tick already has it absorbed.
+        tracker.fall_speed = if grounded { 0.0 } else { (-velocity.linvel.y).max(0.0) };
+
+        let moved = transform.translation - tracker.last_position;
+        match ground {
+            Some(ground) if walking => {
+                tracker.stride += Vec3::new(moved.x, 0.0, moved.z).length();
+                if tracker.stride >= settings.stride_length {
+                    tracker.stride = 0.0;
+                    footsteps.send(PlayerFootstep { entity, ground });
+                }
+            }
+            _ => tracker.stride = 0.0,
+        }
+
+        tracker.grounded = grounded;
+        tracker.crouching = body.crouch;
+        tracker.last_position = transform.translation;
+    }
+}
+
+pub fn apply_fall_damage(
+    settings: Res<MovementEventSettings>,
+    mut landed: EventReader<PlayerLanded>,
+    mut fall_damage: EventWriter<PlayerFallDamage>,
+) {
+    for event in landed.read() {
+        let amount = settings.fall_damage.damage(event.fall_speed);
+        if amount > 0.0 {
+            fall_damage.send(PlayerFallDamage {
+                entity: event.entity,
+                amount,
+            });
+        }
+    }
+}
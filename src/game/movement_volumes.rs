@@ -0,0 +1,185 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use bevy_tnua::TnuaToggle;
+
+use crate::app::AppState;
+use crate::game::network::has_authority;
+use crate::game::player::{execute_move, PlayerBody, VIEW_HEIGHT};
+use crate::game::simulation::SimulationSet;
+
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+/// Marks a (sensor) volume the player can climb in.
+/// Add it in Blender next to the `Collider` proxy of a `*_sensor` object.
+pub struct Ladder {
+    pub climb_speed: f32,
+}
+
+impl Default for Ladder {
+    fn default() -> Self {
+        Ladder { climb_speed: 2.5 }
+    }
+}
+
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+/// Marks a (sensor) volume the player swims in.
+pub struct Water {
+    pub swim_speed: f32,
+    /// Gravity scale applied to the player while inside the water.
+    pub gravity_scale: f32,
+    /// How fast the velocity approaches the desired swim velocity.
+    pub drag: f32,
+}
+
+impl Default for Water {
+    fn default() -> Self {
+        Water {
+            swim_speed: 2.0,
+            gravity_scale: 0.1,
+            drag: 4.0,
+        }
+    }
+}
+
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+/// Seconds of air left while the head is under water.
+pub struct Oxygen {
+    pub max: f32,
+    pub remaining: f32,
+    pub refill_rate: f32,
+    /// Damage per second once no air is left.
+    pub drowning_damage: f32,
+}
+
+impl Default for Oxygen {
+    fn default() -> Self {
+        Oxygen {
+            max: 15.0,
+            remaining: 15.0,
+            refill_rate: 5.0,
+            drowning_damage: 10.0,
+        }
+    }
+}
+
+impl Oxygen {
+    pub fn is_depleted(&self) -> bool {
+        self.remaining <= 0.0
+    }
+}
+
+#[derive(Reflect, Default, Debug, Clone, Copy, PartialEq)]
+pub enum MovementMode {
+    #[default]
+    Walk,
+    Climb {
+        speed: f32,
+    },
+    Swim {
+        speed: f32,
+        gravity_scale: f32,
+        drag: f32,
+    },
+}
+
+#[derive(Component, Reflect, Default, Debug)]
+#[reflect(Component)]
+/// The movement mode the player is currently in, decided by the volumes it touches.
+pub struct MovementVolumeState {
+    pub mode: MovementMode,
+    /// Seconds left until a ladder can be grabbed again after jumping off.
+    pub ladder_cooldown: f32,
+}
+
+pub struct MovementVolumesPlugin;
+
+impl Plugin for MovementVolumesPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Ladder>()
+            .register_type::<Water>()
+            .register_type::<Oxygen>()
+            .register_type::<MovementVolumeState>()
+            .add_systems(FixedUpdate, (
+                detect_movement_volumes.before(execute_move),
+                // Drowning damage is dealt by the side with the authority, so the oxygen is tracked there.
+                update_oxygen.after(execute_move).run_if(has_authority),
+            ).in_set(SimulationSet::Movement).run_if(in_state(AppState::Game)));
+    }
+}
+
+pub fn detect_movement_volumes(
+    time: Res<Time>,
+    rapier_context: Res<RapierContext>,
+    ladders: Query<(Entity, &Ladder)>,
+    waters: Query<(Entity, &Water)>,
+    mut player_query: Query<(Entity, &mut MovementVolumeState, &mut TnuaToggle, &mut GravityScale), With<PlayerBody>>,
+) {
+    for (player, mut state, mut toggle, mut gravity) in player_query.iter_mut() {
+        state.ladder_cooldown = (state.ladder_cooldown - time.delta_seconds()).max(0.0);
+
+        let touches = |volume: Entity| rapier_context.intersection_pair(volume, player) == Some(true);
+
+        // Water wins over ladders, so a ladder leading out of a pool still lets the player swim.
+        let mode = if let Some((_, water)) = waters.iter().find(|(entity, _)| touches(*entity)) {
+            MovementMode::Swim {
+                speed: water.swim_speed,
+                gravity_scale: water.gravity_scale,
+                drag: water.drag,
+            }
+        } else if let Some((_, ladder)) = ladders.iter()
+            .filter(|_| state.ladder_cooldown <= 0.0)
+            .find(|(entity, _)| touches(*entity)) {
+            MovementMode::Climb { speed: ladder.climb_speed }
+        } else {
+            MovementMode::Walk
+        };
+
+        if state.mode == mode {
+            continue;
+        }
+        state.mode = mode;
+
+        // Tnua only keeps sensing while we drive the velocity manually.
+        match mode {
+            MovementMode::Walk => {
+                *toggle = TnuaToggle::Enabled;
+                gravity.0 = 1.0;
+            }
+            MovementMode::Climb { .. } => {
+                *toggle = TnuaToggle::SenseOnly;
+                gravity.0 = 0.0;
+            }
+            MovementMode::Swim { gravity_scale, .. } => {
+                *toggle = TnuaToggle::SenseOnly;
+                gravity.0 = gravity_scale;
+            }
+        }
+    }
+}
+
+pub fn update_oxygen(
+    time: Res<Time>,
+    rapier_context: Res<RapierContext>,
+    waters: Query<(), With<Water>>,
+    mut player_query: Query<(&Transform, &mut Oxygen), With<PlayerBody>>,
+) {
+    let is_water = |entity: Entity| waters.contains(entity);
+    for (transform, mut oxygen) in player_query.iter_mut() {
+        // The head is under water if the eyes are inside any water volume. `GlobalTransform` is
+        // only updated at the end of the frame, the players are not parented.
+        let eyes = transform.translation + Vec3::Y * VIEW_HEIGHT;
+        let mut head_under_water = false;
+        rapier_context.intersections_with_point(eyes, QueryFilter::new().predicate(&is_water), |_| {
+            head_under_water = true;
+            false
+        });
+
+        if head_under_water {
+            oxygen.remaining = (oxygen.remaining - time.delta_seconds()).max(0.0);
+        } else {
+            oxygen.remaining = (oxygen.remaining + oxygen.refill_rate * time.delta_seconds()).min(oxygen.max);
+        }
+    }
+}
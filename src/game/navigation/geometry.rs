@@ -0,0 +1,64 @@
+use bevy::prelude::*;
+use bevy_rapier3d::parry::math::{Isometry, Point, Real};
+use bevy_rapier3d::parry::shape::TypedShape;
+use bevy_rapier3d::prelude::*;
+
+pub type Triangle = [Vec3; 3];
+
+/// Triangles of a collider in world space, counter-clockwise seen from outside.
+pub fn collider_triangles(collider: &Collider, transform: &GlobalTransform) -> Vec<Triangle> {
+    let (scale, rotation, translation) = transform.to_scale_rotation_translation();
+    // Rapier bakes the entity scale into the shape, but only once it has seen the collider.
+    let scale = scale / collider.scale();
+    let to_world = |local: Vec3| translation + rotation * (local * scale);
+
+    let mut triangles = Vec::new();
+    shape_triangles(collider.raw.as_typed_shape(), &Isometry::identity(), &to_world, &mut triangles);
+    triangles
+}
+
+fn shape_triangles(
+    shape: TypedShape,
+    isometry: &Isometry<Real>,
+    to_world: &dyn Fn(Vec3) -> Vec3,
+    triangles: &mut Vec<Triangle>,
+) {
+    let (vertices, indices) = match shape {
+        TypedShape::TriMesh(trimesh) => (trimesh.vertices().to_vec(), trimesh.indices().to_vec()),
+        TypedShape::Cuboid(cuboid) => cuboid.to_trimesh(),
+        TypedShape::Ball(ball) => ball.to_trimesh(8, 8),
+        TypedShape::Capsule(capsule) => capsule.to_trimesh(8, 8),
+        TypedShape::Cylinder(cylinder) => cylinder.to_trimesh(8),
+        TypedShape::Cone(cone) => cone.to_trimesh(8),
+        TypedShape::ConvexPolyhedron(polyhedron) => polyhedron.to_trimesh(),
+        TypedShape::HeightField(heightfield) => heightfield.to_trimesh(),
+        TypedShape::Compound(compound) => {
+            for (sub_isometry, sub_shape) in compound.shapes() {
+                shape_triangles(sub_shape.as_typed_shape(), &(isometry * sub_isometry), to_world, triangles);
+            }
+            return;
+        }
+        // nothing in the levels uses the other shapes yet
+        _ => return,
+    };
+
+    let to_vec3 = |point: &Point<Real>| {
+        let point = isometry * point;
+        to_world(Vec3::new(point.x, point.y, point.z))
+    };
+    triangles.extend(indices.iter().map(|[a, b, c]| {
+        [
+            to_vec3(&vertices[*a as usize]),
+            to_vec3(&vertices[*b as usize]),
+            to_vec3(&vertices[*c as usize]),
+        ]
+    }));
+}
+
+/// Minimum and maximum corner of the triangles.
+pub fn bounds(triangles: &[Triangle]) -> (Vec3, Vec3) {
+    triangles.iter().flatten().fold(
+        (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+        |(min, max), vertex| (min.min(*vertex), max.max(*vertex)),
+    )
+}
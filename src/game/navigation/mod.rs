@@ -0,0 +1,440 @@
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+use bevy::utils::{HashMap, HashSet};
+use bevy_rapier3d::prelude::*;
+use futures_lite::future;
+
+use crate::app::{AppState, Headless};
+use crate::game::navigation::geometry::{bounds, collider_triangles, Triangle};
+use crate::game::navigation::navmesh::{build_navmesh, build_tile, tile_border, tiles_in, NavMesh, NavTile};
+
+pub mod geometry;
+pub mod navmesh;
+pub mod pathfinding;
+
+#[derive(Resource, Reflect, Clone, Debug)]
+#[reflect(Resource)]
+/// Changing it regenerates the whole navmesh.
+pub struct NavMeshSettings {
+    /// Horizontal size of a voxel.
+    pub cell_size: f32,
+    pub agent_radius: f32,
+    pub agent_height: f32,
+    /// Highest step an agent can walk up.
+    pub max_step: f32,
+    /// Steepest walkable slope in degrees.
+    pub max_slope: f32,
+    /// Cells per tile side, tiles are regenerated on their own.
+    pub tile_size: u32,
+    pub debug_draw: bool,
+}
+
+impl Default for NavMeshSettings {
+    fn default() -> Self {
+        NavMeshSettings {
+            cell_size: 0.2,
+            agent_radius: 0.3,
+            agent_height: 1.0,
+            max_step: 0.3,
+            max_slope: 45.0,
+            tile_size: 64,
+            debug_draw: false,
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+/// The current navmesh, replaced as a whole whenever a tile was regenerated.
+pub struct Navigation {
+    pub navmesh: Arc<NavMesh>,
+    /// Increased with every new navmesh, paths computed on an older one may be outdated.
+    pub version: u32,
+    tiles: HashMap<IVec2, NavTile>,
+}
+
+impl Navigation {
+    /// Runs the path query on the async compute pool.
+    pub fn find_path_async(&self, start: Vec3, goal: Vec3) -> Task<Option<Vec<Vec3>>> {
+        let navmesh = self.navmesh.clone();
+        AsyncComputeTaskPool::get().spawn(async move { navmesh.find_path(start, goal) })
+    }
+}
+
+#[derive(Resource, Default)]
+struct NavMeshBuilder {
+    /// World triangles of every static collider with their bounds.
+    sources: HashMap<Entity, (Arc<Vec<Triangle>>, Vec3, Vec3)>,
+    dirty: HashSet<IVec2>,
+    tasks: HashMap<IVec2, Task<NavTile>>,
+}
+
+impl NavMeshBuilder {
+    fn mark_dirty(&mut self, min: Vec3, max: Vec3, settings: &NavMeshSettings) {
+        self.dirty.extend(tiles_in(min, max, settings));
+    }
+}
+
+#[derive(Component, Debug, Clone, Copy)]
+/// Asks for a path, replaced by a `NavPath` once it is computed.
+pub struct PathRequest {
+    pub start: Vec3,
+    pub goal: Vec3,
+}
+
+#[derive(Component)]
+pub struct PathTask(Task<Option<Vec<Vec3>>>);
+
+#[derive(Component, Debug, Clone, Default)]
+pub struct NavPath {
+    pub points: Vec<Vec3>,
+    /// Index of the next point to walk to.
+    pub current: usize,
+}
+
+impl NavPath {
+    pub fn next_point(&self) -> Option<Vec3> {
+        self.points.get(self.current).copied()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.current >= self.points.len()
+    }
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PathComputed {
+    pub entity: Entity,
+    pub found: bool,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct NavMeshUpdated {
+    pub version: u32,
+}
+
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<NavMeshSettings>()
+            .init_resource::<NavMeshSettings>()
+            .init_resource::<Navigation>()
+            .init_resource::<NavMeshBuilder>()
+            .add_event::<PathComputed>()
+            .add_event::<NavMeshUpdated>()
+            .add_systems(OnExit(AppState::Game), reset_navigation)
+            .add_systems(Update, (
+                track_static_colliders,
+                rebuild_dirty_tiles.after(track_static_colliders),
+                poll_tile_tasks.after(rebuild_dirty_tiles),
+                request_paths,
+                poll_path_tasks.after(request_paths),
+            ).run_if(in_state(AppState::Game)));
+
+        // Gizmos need the renderer.
+        if !app.world.contains_resource::<Headless>() {
+            app.add_systems(Update, draw_navmesh.run_if(in_state(AppState::Game)));
+        }
+    }
+}
+
+pub fn reset_navigation(mut commands: Commands) {
+    // running tasks are cancelled by dropping them
+    commands.insert_resource(Navigation::default());
+    commands.insert_resource(NavMeshBuilder::default());
+}
+
+// Anything which is (part of) a moving body is not part of the navmesh.
+fn is_static(entity: Entity, bodies: &Query<&RigidBody>, parents: &Query<&Parent>) -> bool {
+    std::iter::once(entity)
+        .chain(parents.iter_ancestors(entity))
+        .find_map(|entity| bodies.get(entity).ok())
+        .map_or(true, |body| *body == RigidBody::Fixed)
+}
+
+fn track_static_colliders(
+    settings: Res<NavMeshSettings>,
+    mut builder: ResMut<NavMeshBuilder>,
+    navigation: Res<Navigation>,
+    changed_colliders: Query<
+        (Entity, &Collider, &GlobalTransform),
+        (Without<Sensor>, Or<(Changed<Collider>, Changed<GlobalTransform>)>),
+    >,
+    mut removed_colliders: RemovedComponents<Collider>,
+    bodies: Query<&RigidBody>,
+    parents: Query<&Parent>,
+) {
+    let builder = builder.as_mut();
+
+    if settings.is_changed() && !settings.is_added() {
+        builder.dirty.extend(navigation.tiles.keys());
+        for (_, min, max) in builder.sources.values() {
+            builder.dirty.extend(tiles_in(*min, *max, &settings));
+        }
+    }
+
+    for entity in removed_colliders.read() {
+        if let Some((_, min, max)) = builder.sources.remove(&entity) {
+            builder.mark_dirty(min, max, &settings);
+        }
+    }
+
+    for (entity, collider, transform) in changed_colliders.iter() {
+        // the old area has to be regenerated as well if it moved
+        if let Some((_, min, max)) = builder.sources.remove(&entity) {
+            builder.mark_dirty(min, max, &settings);
+        }
+        if !is_static(entity, &bodies, &parents) {
+            continue;
+        }
+
+        let triangles = collider_triangles(collider, transform);
+        if triangles.is_empty() {
+            continue;
+        }
+        let (min, max) = bounds(&triangles);
+        builder.mark_dirty(min, max, &settings);
+        builder.sources.insert(entity, (Arc::new(triangles), min, max));
+    }
+}
+
+fn rebuild_dirty_tiles(settings: Res<NavMeshSettings>, mut builder: ResMut<NavMeshBuilder>) {
+    let builder = builder.as_mut();
+    let pool = AsyncComputeTaskPool::get();
+    let size = settings.tile_size as f32 * settings.cell_size;
+    let border = tile_border(&settings) as f32 * settings.cell_size;
+
+    // A tile which is still building stays dirty and is started again once that finished.
+    let ready: Vec<IVec2> = builder.dirty.iter().filter(|coord| !builder.tasks.contains_key(*coord)).copied().collect();
+    for coord in ready {
+        builder.dirty.remove(&coord);
+
+        let min = coord.as_vec2() * size - border;
+        let max = min + size + border * 2.0;
+        let sources: Vec<Arc<Vec<Triangle>>> = builder
+            .sources
+            .values()
+            .filter(|(_, source_min, source_max)| {
+                source_max.x >= min.x && source_min.x <= max.x && source_max.z >= min.y && source_min.z <= max.y
+            })
+            .map(|(triangles, ..)| triangles.clone())
+            .collect();
+
+        let settings = settings.clone();
+        let task = pool.spawn(async move {
+            let triangles: Vec<Triangle> = sources.iter().flat_map(|triangles| triangles.iter().copied()).collect();
+            build_tile(coord, &triangles, &settings)
+        });
+        builder.tasks.insert(coord, task);
+    }
+}
+
+fn poll_tile_tasks(
+    settings: Res<NavMeshSettings>,
+    mut builder: ResMut<NavMeshBuilder>,
+    mut navigation: ResMut<Navigation>,
+    mut updated: EventWriter<NavMeshUpdated>,
+) {
+    let mut changed = false;
+    builder.tasks.retain(|coord, task| {
+        let Some(tile) = future::block_on(future::poll_once(task)) else {
+            return true;
+        };
+        if tile.is_empty() {
+            changed |= navigation.tiles.remove(coord).is_some();
+        } else if navigation.tiles.get(coord) != Some(&tile) {
+            navigation.tiles.insert(*coord, tile);
+            changed = true;
+        }
+        false
+    });
+
+    if changed {
+        // Only the tiles are generated in the background, linking them is cheap.
+        let navmesh = build_navmesh(navigation.tiles.values(), &settings);
+        navigation.navmesh = Arc::new(navmesh);
+        navigation.version += 1;
+        updated.send(NavMeshUpdated { version: navigation.version });
+    }
+}
+
+pub fn request_paths(
+    mut commands: Commands,
+    navigation: Res<Navigation>,
+    requests: Query<(Entity, &PathRequest)>,
+) {
+    for (entity, request) in requests.iter() {
+        // replaces a running request
+        commands.entity(entity)
+            .remove::<PathRequest>()
+            .insert(PathTask(navigation.find_path_async(request.start, request.goal)));
+    }
+}
+
+pub fn poll_path_tasks(
+    mut commands: Commands,
+    mut tasks: Query<(Entity, &mut PathTask)>,
+    mut computed: EventWriter<PathComputed>,
+) {
+    for (entity, mut task) in tasks.iter_mut() {
+        let Some(path) = future::block_on(future::poll_once(&mut task.0)) else {
+            continue;
+        };
+        let found = path.is_some();
+        let mut cmd = commands.entity(entity);
+        cmd.remove::<PathTask>();
+        match path {
+            Some(points) => cmd.insert(NavPath { points, current: 0 }),
+            None => cmd.remove::<NavPath>(),
+        };
+        computed.send(PathComputed { entity, found });
+    }
+}
+
+fn draw_navmesh(settings: Res<NavMeshSettings>, navigation: Res<Navigation>, mut gizmos: Gizmos) {
+    if !settings.debug_draw {
+        return;
+    }
+    let offset = Vec3::Y * 0.05;
+    for polygon in navigation.navmesh.polygons.iter() {
+        let min = polygon.min_cell.as_vec2() * settings.cell_size;
+        let max = (polygon.min_cell + polygon.size).as_vec2() * settings.cell_size;
+        let y = polygon.center.y;
+        let corners = [
+            Vec3::new(min.x, y, min.y),
+            Vec3::new(max.x, y, min.y),
+            Vec3::new(max.x, y, max.y),
+            Vec3::new(min.x, y, max.y),
+        ];
+        gizmos.linestrip(corners.iter().chain(corners.first()).map(|corner| *corner + offset), Color::CYAN);
+        for link in polygon.links.iter() {
+            gizmos.line(link.portal[0] + offset, link.portal[1] + offset, Color::YELLOW);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate(triangles: &[Triangle], settings: &NavMeshSettings) -> HashMap<IVec2, NavTile> {
+        let (min, max) = bounds(triangles);
+        tiles_in(min, max, settings)
+            .map(|coord| (coord, build_tile(coord, triangles, settings)))
+            .filter(|(_, tile)| !tile.is_empty())
+            .collect()
+    }
+
+    fn length(path: &[Vec3]) -> f32 {
+        path.windows(2).map(|pair| pair[0].distance(pair[1])).sum()
+    }
+
+    // The static colliders `physics_replace_proxies` creates from the world, without running the app.
+    fn world_triangles() -> Vec<Triangle> {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/models/World.glb");
+        let (document, buffers, _) = gltf::import(path).expect("World.glb can be loaded");
+
+        let mut triangles = Vec::new();
+        for node in document.nodes() {
+            let extras = node.extras().as_ref().map_or("", |extras| extras.get());
+            if !extras.contains("\"Collider\"") || extras.contains("\"Dynamic\"") {
+                continue;
+            }
+            let Some(mesh) = node.mesh() else {
+                continue;
+            };
+
+            let (translation, rotation, scale) = node.transform().decomposed();
+            let transform = GlobalTransform::from(Transform {
+                translation: Vec3::from(translation),
+                rotation: Quat::from_array(rotation),
+                scale: Vec3::from(scale),
+            });
+            for primitive in mesh.primitives() {
+                let reader = primitive.reader(|buffer| Some(buffers[buffer.index()].0.as_slice()));
+                let vertices: Vec<Vec3> = reader.read_positions().unwrap().map(Vec3::from).collect();
+                let indices: Vec<u32> = reader.read_indices().unwrap().into_u32().collect();
+                let indices = indices.chunks(3).map(|chunk| [chunk[0], chunk[1], chunk[2]]).collect();
+                triangles.extend(collider_triangles(&Collider::trimesh(vertices, indices), &transform));
+            }
+        }
+        triangles
+    }
+
+    fn arena() -> (Vec<Triangle>, Vec<Triangle>) {
+        let floor = collider_triangles(
+            &Collider::cuboid(10.0, 0.5, 10.0),
+            &GlobalTransform::from_translation(Vec3::new(0.0, -0.5, 0.0)),
+        );
+        let wall = collider_triangles(
+            &Collider::cuboid(0.5, 1.0, 8.0),
+            &GlobalTransform::from_translation(Vec3::new(0.0, 1.0, -2.0)),
+        );
+        (floor, wall)
+    }
+
+    #[test]
+    fn finds_path_in_world() {
+        let settings = NavMeshSettings {
+            cell_size: 0.5,
+            ..default()
+        };
+        let triangles = world_triangles();
+        assert!(!triangles.is_empty());
+
+        let tiles = generate(&triangles, &settings);
+        let navmesh = build_navmesh(tiles.values(), &settings);
+        assert!(!navmesh.is_empty());
+
+        let start = Vec3::new(-10.0, 0.0, -10.0);
+        let goal = Vec3::new(30.0, 0.0, 25.0);
+        let path = navmesh.find_path(start, goal).expect("the floor is walkable");
+        assert!(path.iter().all(|point| point.y.abs() < 0.01));
+        // nothing static is in the way
+        assert_eq!(path.len(), 2);
+        assert!((length(&path) - start.distance(goal)).abs() < 0.1);
+
+        // the dynamic cube is not part of the navmesh, so there is nothing above the floor
+        assert!(navmesh.find_polygon(Vec3::new(1.18, 4.9, 6.07)).is_none());
+    }
+
+    #[test]
+    fn walks_around_walls() {
+        let settings = NavMeshSettings::default();
+        let (floor, wall) = arena();
+        let triangles = [floor, wall].concat();
+        let navmesh = build_navmesh(generate(&triangles, &settings).values(), &settings);
+
+        let path = navmesh.find_path(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(5.0, 0.0, 0.0)).unwrap();
+        assert!(path.len() > 2);
+        assert!(length(&path) > 12.0);
+        // it keeps the agent radius to the wall end
+        assert!(path.iter().any(|point| point.z > 6.0 + settings.agent_radius - 0.01));
+
+        // the wall top is too high to step on
+        assert!(navmesh.find_path(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(0.0, 2.0, -2.0)).is_none());
+    }
+
+    #[test]
+    fn regenerates_changed_tiles() {
+        let settings = NavMeshSettings::default();
+        let (floor, wall) = arena();
+        let mut tiles = generate(&[floor.clone(), wall.clone()].concat(), &settings);
+        let unchanged = tiles.clone();
+
+        // the wall is removed, only the tiles it touched are built again
+        let (min, max) = bounds(&wall);
+        let dirty: Vec<IVec2> = tiles_in(min, max, &settings).collect();
+        for coord in dirty.iter() {
+            tiles.insert(*coord, build_tile(*coord, &floor, &settings));
+        }
+        assert!(unchanged.iter().filter(|(coord, _)| !dirty.contains(coord)).all(|(coord, tile)| tiles[coord] == *tile));
+
+        let navmesh = build_navmesh(tiles.values(), &settings);
+        let path = navmesh.find_path(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(5.0, 0.0, 0.0)).unwrap();
+        assert_eq!(path.len(), 2);
+        assert!((length(&path) - 10.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,443 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use crate::game::navigation::geometry::Triangle;
+use crate::game::navigation::NavMeshSettings;
+
+/// Polygons are merged from at most this many cells per side, to keep path costs meaningful.
+const MAX_POLYGON_CELLS: usize = 32;
+/// How far (in cells) `find_polygon` looks around a point which is not directly on the navmesh.
+const SEARCH_CELLS: i32 = 8;
+const DIRECTIONS: [IVec2; 4] = [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y];
+
+#[derive(Debug, Clone, Copy)]
+struct SolidSpan {
+    min: f32,
+    max: f32,
+    walkable: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Free space above a walkable surface.
+pub struct OpenSpan {
+    pub floor: f32,
+    pub ceiling: f32,
+}
+
+impl OpenSpan {
+    fn connects(&self, other: &OpenSpan, settings: &NavMeshSettings) -> bool {
+        (self.floor - other.floor).abs() <= settings.max_step
+            && self.ceiling.min(other.ceiling) - self.floor.max(other.floor) >= settings.agent_height
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Walkable spans of a square part of the level, generated independently of the other tiles.
+pub struct NavTile {
+    pub coord: IVec2,
+    /// Spans per cell, row by row.
+    pub columns: Vec<Vec<OpenSpan>>,
+}
+
+impl NavTile {
+    pub fn is_empty(&self) -> bool {
+        self.columns.iter().all(|column| column.is_empty())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NavLink {
+    pub polygon: usize,
+    /// Shared edge with the linked polygon.
+    pub portal: [Vec3; 2],
+}
+
+#[derive(Debug, Clone)]
+/// A rectangle of walkable cells.
+pub struct NavPolygon {
+    pub min_cell: IVec2,
+    pub size: IVec2,
+    pub center: Vec3,
+    pub links: Vec<NavLink>,
+}
+
+#[derive(Debug, Default)]
+pub struct NavMesh {
+    pub cell_size: f32,
+    pub max_step: f32,
+    pub agent_height: f32,
+    pub polygons: Vec<NavPolygon>,
+    /// Floors per cell and the polygon they belong to.
+    columns: HashMap<IVec2, Vec<(f32, usize)>>,
+}
+
+/// World space bounds (x and z) of a tile.
+pub fn tile_bounds(coord: IVec2, settings: &NavMeshSettings) -> (Vec2, Vec2) {
+    let size = settings.tile_size as f32 * settings.cell_size;
+    let min = coord.as_vec2() * size;
+    (min, min + size)
+}
+
+/// Cells around a tile which are voxelized as well, so that the erosion at the tile border is correct.
+pub fn tile_border(settings: &NavMeshSettings) -> i32 {
+    (settings.agent_radius / settings.cell_size).ceil() as i32 + 1
+}
+
+/// All tiles touched by the area from `min` to `max`.
+pub fn tiles_in(min: Vec3, max: Vec3, settings: &NavMeshSettings) -> impl Iterator<Item = IVec2> {
+    let size = settings.tile_size as f32 * settings.cell_size;
+    let border = tile_border(settings) as f32 * settings.cell_size;
+    let min = ((Vec2::new(min.x, min.z) - border) / size).floor().as_ivec2();
+    let max = ((Vec2::new(max.x, max.z) + border) / size).floor().as_ivec2();
+    (min.y..=max.y).flat_map(move |z| (min.x..=max.x).map(move |x| IVec2::new(x, z)))
+}
+
+// Sutherland–Hodgman against a single axis aligned plane
+fn clip(polygon: &[Vec3], axis: usize, value: f32, keep_above: bool) -> Vec<Vec3> {
+    let side = |point: &Vec3| if keep_above { point[axis] - value } else { value - point[axis] };
+    let mut clipped = Vec::with_capacity(polygon.len() + 2);
+    for (index, a) in polygon.iter().enumerate() {
+        let b = polygon[(index + 1) % polygon.len()];
+        let (side_a, side_b) = (side(a), side(&b));
+        if side_a >= 0.0 {
+            clipped.push(*a);
+        }
+        if (side_a >= 0.0) != (side_b >= 0.0) {
+            clipped.push(*a + (b - *a) * (side_a / (side_a - side_b)));
+        }
+    }
+    clipped
+}
+
+fn add_span(column: &mut Vec<SolidSpan>, mut span: SolidSpan, settings: &NavMeshSettings) {
+    let mut index = 0;
+    while index < column.len() {
+        let other = column[index];
+        if other.min > span.max || other.max < span.min {
+            index += 1;
+            continue;
+        }
+        // Overlapping spans are merged, the top surface decides if it's walkable.
+        if (other.max - span.max).abs() <= settings.max_step {
+            span.walkable |= other.walkable;
+        } else if other.max > span.max {
+            span.walkable = other.walkable;
+        }
+        span.min = span.min.min(other.min);
+        span.max = span.max.max(other.max);
+        column.remove(index);
+    }
+    let index = column.iter().position(|other| other.min > span.min).unwrap_or(column.len());
+    column.insert(index, span);
+}
+
+/// Voxelizes the triangles around a tile and returns the spans an agent can stand on.
+pub fn build_tile(coord: IVec2, triangles: &[Triangle], settings: &NavMeshSettings) -> NavTile {
+    let cell_size = settings.cell_size;
+    let tile_size = settings.tile_size as i32;
+    let border = tile_border(settings);
+    let size = tile_size + border * 2;
+    let origin = coord * tile_size - IVec2::splat(border);
+    let min_walkable_y = settings.max_slope.to_radians().cos();
+
+    // 1. rasterize the triangles into solid spans
+    let mut solid: Vec<Vec<SolidSpan>> = vec![Vec::new(); (size * size) as usize];
+    for triangle in triangles {
+        let normal = (triangle[1] - triangle[0]).cross(triangle[2] - triangle[0]).normalize_or_zero();
+        let walkable = normal.y >= min_walkable_y;
+
+        let (min, max) = triangle.iter().fold((Vec3::MAX, Vec3::MIN), |(min, max), v| (min.min(*v), max.max(*v)));
+        let min_cell = (Vec2::new(min.x, min.z) / cell_size).floor().as_ivec2() - origin;
+        let max_cell = (Vec2::new(max.x, max.z) / cell_size).floor().as_ivec2() - origin;
+        if max_cell.x < 0 || max_cell.y < 0 || min_cell.x >= size || min_cell.y >= size {
+            continue;
+        }
+        let min_cell = min_cell.max(IVec2::ZERO);
+        let max_cell = max_cell.min(IVec2::splat(size - 1));
+
+        for z in min_cell.y..=max_cell.y {
+            let z0 = (origin.y + z) as f32 * cell_size;
+            let row = clip(&clip(triangle, 2, z0, true), 2, z0 + cell_size, false);
+            if row.len() < 3 {
+                continue;
+            }
+            for x in min_cell.x..=max_cell.x {
+                let x0 = (origin.x + x) as f32 * cell_size;
+                let cell = clip(&clip(&row, 0, x0, true), 0, x0 + cell_size, false);
+                if cell.len() < 3 {
+                    continue;
+                }
+                let (span_min, span_max) = cell.iter().fold((f32::MAX, f32::MIN), |(min, max), v| (min.min(v.y), max.max(v.y)));
+                add_span(&mut solid[(z * size + x) as usize], SolidSpan {
+                    min: span_min,
+                    max: span_max,
+                    walkable,
+                }, settings);
+            }
+        }
+    }
+
+    // 2. the free space above walkable spans, if an agent fits into it
+    let mut starts = Vec::with_capacity(solid.len() + 1);
+    let mut spans = Vec::new();
+    for column in solid.iter() {
+        starts.push(spans.len());
+        for (index, span) in column.iter().enumerate() {
+            let ceiling = column.get(index + 1).map_or(f32::MAX, |next| next.min);
+            if span.walkable && ceiling - span.max >= settings.agent_height {
+                spans.push(OpenSpan { floor: span.max, ceiling });
+            }
+        }
+    }
+    starts.push(spans.len());
+
+    let column_of = |cell: IVec2| (cell.y * size + cell.x) as usize;
+    let neighbour = |cell: IVec2, span: &OpenSpan, direction: IVec2| -> Option<usize> {
+        let next = cell + direction;
+        if next.x < 0 || next.y < 0 || next.x >= size || next.y >= size {
+            return None;
+        }
+        let column = column_of(next);
+        (starts[column]..starts[column + 1]).find(|other| span.connects(&spans[*other], settings))
+    };
+
+    // 3. erode the walkable area by the agent radius, starting at every edge or ledge
+    let mut distance = vec![u32::MAX; spans.len()];
+    let mut queue = VecDeque::new();
+    for z in 0..size {
+        for x in 0..size {
+            let cell = IVec2::new(x, z);
+            let column = column_of(cell);
+            for index in starts[column]..starts[column + 1] {
+                if DIRECTIONS.iter().any(|direction| neighbour(cell, &spans[index], *direction).is_none()) {
+                    distance[index] = 0;
+                    queue.push_back((cell, index));
+                }
+            }
+        }
+    }
+    while let Some((cell, index)) = queue.pop_front() {
+        for direction in DIRECTIONS {
+            let Some(next) = neighbour(cell, &spans[index], direction) else {
+                continue;
+            };
+            if distance[next] > distance[index] + 1 {
+                distance[next] = distance[index] + 1;
+                queue.push_back((cell + direction, next));
+            }
+        }
+    }
+
+    let radius = (settings.agent_radius / cell_size).ceil() as u32;
+    let mut columns = Vec::with_capacity((tile_size * tile_size) as usize);
+    for z in border..border + tile_size {
+        for x in border..border + tile_size {
+            let column = column_of(IVec2::new(x, z));
+            columns.push(
+                (starts[column]..starts[column + 1])
+                    .filter(|index| distance[*index] >= radius.max(1))
+                    .map(|index| spans[index])
+                    .collect(),
+            );
+        }
+    }
+
+    NavTile { coord, columns }
+}
+
+/// Merges the spans of all tiles into linked polygons.
+pub fn build_navmesh<'a>(tiles: impl IntoIterator<Item = &'a NavTile>, settings: &NavMeshSettings) -> NavMesh {
+    let tile_size = settings.tile_size as i32;
+    let cell_size = settings.cell_size;
+
+    let mut cells: HashMap<IVec2, Vec<OpenSpan>> = HashMap::new();
+    for tile in tiles {
+        for (index, column) in tile.columns.iter().enumerate() {
+            if column.is_empty() {
+                continue;
+            }
+            let local = IVec2::new(index as i32 % tile_size, index as i32 / tile_size);
+            cells.insert(tile.coord * tile_size + local, column.clone());
+        }
+    }
+
+    // Stable span ids, row by row.
+    let mut sorted_cells: Vec<IVec2> = cells.keys().copied().collect();
+    sorted_cells.sort_by_key(|cell| (cell.y, cell.x));
+    let mut starts: HashMap<IVec2, usize> = HashMap::new();
+    let mut spans: Vec<(IVec2, OpenSpan)> = Vec::new();
+    for cell in sorted_cells {
+        starts.insert(cell, spans.len());
+        spans.extend(cells[&cell].iter().map(|span| (cell, *span)));
+    }
+
+    let neighbour = |id: usize, direction: IVec2| -> Option<usize> {
+        let (cell, span) = spans[id];
+        let next = cell + direction;
+        let start = *starts.get(&next)?;
+        let count = cells[&next].len();
+        (start..start + count)
+            .filter(|other| span.connects(&spans[*other].1, settings))
+            .min_by(|a, b| {
+                let a = (spans[*a].1.floor - span.floor).abs();
+                let b = (spans[*b].1.floor - span.floor).abs();
+                a.total_cmp(&b)
+            })
+    };
+    let linked = |from: usize, to: usize, direction: IVec2| {
+        neighbour(from, direction) == Some(to) && neighbour(to, -direction) == Some(from)
+    };
+
+    // Greedily grow rectangles, first along x and then row by row along z.
+    let mut polygon_of: Vec<Option<usize>> = vec![None; spans.len()];
+    let mut polygons = Vec::new();
+    for id in 0..spans.len() {
+        if polygon_of[id].is_some() {
+            continue;
+        }
+
+        let mut row = vec![id];
+        while row.len() < MAX_POLYGON_CELLS {
+            let last = row[row.len() - 1];
+            match neighbour(last, IVec2::X) {
+                Some(next) if polygon_of[next].is_none() && linked(last, next, IVec2::X) => row.push(next),
+                _ => break,
+            }
+        }
+
+        let mut rows = vec![row];
+        while rows.len() < MAX_POLYGON_CELLS {
+            let previous = &rows[rows.len() - 1];
+            let next_row: Option<Vec<usize>> = previous
+                .iter()
+                .map(|id| {
+                    neighbour(*id, IVec2::Y).filter(|next| polygon_of[*next].is_none() && linked(*id, *next, IVec2::Y))
+                })
+                .collect();
+            match next_row {
+                Some(next_row) if next_row.windows(2).all(|pair| linked(pair[0], pair[1], IVec2::X)) => rows.push(next_row),
+                _ => break,
+            }
+        }
+
+        let polygon = polygons.len();
+        let mut floor_sum = 0.0;
+        for id in rows.iter().flatten() {
+            polygon_of[*id] = Some(polygon);
+            floor_sum += spans[*id].1.floor;
+        }
+        let min_cell = spans[id].0;
+        let size = IVec2::new(rows[0].len() as i32, rows.len() as i32);
+        let center = (min_cell.as_vec2() + size.as_vec2() * 0.5) * cell_size;
+        polygons.push(NavPolygon {
+            min_cell,
+            size,
+            center: Vec3::new(center.x, floor_sum / (size.x * size.y) as f32, center.y),
+            links: Vec::new(),
+        });
+    }
+
+    // Portals are the shared edges between neighbouring polygons.
+    let mut portals: HashMap<(usize, usize, IVec2), [Vec3; 2]> = HashMap::new();
+    for (id, (cell, span)) in spans.iter().enumerate() {
+        let polygon = polygon_of[id].unwrap_or_default();
+        for direction in DIRECTIONS {
+            let Some(other) = neighbour(id, direction) else {
+                continue;
+            };
+            let other_polygon = polygon_of[other].unwrap_or_default();
+            if other_polygon == polygon {
+                continue;
+            }
+
+            let y = (span.floor + spans[other].1.floor) * 0.5;
+            let min = cell.as_vec2() * cell_size;
+            let max = min + cell_size;
+            // the edge between both cells, from its lower to its higher coordinate
+            let (edge, axis) = match direction {
+                IVec2 { x: 1, .. } => ([Vec3::new(max.x, y, min.y), Vec3::new(max.x, y, max.y)], 2),
+                IVec2 { x: -1, .. } => ([Vec3::new(min.x, y, min.y), Vec3::new(min.x, y, max.y)], 2),
+                IVec2 { y: 1, .. } => ([Vec3::new(min.x, y, max.y), Vec3::new(max.x, y, max.y)], 0),
+                _ => ([Vec3::new(min.x, y, min.y), Vec3::new(max.x, y, min.y)], 0),
+            };
+            portals
+                .entry((polygon, other_polygon, direction))
+                .and_modify(|portal| {
+                    if edge[0][axis] < portal[0][axis] {
+                        portal[0] = edge[0];
+                    }
+                    if edge[1][axis] > portal[1][axis] {
+                        portal[1] = edge[1];
+                    }
+                })
+                .or_insert(edge);
+        }
+    }
+    let mut portals: Vec<_> = portals.into_iter().collect();
+    portals.sort_by_key(|((polygon, other, direction), _)| (*polygon, *other, direction.x, direction.y));
+    for ((polygon, other, _), portal) in portals {
+        polygons[polygon].links.push(NavLink {
+            polygon: other,
+            portal,
+        });
+    }
+
+    let mut columns: HashMap<IVec2, Vec<(f32, usize)>> = HashMap::new();
+    for (id, (cell, span)) in spans.iter().enumerate() {
+        columns.entry(*cell).or_default().push((span.floor, polygon_of[id].unwrap_or_default()));
+    }
+
+    NavMesh {
+        cell_size,
+        max_step: settings.max_step,
+        agent_height: settings.agent_height,
+        polygons,
+        columns,
+    }
+}
+
+impl NavMesh {
+    pub fn is_empty(&self) -> bool {
+        self.polygons.is_empty()
+    }
+
+    /// The polygon below (or close to) a point and the closest point on it.
+    pub fn find_polygon(&self, point: Vec3) -> Option<(usize, Vec3)> {
+        let cell = (Vec2::new(point.x, point.z) / self.cell_size).floor().as_ivec2();
+
+        for radius in 0..=SEARCH_CELLS {
+            let mut best: Option<(f32, usize, Vec3)> = None;
+            for z in -radius..=radius {
+                for x in -radius..=radius {
+                    if x.abs() != radius && z.abs() != radius {
+                        continue;
+                    }
+                    let cell = cell + IVec2::new(x, z);
+                    let Some(floors) = self.columns.get(&cell) else {
+                        continue;
+                    };
+                    let min = cell.as_vec2() * self.cell_size;
+                    let closest = Vec2::new(point.x, point.z).clamp(min, min + self.cell_size);
+                    for (floor, polygon) in floors {
+                        // The point has to stand on the floor, not be below it or far above.
+                        let height = point.y - floor;
+                        if height < -self.max_step || height > self.agent_height {
+                            continue;
+                        }
+                        let snapped = Vec3::new(closest.x, *floor, closest.y);
+                        let distance = snapped.distance_squared(point);
+                        if best.map_or(true, |(best_distance, ..)| distance < best_distance) {
+                            best = Some((distance, *polygon, snapped));
+                        }
+                    }
+                }
+            }
+            if let Some((_, polygon, snapped)) = best {
+                return Some((polygon, snapped));
+            }
+        }
+        None
+    }
+}
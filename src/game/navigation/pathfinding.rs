@@ -0,0 +1,157 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use crate::game::navigation::navmesh::NavMesh;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct OpenPolygon {
+    cost: f32,
+    polygon: usize,
+}
+
+impl Eq for OpenPolygon {}
+
+impl Ord for OpenPolygon {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // reversed, the heap has to return the cheapest first
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+impl PartialOrd for OpenPolygon {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// only x and z matter for the funnel
+fn cross(a: Vec3, b: Vec3) -> f32 {
+    a.x * b.z - a.z * b.x
+}
+
+impl NavMesh {
+    /// Shortest path from `start` to `goal`, including both (snapped onto the navmesh).
+    pub fn find_path(&self, start: Vec3, goal: Vec3) -> Option<Vec<Vec3>> {
+        let (start_polygon, start) = self.find_polygon(start)?;
+        let (goal_polygon, goal) = self.find_polygon(goal)?;
+        let portals = self.find_corridor(start_polygon, start, goal_polygon, goal)?;
+        Some(funnel(start, goal, &portals))
+    }
+
+    /// A* over the polygons, returns the portals to pass as (left, right).
+    fn find_corridor(&self, start_polygon: usize, start: Vec3, goal_polygon: usize, goal: Vec3) -> Option<Vec<[Vec3; 2]>> {
+        let mut costs: HashMap<usize, f32> = HashMap::new();
+        let mut entries: HashMap<usize, Vec3> = HashMap::new();
+        let mut came_from: HashMap<usize, (usize, usize)> = HashMap::new();
+        let mut open = BinaryHeap::new();
+
+        costs.insert(start_polygon, 0.0);
+        entries.insert(start_polygon, start);
+        open.push(OpenPolygon {
+            cost: start.distance(goal),
+            polygon: start_polygon,
+        });
+
+        while let Some(OpenPolygon { cost, polygon }) = open.pop() {
+            if polygon == goal_polygon {
+                break;
+            }
+            let entry = entries[&polygon];
+            // outdated heap entry
+            if cost > costs[&polygon] + entry.distance(goal) {
+                continue;
+            }
+
+            for (index, link) in self.polygons[polygon].links.iter().enumerate() {
+                let next_entry = (link.portal[0] + link.portal[1]) * 0.5;
+                let next_cost = costs[&polygon] + entry.distance(next_entry);
+                if costs.get(&link.polygon).is_some_and(|known| *known <= next_cost) {
+                    continue;
+                }
+                costs.insert(link.polygon, next_cost);
+                entries.insert(link.polygon, next_entry);
+                came_from.insert(link.polygon, (polygon, index));
+                open.push(OpenPolygon {
+                    cost: next_cost + next_entry.distance(goal),
+                    polygon: link.polygon,
+                });
+            }
+        }
+
+        if !costs.contains_key(&goal_polygon) {
+            return None;
+        }
+
+        let mut portals = Vec::new();
+        let mut polygon = goal_polygon;
+        while let Some((previous, index)) = came_from.get(&polygon) {
+            let [a, b] = self.polygons[*previous].links[*index].portal;
+            let direction = self.polygons[polygon].center - self.polygons[*previous].center;
+            let middle = (a + b) * 0.5;
+            portals.push(if cross(direction, a - middle) > 0.0 { [a, b] } else { [b, a] });
+            polygon = *previous;
+        }
+        portals.reverse();
+        Some(portals)
+    }
+}
+
+/// Simple stupid funnel algorithm, pulls the path tight through the portals.
+fn funnel(start: Vec3, goal: Vec3, portals: &[[Vec3; 2]]) -> Vec<Vec3> {
+    let mut portals = portals.to_vec();
+    portals.push([goal, goal]);
+
+    let mut path = vec![start];
+    let mut apex = start;
+    let mut left = start;
+    let mut right = start;
+    let (mut apex_index, mut left_index, mut right_index) = (0, 0, 0);
+
+    let mut index = 0;
+    while index < portals.len() {
+        let [portal_left, portal_right] = portals[index];
+
+        // tighten the right side
+        if cross(right - apex, portal_right - apex) >= 0.0 {
+            if apex == right || cross(left - apex, portal_right - apex) < 0.0 {
+                right = portal_right;
+                right_index = index;
+            } else {
+                // the right side crosses the left one, the left point becomes a corner
+                apex = left;
+                apex_index = left_index;
+                path.push(apex);
+                right = apex;
+                right_index = apex_index;
+                index = apex_index + 1;
+                continue;
+            }
+        }
+
+        // tighten the left side
+        if cross(left - apex, portal_left - apex) <= 0.0 {
+            if apex == left || cross(right - apex, portal_left - apex) > 0.0 {
+                left = portal_left;
+                left_index = index;
+            } else {
+                apex = right;
+                apex_index = right_index;
+                path.push(apex);
+                left = apex;
+                left_index = apex_index;
+                index = apex_index + 1;
+                continue;
+            }
+        }
+
+        index += 1;
+    }
+
+    if path.last() != Some(&goal) {
+        path.push(goal);
+    }
+    path
+}
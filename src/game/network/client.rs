@@ -0,0 +1,480 @@
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_rapier3d::prelude::*;
+
+use crate::app::AppState;
+use crate::game::game_modes::{LocalReady, MatchPhase, MatchStatus};
+use crate::game::health::Health;
+use crate::game::kills::PlayerKilled;
+use crate::game::network::lag_compensation::HitboxesRewound;
+use crate::game::network::prediction::{InputHistory, Prediction, PredictionPlugin};
+use crate::game::network::protocol::{
+    ClientMessage, NetId, PlayerInput, PlayerState, ServerMessage, Snapshot, PROTOCOL_VERSION,
+};
+use crate::game::network::transport::NetSocket;
+use crate::game::network::{NetworkMode, NetworkSettings};
+use crate::game::player::{build_player, execute_move, LocalPlayer, PlayerBody, PlayerCamera};
+use crate::game::simulation::{SimulationSet, SimulationTick};
+use crate::game::weapons::{apply_weapon_controls, Weapon};
+use crate::game::world::GameEntity;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionState {
+    /// Seconds until the next hello is sent.
+    Connecting(f32),
+    Connected { client: u32 },
+}
+
+#[derive(Resource, Debug)]
+pub struct NetClient {
+    pub socket: NetSocket,
+    pub server: SocketAddr,
+    pub state: ConnectionState,
+    /// Seconds since the last message of the server.
+    pub silence: f32,
+    /// The newest snapshot which was not applied yet.
+    pub snapshot: Option<Snapshot>,
+    /// Tick of the newest applied snapshot.
+    pub last_tick: u64,
+    /// The newest match status which was not applied yet, with its server tick.
+    pub status: Option<(u64, MatchStatus)>,
+    /// Tick of the newest applied match status.
+    pub last_status_tick: u64,
+    /// Server ticks per second.
+    pub tick_rate: f64,
+    /// The server tick remote players are shown at, a bit behind the newest snapshot.
+    pub render_tick: Option<f64>,
+    /// Replicated players on this machine, including the local one.
+    pub players: HashMap<NetId, Entity>,
+    /// Button presses since the last sent input, they may happen between two ticks.
+    trigger_pressed: bool,
+    reload_requested: bool,
+}
+
+impl NetClient {
+    pub fn client_id(&self) -> Option<u32> {
+        match self.state {
+            ConnectionState::Connected { client } => Some(client),
+            ConnectionState::Connecting(_) => None,
+        }
+    }
+}
+
+#[derive(Component, Debug)]
+/// A player controlled by another machine. It only shows where the server says it is.
+pub struct RemotePlayer;
+
+#[derive(Component, Debug, Default)]
+/// Received states of a remote player, to interpolate between them.
+pub struct SnapshotBuffer {
+    /// Server tick, translation and rotation, oldest first.
+    pub states: VecDeque<(u64, Vec3, Quat)>,
+}
+
+impl SnapshotBuffer {
+    /// Interpolated state at the given tick, the oldest or newest state outside of the buffered range.
+    pub fn sample(&self, tick: f64) -> Option<(Vec3, Quat)> {
+        let (_, first_translation, first_rotation) = *self.states.front()?;
+        let mut result = (first_translation, first_rotation);
+        for ((from_tick, from_translation, from_rotation), (to_tick, to_translation, to_rotation)) in
+            self.states.iter().zip(self.states.iter().skip(1))
+        {
+            if tick < *from_tick as f64 {
+                break;
+            }
+            let alpha = ((tick - *from_tick as f64) / (*to_tick - *from_tick) as f64).min(1.0) as f32;
+            result = (from_translation.lerp(*to_translation, alpha), from_rotation.slerp(*to_rotation, alpha));
+        }
+        Some(result)
+    }
+}
+
+pub struct ClientPlugin;
+
+impl Plugin for ClientPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(PredictionPlugin)
+            .add_systems(OnEnter(AppState::Game), connect)
+            .add_systems(OnExit(AppState::Game), disconnect)
+            .add_systems(PreUpdate, (
+                advance_client_socket,
+                receive_server_messages.after(advance_client_socket),
+            ).run_if(in_state(AppState::Game)).run_if(resource_exists::<NetClient>()))
+            .add_systems(Update, (
+                send_hello,
+                send_ready.run_if(resource_exists::<LocalReady>()),
+                apply_snapshot,
+                apply_match_status,
+                interpolate_remote_players.after(apply_snapshot),
+                latch_buttons.after(apply_weapon_controls),
+            ).run_if(in_state(AppState::Game)).run_if(resource_exists::<NetClient>()))
+            .add_systems(FixedUpdate, send_input
+                .in_set(SimulationSet::Movement)
+                .before(execute_move)
+                .run_if(in_state(AppState::Game))
+                .run_if(resource_exists::<NetClient>()));
+    }
+}
+
+pub fn connect(
+    mut commands: Commands,
+    mode: Res<NetworkMode>,
+    settings: Res<NetworkSettings>,
+    mut app_state_next_state: ResMut<NextState<AppState>>,
+) {
+    let NetworkMode::Client { server } = *mode else {
+        return;
+    };
+    let bind = if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    match NetSocket::bind(bind) {
+        Ok(socket) => {
+            info!("connecting to {}", server);
+            commands.insert_resource(NetClient {
+                socket: socket.with_conditioner(settings.conditioner),
+                server,
+                state: ConnectionState::Connecting(0.0),
+                silence: 0.0,
+                snapshot: None,
+                last_tick: 0,
+                status: None,
+                last_status_tick: 0,
+                tick_rate: 64.0,
+                render_tick: None,
+                players: HashMap::default(),
+                trigger_pressed: false,
+                reload_requested: false,
+            });
+        }
+        Err(error) => {
+            error!("could not open a socket: {}", error);
+            app_state_next_state.set(AppState::MainMenu);
+        }
+    }
+}
+
+pub fn disconnect(mut commands: Commands, client: Option<Res<NetClient>>) {
+    let Some(client) = client else {
+        return;
+    };
+    client.socket.send(client.server, &ClientMessage::Disconnect);
+    for entity in client.players.values() {
+        if let Some(entity) = commands.get_entity(*entity) {
+            entity.despawn_recursive();
+        }
+    }
+    commands.remove_resource::<NetClient>();
+}
+
+pub fn advance_client_socket(time: Res<Time<Real>>, mut client: ResMut<NetClient>) {
+    client.socket.advance(time.delta());
+}
+
+pub fn receive_server_messages(
+    mut client: ResMut<NetClient>,
+    mut prediction: ResMut<Prediction>,
+    mut rewound: EventWriter<HitboxesRewound>,
+    mut app_state_next_state: ResMut<NextState<AppState>>,
+) {
+    let client = client.as_mut();
+    for (address, message) in client.socket.receive::<ServerMessage>() {
+        if address != client.server {
+            continue;
+        }
+        client.silence = 0.0;
+
+        match message {
+            ServerMessage::Welcome { client: id, tick_rate } => {
+                if client.client_id().is_none() {
+                    info!("connected as client {}, the server runs {} ticks per second", id, tick_rate);
+                }
+                client.state = ConnectionState::Connected { client: id };
+                client.tick_rate = tick_rate;
+            }
+            ServerMessage::Refused { reason } => {
+                error!("the server refused the connection: {}", reason);
+                app_state_next_state.set(AppState::MainMenu);
+            }
+            ServerMessage::Snapshot(snapshot) => {
+                // UDP doesn't keep the order, only the newest snapshot counts
+                if snapshot.tick <= client.last_tick
+                    || client.snapshot.as_ref().is_some_and(|pending| snapshot.tick <= pending.tick)
+                {
+                    continue;
+                }
+                // The prediction compares it with the next tick, before anything else moves.
+                let own_state = snapshot.players
+                    .iter()
+                    .find(|player| player.owner.is_some() && player.owner == client.client_id());
+                if let Some(state) = own_state {
+                    prediction.authoritative = Some((snapshot.ack, *state));
+                }
+                client.snapshot = Some(snapshot);
+            }
+            ServerMessage::Status { tick, status } => {
+                if tick <= client.last_status_tick
+                    || client.status.as_ref().is_some_and(|(pending, _)| tick <= *pending)
+                {
+                    continue;
+                }
+                client.status = Some((tick, status));
+            }
+            ServerMessage::HitboxesRewound { tick, hitboxes } => {
+                rewound.send(HitboxesRewound {
+                    tick,
+                    colliders: hitboxes.iter().map(|(shape, transform)| (shape.collider(), *transform)).collect(),
+                });
+            }
+            ServerMessage::Ping(sequence) => {
+                client.socket.send(client.server, &ClientMessage::Pong(sequence));
+            }
+            ServerMessage::Disconnect => {
+                warn!("the server closed the connection");
+                app_state_next_state.set(AppState::MainMenu);
+            }
+        }
+    }
+}
+
+pub fn send_hello(
+    time: Res<Time>,
+    settings: Res<NetworkSettings>,
+    mut client: ResMut<NetClient>,
+    mut app_state_next_state: ResMut<NextState<AppState>>,
+) {
+    client.silence += time.delta_seconds();
+    if client.silence >= settings.timeout {
+        warn!("lost the connection to {}", client.server);
+        app_state_next_state.set(AppState::MainMenu);
+        return;
+    }
+
+    let ConnectionState::Connecting(remaining) = client.state else {
+        return;
+    };
+    let remaining = remaining - time.delta_seconds();
+    if remaining > 0.0 {
+        client.state = ConnectionState::Connecting(remaining);
+        return;
+    }
+    client.state = ConnectionState::Connecting(settings.hello_interval);
+    client.socket.send(client.server, &ClientMessage::Hello { version: PROTOCOL_VERSION });
+}
+
+pub fn send_ready(
+    time: Res<Time>,
+    settings: Res<NetworkSettings>,
+    client: Res<NetClient>,
+    status: Res<MatchStatus>,
+    local_ready: Res<LocalReady>,
+    mut resend: Local<f32>,
+) {
+    if status.phase != MatchPhase::Lobby || client.client_id().is_none() {
+        return;
+    }
+    *resend -= time.delta_seconds();
+    if *resend > 0.0 && !local_ready.is_changed() {
+        return;
+    }
+    *resend = settings.hello_interval;
+    client.socket.send(client.server, &ClientMessage::Ready(local_ready.0));
+}
+
+pub fn apply_snapshot(
+    mut commands: Commands,
+    mut client: ResMut<NetClient>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut placeholder: Local<Option<(Handle<Mesh>, Handle<StandardMaterial>)>>,
+    mut players: Query<(&mut Health, Option<&mut SnapshotBuffer>)>,
+) {
+    let client = client.as_mut();
+    let Some(client_id) = client.client_id() else {
+        return;
+    };
+    let Some(snapshot) = client.snapshot.take() else {
+        return;
+    };
+    client.last_tick = snapshot.tick;
+
+    // Players missing in the snapshot died or left.
+    client.players.retain(|id, entity| {
+        let exists = snapshot.players.iter().any(|player| player.id == *id);
+        if !exists {
+            commands.entity(*entity).despawn_recursive();
+        }
+        exists
+    });
+
+    for state in snapshot.players.iter() {
+        let Some(entity) = client.players.get(&state.id).copied() else {
+            let transform = Transform::from_translation(state.translation).with_rotation(state.rotation);
+            let entity = if state.owner == Some(client_id) {
+                build_player(&mut commands, transform)
+            } else {
+                let (mesh, material) = placeholder.get_or_insert_with(|| (
+                    meshes.add(Mesh::from(shape::Capsule {
+                        radius: 0.25,
+                        depth: 0.6,
+                        ..default()
+                    })),
+                    materials.add(Color::rgb(0.15, 0.35, 0.7).into()),
+                )).clone();
+                let entity = build_remote_player(&mut commands, transform, mesh, material);
+                let mut buffer = SnapshotBuffer::default();
+                buffer.states.push_back((snapshot.tick, state.translation, state.rotation));
+                commands.entity(entity).insert(buffer);
+                entity
+            };
+            commands.entity(entity).insert((state.id, Health {
+                current: state.health,
+                max: state.max_health,
+            }));
+            client.players.insert(state.id, entity);
+            continue;
+        };
+
+        // The position of the local player is handled by the prediction.
+        let Ok((mut health, buffer)) = players.get_mut(entity) else {
+            continue;
+        };
+        apply_health(&mut health, state);
+        if let Some(mut buffer) = buffer {
+            buffer.states.push_back((snapshot.tick, state.translation, state.rotation));
+            // a second of states is plenty for any interpolation delay
+            while buffer.states.len() as f64 > client.tick_rate.max(2.0) {
+                buffer.states.pop_front();
+            }
+        }
+    }
+}
+
+/// Shows remote players a few ticks in the past, in-between the two snapshots around that time.
+pub fn apply_match_status(
+    mut client: ResMut<NetClient>,
+    status: Option<ResMut<MatchStatus>>,
+    mut killed: EventWriter<PlayerKilled>,
+) {
+    let Some(mut status) = status else {
+        return;
+    };
+    let Some((tick, received)) = client.status.take() else {
+        return;
+    };
+    let first_status = client.last_status_tick == 0;
+    client.last_status_tick = tick;
+
+    // Kills of the server show up here, the ones from before joining are skipped.
+    let seen = status.kills.last().map(|record| record.id);
+    if !first_status {
+        for record in received.kills.iter().filter(|record| seen.map_or(true, |seen| record.id > seen)) {
+            killed.send(record.kill.clone());
+        }
+    }
+    status.set_if_neq(received);
+}
+
+pub fn interpolate_remote_players(
+    time: Res<Time>,
+    settings: Res<NetworkSettings>,
+    mut client: ResMut<NetClient>,
+    mut remote_players: Query<(&mut Transform, &SnapshotBuffer), With<RemotePlayer>>,
+) {
+    if client.last_tick == 0 {
+        return;
+    }
+    let target = client.last_tick as f64 - settings.interpolation_delay;
+    let advanced = client.render_tick.map(|tick| tick + time.delta_seconds_f64() * client.tick_rate);
+    let render_tick = match advanced {
+        // Drift slowly towards the target, so that the remote players don't jump.
+        Some(tick) if (target - tick).abs() < client.tick_rate => tick + (target - tick) * 0.1,
+        // Way off, e.g. after the first snapshot or a long freeze.
+        _ => target,
+    };
+    client.render_tick = Some(render_tick);
+
+    for (mut transform, buffer) in remote_players.iter_mut() {
+        if let Some((translation, rotation)) = buffer.sample(render_tick) {
+            transform.translation = translation;
+            transform.rotation = rotation;
+        }
+    }
+}
+
+fn apply_health(health: &mut Health, state: &PlayerState) {
+    health.current = state.health;
+    health.max = state.max_health;
+}
+
+pub fn build_remote_player(
+    commands: &mut Commands,
+    transform: Transform,
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+) -> Entity {
+    commands.spawn((
+        Name::new("RemotePlayer"),
+        RemotePlayer,
+        GameEntity,
+        SpatialBundle::from_transform(transform),
+        // Moved by the snapshots, but still something to shoot at.
+        RigidBody::KinematicPositionBased,
+        Collider::capsule_y(0.3, 0.25),
+    )).with_children(|parent| {
+        parent.spawn(PbrBundle {
+            mesh,
+            material,
+            transform: Transform::from_xyz(0.0, 0.2, 0.0),
+            ..default()
+        });
+    }).id()
+}
+
+pub fn latch_buttons(mut client: ResMut<NetClient>, weapons: Query<&Weapon, With<LocalPlayer>>) {
+    for weapon in weapons.iter() {
+        client.trigger_pressed |= weapon.trigger_pressed;
+        client.reload_requested |= weapon.reload_requested;
+    }
+}
+
+pub fn send_input(
+    tick: Res<SimulationTick>,
+    settings: Res<NetworkSettings>,
+    mut client: ResMut<NetClient>,
+    mut history: ResMut<InputHistory>,
+    cameras: Query<&Transform, With<PlayerCamera>>,
+    players: Query<(&PlayerBody, Option<&Weapon>), With<LocalPlayer>>,
+) {
+    if client.client_id().is_none() {
+        return;
+    }
+    let Ok((body, weapon)) = players.get_single() else {
+        return;
+    };
+    let (_, pitch, _) = cameras
+        .get_single()
+        .map(|camera| camera.rotation.to_euler(EulerRot::YXZ))
+        .unwrap_or_default();
+
+    let input = PlayerInput {
+        tick: tick.0,
+        rotation: body.desired_rotation,
+        pitch,
+        direction: body.desired_direction,
+        jump: body.jump || body.jump_buffered > 0.0,
+        crouch: body.crouch,
+        trigger_held: weapon.is_some_and(|weapon| weapon.trigger_held),
+        trigger_pressed: client.trigger_pressed,
+        reload_requested: client.reload_requested,
+        view_tick: client.render_tick.unwrap_or(client.last_tick as f64),
+    };
+    client.trigger_pressed = false;
+    client.reload_requested = false;
+
+    // `execute_move` predicts the outcome of this input in the same tick.
+    history.push(input);
+    let inputs = history.recent(settings.input_redundancy.max(1));
+    client.socket.send(client.server, &ClientMessage::Input(inputs));
+}
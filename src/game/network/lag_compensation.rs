@@ -0,0 +1,322 @@
+use std::collections::VecDeque;
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::app::{AppState, Headless};
+use crate::game::network::server::NetServer;
+use crate::game::player::PlayerBody;
+use crate::game::simulation::{SimulationSet, SimulationSettings, SimulationTick};
+use crate::game::world::GameEntity;
+
+#[derive(Resource, Reflect, Debug)]
+#[reflect(Resource)]
+pub struct LagCompensationSettings {
+    /// Seconds a shot can reach into the past at most. Clients with a higher latency have to lead their targets.
+    pub max_rewind: f32,
+    /// Shows the rewound hitboxes of every compensated shot in the rapier debug render. The
+    /// server sends them to its clients, as it doesn't render anything itself.
+    pub debug_draw: bool,
+    /// Seconds the rewound hitboxes stay visible.
+    pub debug_duration: f32,
+}
+
+impl Default for LagCompensationSettings {
+    fn default() -> Self {
+        LagCompensationSettings {
+            max_rewind: 0.25,
+            debug_draw: false,
+            debug_duration: 2.0,
+        }
+    }
+}
+
+#[derive(Component, Debug, Default, Clone, Copy, Deref, DerefMut)]
+/// The server tick a client saw its targets at when it sent its last input.
+/// Only shooters with it get their shots rewound.
+pub struct ViewTick(pub f64);
+
+#[derive(Debug, Clone)]
+pub struct HitboxFrame {
+    pub tick: u64,
+    /// Global transforms of the entity's colliders, including the one of the entity itself.
+    pub colliders: Vec<(Entity, Transform)>,
+}
+
+#[derive(Component, Debug, Default)]
+/// Where the colliders of a player were in the last ticks, recorded after every physics step.
+pub struct HitboxHistory {
+    pub frames: VecDeque<HitboxFrame>,
+}
+
+impl HitboxHistory {
+    /// The collider transforms at a possibly fractional tick, interpolated between the recorded ticks.
+    pub fn at(&self, tick: f64) -> Option<Vec<(Entity, Transform)>> {
+        let oldest = self.frames.front()?;
+        if tick <= oldest.tick as f64 {
+            return Some(oldest.colliders.clone());
+        }
+
+        for (from, to) in self.frames.iter().zip(self.frames.iter().skip(1)) {
+            if tick > to.tick as f64 {
+                continue;
+            }
+            let alpha = ((tick - from.tick as f64) / (to.tick - from.tick) as f64) as f32;
+            let colliders = from.colliders
+                .iter()
+                .map(|(entity, from_transform)| {
+                    let transform = to.colliders
+                        .iter()
+                        .find(|(other, _)| other == entity)
+                        .map_or(*from_transform, |(_, to_transform)| Transform {
+                            translation: from_transform.translation.lerp(to_transform.translation, alpha),
+                            rotation: from_transform.rotation.slerp(to_transform.rotation, alpha),
+                            scale: from_transform.scale,
+                        });
+                    (*entity, transform)
+                })
+                .collect();
+            return Some(colliders);
+        }
+        self.frames.back().map(|newest| newest.colliders.clone())
+    }
+}
+
+#[derive(Event, Debug, Clone)]
+/// Sent for every shot checked against rewound hitboxes.
+pub struct HitboxesRewound {
+    pub tick: f64,
+    pub colliders: Vec<(Collider, Transform)>,
+}
+
+#[derive(Component, Debug)]
+/// A copy of a rewound collider for the debug render. It doesn't interact with anything.
+pub struct RewoundHitbox {
+    pub remaining: f32,
+}
+
+/// Rewinds the hitboxes of all players to the time the shooter saw them.
+#[derive(SystemParam)]
+pub struct LagCompensation<'w, 's> {
+    settings: Res<'w, LagCompensationSettings>,
+    simulation: Res<'w, SimulationSettings>,
+    tick: Res<'w, SimulationTick>,
+    histories: Query<'w, 's, (Entity, &'static HitboxHistory)>,
+    view_ticks: Query<'w, 's, &'static ViewTick>,
+    colliders: Query<'w, 's, (&'static Collider, Option<&'static CollisionGroups>, Has<Sensor>)>,
+    parents: Query<'w, 's, &'static Parent>,
+    rewound: EventWriter<'w, HitboxesRewound>,
+}
+
+impl<'w, 's> LagCompensation<'w, 's> {
+    /// The tick the shooter saw, but not further back than `max_rewind`.
+    pub fn view_tick(&self, shooter: Entity) -> Option<f64> {
+        let view_tick = self.view_ticks.get(shooter).ok()?;
+        let now = self.tick.0 as f64;
+        let oldest = now - self.settings.max_rewind as f64 * self.simulation.tick_rate;
+        Some(view_tick.0.clamp(oldest, now))
+    }
+
+    /// Collider transforms of a compensated entity at the given tick.
+    pub fn rewind(&self, entity: Entity, tick: f64) -> Option<Vec<(Entity, Transform)>> {
+        self.histories.get(entity).ok()?.1.at(tick)
+    }
+
+    fn is_compensated(&self, collider: Entity) -> bool {
+        std::iter::once(collider)
+            .chain(self.parents.iter_ancestors(collider))
+            .any(|entity| self.histories.contains(entity))
+    }
+
+    /// Like `RapierContext::cast_ray_and_get_normal`, but compensated players are checked at the
+    /// tick the shooter saw them. Shooters without a `ViewTick` use the current positions.
+    pub fn cast_ray(
+        &mut self,
+        rapier_context: &RapierContext,
+        shooter: Entity,
+        origin: Vec3,
+        direction: Vec3,
+        max_toi: f32,
+        filter: QueryFilter,
+    ) -> Option<(Entity, RayIntersection)> {
+        let Some(tick) = self.view_tick(shooter) else {
+            return rapier_context.cast_ray_and_get_normal(origin, direction, max_toi, true, filter);
+        };
+
+        // Everything else, e.g. walls, is where it is now.
+        let caller_predicate = filter.predicate;
+        let predicate = |entity: Entity| {
+            !self.is_compensated(entity) && caller_predicate.map_or(true, |predicate| predicate(entity))
+        };
+        let mut closest = rapier_context.cast_ray_and_get_normal(
+            origin,
+            direction,
+            max_toi,
+            true,
+            filter.predicate(&predicate),
+        );
+
+        let mut rewound = Vec::new();
+        for (entity, history) in self.histories.iter() {
+            if entity == shooter {
+                continue;
+            }
+            let Some(colliders) = history.at(tick) else {
+                continue;
+            };
+            for (collider_entity, transform) in colliders {
+                let Ok((collider, groups, sensor)) = self.colliders.get(collider_entity) else {
+                    continue;
+                };
+                if !filter_allows(&filter, entity, collider_entity, groups, sensor) {
+                    continue;
+                }
+                let max_toi = closest.map_or(max_toi, |(_, intersection)| intersection.toi);
+                let hit = collider.cast_ray_and_get_normal(
+                    transform.translation,
+                    transform.rotation,
+                    origin,
+                    direction,
+                    max_toi,
+                    true,
+                );
+                if let Some(intersection) = hit {
+                    closest = Some((collider_entity, intersection));
+                }
+                rewound.push((collider.clone(), transform));
+            }
+        }
+
+        if self.settings.debug_draw {
+            self.rewound.send(HitboxesRewound {
+                tick,
+                colliders: rewound,
+            });
+        }
+        closest
+    }
+}
+
+/// Whether the filter lets a ray hit a rewound collider of the compensated `owner`.
+fn filter_allows(
+    filter: &QueryFilter,
+    owner: Entity,
+    collider: Entity,
+    groups: Option<&CollisionGroups>,
+    sensor: bool,
+) -> bool {
+    let excluded_sensor = sensor && filter.flags.contains(QueryFilterFlags::EXCLUDE_SENSORS);
+    let excluded_solid = !sensor && filter.flags.contains(QueryFilterFlags::EXCLUDE_SOLIDS);
+    let excluded = filter.exclude_collider == Some(collider)
+        || filter.exclude_rigid_body.is_some_and(|body| body == owner || body == collider);
+    // The same test rapier does, colliders without groups interact with everything.
+    let groups_match = match (filter.groups, groups) {
+        (Some(filter), Some(groups)) => {
+            filter.memberships.intersects(groups.filters) && groups.memberships.intersects(filter.filters)
+        }
+        _ => true,
+    };
+    !excluded_sensor
+        && !excluded_solid
+        && !excluded
+        && groups_match
+        && filter.predicate.map_or(true, |predicate| predicate(collider))
+}
+
+pub struct LagCompensationPlugin;
+
+impl Plugin for LagCompensationPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<LagCompensationSettings>()
+            .init_resource::<LagCompensationSettings>()
+            .add_event::<HitboxesRewound>()
+            .add_systems(Update, (
+                track_player_hitboxes.run_if(resource_exists::<NetServer>()),
+                spawn_rewound_hitboxes.run_if(not(resource_exists::<Headless>())),
+                despawn_rewound_hitboxes,
+            ).run_if(in_state(AppState::Game)))
+            .add_systems(FixedUpdate, record_hitboxes
+                .after(SimulationSet::Record)
+                .run_if(in_state(AppState::Game))
+                .run_if(resource_exists::<NetServer>()));
+    }
+}
+
+pub fn track_player_hitboxes(
+    mut commands: Commands,
+    added_players: Query<Entity, (Added<PlayerBody>, Without<HitboxHistory>)>,
+) {
+    for entity in added_players.iter() {
+        commands.entity(entity).insert(HitboxHistory::default());
+    }
+}
+
+pub fn record_hitboxes(
+    tick: Res<SimulationTick>,
+    settings: Res<LagCompensationSettings>,
+    simulation: Res<SimulationSettings>,
+    colliders: Query<&Transform, With<Collider>>,
+    children_query: Query<&Children>,
+    mut histories: Query<(Entity, &Transform, &mut HitboxHistory)>,
+) {
+    // one more, so that the oldest tick within the window can still be interpolated
+    let capacity = (settings.max_rewind as f64 * simulation.tick_rate).ceil() as usize + 2;
+
+    for (entity, transform, mut history) in histories.iter_mut() {
+        // `GlobalTransform` is only updated at the end of the frame, the players are not parented.
+        let mut frame = HitboxFrame {
+            tick: tick.0,
+            colliders: Vec::new(),
+        };
+        if colliders.contains(entity) {
+            frame.colliders.push((entity, *transform));
+        }
+        for child in children_query.iter_descendants(entity) {
+            if let Ok(child_transform) = colliders.get(child) {
+                frame.colliders.push((child, transform.mul_transform(*child_transform)));
+            }
+        }
+
+        history.frames.push_back(frame);
+        while history.frames.len() > capacity {
+            history.frames.pop_front();
+        }
+    }
+}
+
+pub fn spawn_rewound_hitboxes(
+    mut commands: Commands,
+    settings: Res<LagCompensationSettings>,
+    mut rewound: EventReader<HitboxesRewound>,
+) {
+    for event in rewound.read() {
+        for (collider, transform) in event.colliders.iter() {
+            commands.spawn((
+                Name::new("RewoundHitbox"),
+                GameEntity,
+                RewoundHitbox {
+                    remaining: settings.debug_duration,
+                },
+                TransformBundle::from_transform(*transform),
+                collider.clone(),
+                Sensor,
+                CollisionGroups::new(Group::NONE, Group::NONE),
+                ColliderDebugColor(Color::YELLOW),
+            ));
+        }
+    }
+}
+
+pub fn despawn_rewound_hitboxes(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut hitboxes: Query<(Entity, &mut RewoundHitbox)>,
+) {
+    for (entity, mut hitbox) in hitboxes.iter_mut() {
+        hitbox.remaining -= time.delta_seconds();
+        if hitbox.remaining <= 0.0 {
+            commands.entity(entity).despawn();
+        }
+    }
+}
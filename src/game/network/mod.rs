@@ -0,0 +1,142 @@
+use std::net::{SocketAddr, ToSocketAddrs};
+
+use bevy::prelude::*;
+
+use crate::game::network::client::ClientPlugin;
+use crate::game::network::lag_compensation::LagCompensationPlugin;
+use crate::game::network::server::ServerPlugin;
+use crate::game::network::transport::LinkConditioner;
+
+pub mod client;
+pub mod lag_compensation;
+pub mod prediction;
+pub mod protocol;
+pub mod server;
+pub mod transport;
+
+pub const DEFAULT_PORT: u16 = 7777;
+
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Chosen on the command line, see `NetworkMode::from_args`.
+pub enum NetworkMode {
+    /// Single player, everything is simulated locally.
+    #[default]
+    Offline,
+    /// Dedicated server without a local player. It owns the simulation of all players.
+    Server { bind: SocketAddr },
+    /// Plays on a server, which has the last word on the movement and health of all players.
+    Client { server: SocketAddr },
+}
+
+impl NetworkMode {
+    /// `--server [address]` starts a dedicated server, `--connect <address>` joins one.
+    /// Addresses without a port use `DEFAULT_PORT`.
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut mode = NetworkMode::Offline;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--server" => {
+                    let address = args.next().unwrap_or_else(|| format!("0.0.0.0:{}", DEFAULT_PORT));
+                    mode = NetworkMode::Server { bind: resolve(&address)? };
+                }
+                "--connect" => {
+                    let address = args.next().ok_or("--connect needs a server address")?;
+                    mode = NetworkMode::Client { server: resolve(&address)? };
+                }
+                _ => return Err(format!("unknown argument `{}`", arg)),
+            }
+        }
+        Ok(mode)
+    }
+}
+
+fn resolve(address: &str) -> Result<SocketAddr, String> {
+    let with_port = if address.contains(':') {
+        address.to_string()
+    } else {
+        format!("{}:{}", address, DEFAULT_PORT)
+    };
+    with_port
+        .to_socket_addrs()
+        .map_err(|error| format!("invalid address `{}`: {}", address, error))?
+        .next()
+        .ok_or_else(|| format!("`{}` doesn't resolve to an address", address))
+}
+
+/// Run condition for everything only a single player game does locally, e.g. spawning the camera player.
+pub fn is_offline(mode: Option<Res<NetworkMode>>) -> bool {
+    mode.map_or(true, |mode| *mode == NetworkMode::Offline)
+}
+
+/// Run condition for game logic which only the side owning the simulation runs, e.g. spawning bots.
+pub fn has_authority(mode: Option<Res<NetworkMode>>) -> bool {
+    mode.map_or(true, |mode| !matches!(*mode, NetworkMode::Client { .. }))
+}
+
+#[derive(Resource, Reflect, Debug)]
+#[reflect(Resource)]
+pub struct NetworkSettings {
+    /// Seconds without any message until the other side counts as disconnected.
+    pub timeout: f32,
+    /// Seconds between two connection attempts of the client.
+    pub hello_interval: f32,
+    /// Seconds between two pings of the server to each client.
+    pub ping_interval: f32,
+    /// Minimum seconds between two match status messages of the server.
+    pub status_interval: f32,
+    /// Seconds until an unchanged match status is sent again.
+    pub status_resend_interval: f32,
+    pub max_clients: u32,
+    /// Predicted positions closer than this to the server's are not corrected.
+    pub correction_tolerance: f32,
+    /// Part of a correction which is hidden from the camera, it fades away over this many seconds.
+    pub correction_smoothing: f32,
+    /// Every input is sent this many times.
+    pub input_redundancy: usize,
+    /// Ticks remote players are shown in the past, so that there is a newer snapshot to interpolate to.
+    pub interpolation_delay: f64,
+    /// Delays and drops outgoing datagrams to test bad connections.
+    pub conditioner: Option<LinkConditioner>,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        NetworkSettings {
+            timeout: 5.0,
+            hello_interval: 0.5,
+            ping_interval: 1.0,
+            status_interval: 0.1,
+            status_resend_interval: 1.0,
+            max_clients: 8,
+            correction_tolerance: 0.01,
+            correction_smoothing: 0.2,
+            input_redundancy: 4,
+            interpolation_delay: 6.0,
+            conditioner: None,
+        }
+    }
+}
+
+pub struct NetworkPlugin {
+    pub mode: NetworkMode,
+}
+
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<NetworkSettings>()
+            .init_resource::<NetworkSettings>()
+            .insert_resource(self.mode)
+            // `fire_weapons` asks it in every mode, it only rewinds on a server.
+            .add_plugins(LagCompensationPlugin);
+
+        match self.mode {
+            NetworkMode::Offline => {}
+            NetworkMode::Server { .. } => {
+                app.add_plugins(ServerPlugin);
+            }
+            NetworkMode::Client { .. } => {
+                app.add_plugins(ClientPlugin);
+            }
+        }
+    }
+}
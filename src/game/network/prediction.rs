@@ -0,0 +1,327 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use bevy_rapier3d::prelude::*;
+
+use crate::app::AppState;
+use crate::game::network::client::NetClient;
+use crate::game::network::protocol::{PlayerInput, PlayerState};
+use crate::game::network::NetworkSettings;
+use crate::game::player::{LocalPlayer, PlayerBody, PlayerCamera};
+use crate::game::simulation::{
+    advance_tick, interpolate_offsets, Resimulate, SimulationSet, SimulationTick,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct PredictedTick {
+    pub input: PlayerInput,
+    /// State of the local player after simulating the input.
+    pub translation: Vec3,
+    pub velocity: Vec3,
+}
+
+#[derive(Resource, Debug, Default)]
+/// Ring buffer of the inputs of the local player the server has not acknowledged yet,
+/// together with their predicted outcome.
+pub struct InputHistory {
+    ticks: VecDeque<PredictedTick>,
+}
+
+impl InputHistory {
+    /// Two seconds at the default tick rate, older inputs are not replayed anymore.
+    pub const CAPACITY: usize = 128;
+
+    pub fn push(&mut self, input: PlayerInput) {
+        if self.ticks.len() == Self::CAPACITY {
+            self.ticks.pop_front();
+        }
+        self.ticks.push_back(PredictedTick {
+            input,
+            translation: Vec3::ZERO,
+            velocity: Vec3::ZERO,
+        });
+    }
+
+    /// The newest inputs, oldest first.
+    pub fn recent(&self, count: usize) -> Vec<PlayerInput> {
+        let skip = self.ticks.len().saturating_sub(count);
+        self.ticks.iter().skip(skip).map(|predicted| predicted.input).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ticks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ticks.is_empty()
+    }
+
+    fn record(&mut self, tick: u64, translation: Vec3, velocity: Vec3) {
+        if let Some(predicted) = self.ticks.iter_mut().rev().find(|predicted| predicted.input.tick == tick) {
+            predicted.translation = translation;
+            predicted.velocity = velocity;
+        }
+    }
+
+    /// Forgets everything up to the acknowledged tick and returns the prediction of it.
+    fn acknowledge(&mut self, tick: u64) -> Option<PredictedTick> {
+        while self.ticks.front().is_some_and(|predicted| predicted.input.tick < tick) {
+            self.ticks.pop_front();
+        }
+        self.ticks.front()
+            .is_some_and(|predicted| predicted.input.tick == tick)
+            .then(|| self.ticks.pop_front())
+            .flatten()
+    }
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct Prediction {
+    /// The newest server state of the local player, with the tick of the last input it includes.
+    pub authoritative: Option<(u64, PlayerState)>,
+    /// Part of the last corrections which is still hidden from the camera.
+    pub visual_error: Vec3,
+    /// Number of times the prediction was wrong.
+    pub corrections: u32,
+    /// Distance between the prediction and the server state of the last correction.
+    pub last_error: f32,
+    pub replayed_ticks: u32,
+}
+
+pub struct PredictionPlugin;
+
+impl Plugin for PredictionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InputHistory>()
+            .init_resource::<Prediction>()
+            .add_systems(OnEnter(AppState::Game), reset_prediction)
+            .add_systems(FixedUpdate, (
+                reconcile.before(SimulationSet::Movement),
+                record_prediction.in_set(SimulationSet::Record).before(advance_tick),
+            ).run_if(in_state(AppState::Game)).run_if(resource_exists::<NetClient>()))
+            .add_systems(PostUpdate, hide_corrections
+                .after(interpolate_offsets)
+                .before(TransformSystem::TransformPropagate)
+                .run_if(resource_exists::<NetClient>()));
+    }
+}
+
+pub fn reset_prediction(mut commands: Commands) {
+    commands.insert_resource(InputHistory::default());
+    commands.insert_resource(Prediction::default());
+}
+
+pub fn record_prediction(
+    tick: Res<SimulationTick>,
+    mut history: ResMut<InputHistory>,
+    players: Query<(&Transform, &Velocity), With<LocalPlayer>>,
+) {
+    if let Ok((transform, velocity)) = players.get_single() {
+        history.record(tick.0, transform.translation, velocity.linvel);
+    }
+}
+
+/// Compares the server state with what was predicted for the same tick. If they differ, the
+/// local player is reset to the server state and all newer inputs are simulated again.
+pub fn reconcile(world: &mut World) {
+    let Some((ack, state)) = world.resource_mut::<Prediction>().authoritative.take() else {
+        return;
+    };
+    let tolerance = world.resource::<NetworkSettings>().correction_tolerance;
+    let Ok(player) = world.query_filtered::<Entity, With<LocalPlayer>>().get_single(world) else {
+        return;
+    };
+
+    let mut history = world.resource_mut::<InputHistory>();
+    // Without a prediction of that tick, e.g. right after spawning, there is nothing to compare.
+    let Some(predicted) = history.acknowledge(ack) else {
+        return;
+    };
+    let error = state.translation.distance(predicted.translation);
+    if error <= tolerance {
+        return;
+    }
+    let replay: Vec<PlayerInput> = history.ticks.iter().map(|predicted| predicted.input).collect();
+
+    // Only the local player is replayed, everything else has to stay where it is. Kinematic bodies
+    // are stepped as well, so every body is put back afterwards.
+    let others: Vec<(Entity, Transform, Option<Velocity>)> = world
+        .query_filtered::<(Entity, &Transform, Option<&Velocity>), (With<RigidBody>, Without<LocalPlayer>)>()
+        .iter(world)
+        .map(|(entity, transform, velocity)| (entity, *transform, velocity.copied()))
+        .collect();
+    // The replayed collisions already happened, e.g. projectiles must not hit twice.
+    let collisions = std::mem::take(&mut *world.resource_mut::<Events<CollisionEvent>>());
+    let contact_forces = std::mem::take(&mut *world.resource_mut::<Events<ContactForceEvent>>());
+
+    let mut entity = world.entity_mut(player);
+    let current_input = entity.get::<PlayerBody>().expect("the local player has a body").clone();
+    let before = entity.get::<Transform>().expect("the local player has a transform").translation;
+    entity.get_mut::<Transform>().expect("the local player has a transform").translation = state.translation;
+    entity.get_mut::<Velocity>().expect("the local player has a velocity").linvel = state.velocity;
+
+    for input in replay.iter() {
+        input.apply_to(&mut world.get_mut::<PlayerBody>(player).expect("the local player has a body"));
+        world.run_schedule(Resimulate);
+
+        let translation = world.get::<Transform>(player).expect("the local player has a transform").translation;
+        let velocity = world.get::<Velocity>(player).expect("the local player has a velocity").linvel;
+        world.resource_mut::<InputHistory>().record(input.tick, translation, velocity);
+    }
+
+    *world.get_mut::<PlayerBody>(player).expect("the local player has a body") = current_input;
+    for (other, transform, velocity) in others {
+        let Some(mut entity) = world.get_entity_mut(other) else {
+            continue;
+        };
+        entity.insert(transform);
+        if let Some(velocity) = velocity {
+            entity.insert(velocity);
+        }
+    }
+    *world.resource_mut::<Events<CollisionEvent>>() = collisions;
+    *world.resource_mut::<Events<ContactForceEvent>>() = contact_forces;
+
+    let after = world.get::<Transform>(player).expect("the local player has a transform").translation;
+    let mut prediction = world.resource_mut::<Prediction>();
+    prediction.visual_error += before - after;
+    prediction.corrections += 1;
+    prediction.last_error = error;
+    prediction.replayed_ticks += replay.len() as u32;
+}
+
+/// Moves the camera back to where the player was before the correction and lets it catch up smoothly.
+pub fn hide_corrections(
+    time: Res<Time>,
+    settings: Res<NetworkSettings>,
+    mut prediction: ResMut<Prediction>,
+    players: Query<&Transform, (With<LocalPlayer>, Without<PlayerCamera>)>,
+    mut cameras: Query<&mut Transform, With<PlayerCamera>>,
+) {
+    let fade = (-time.delta_seconds() / settings.correction_smoothing.max(f32::EPSILON)).exp();
+    prediction.visual_error *= fade;
+    if prediction.visual_error.length() < 0.001 {
+        prediction.visual_error = Vec3::ZERO;
+    }
+
+    let (Ok(player), Ok(mut camera)) = (players.get_single(), cameras.get_single_mut()) else {
+        return;
+    };
+    camera.translation += player.rotation.inverse() * prediction.visual_error;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use crate::game::game_modes::MatchRoster;
+    use crate::game::kills::PlayerKilled;
+    use crate::game::network::server::NetServer;
+    use crate::game::network::transport::LinkConditioner;
+    use crate::game::network::{NetworkMode, NetworkPlugin};
+    use crate::game::respawn::{PlayerDied, RespawnSettings};
+    use crate::game::simulation::headless_app;
+    use crate::game::world::LoadedMarker;
+
+    use super::*;
+
+    const FRAME_RATE: u32 = 64;
+
+    fn spawn_level(mut commands: Commands, mut scenes: ResMut<Assets<Scene>>) {
+        commands.spawn((
+            TransformBundle::from_transform(Transform::from_xyz(0.0, -0.5, 0.0)),
+            Collider::cuboid(50.0, 0.5, 50.0),
+        ));
+        // the server waits for a loaded world before spawning players
+        commands.spawn((
+            SceneBundle {
+                scene: scenes.add(Scene::new(World::new())),
+                ..default()
+            },
+            LoadedMarker,
+        ));
+    }
+
+    fn network_app(mode: NetworkMode) -> App {
+        let mut app = headless_app(FRAME_RATE);
+        app.init_resource::<RespawnSettings>()
+            .init_resource::<MatchRoster>()
+            .add_event::<PlayerDied>()
+            .add_event::<PlayerKilled>()
+            .insert_resource(NetworkSettings {
+                conditioner: Some(LinkConditioner {
+                    latency: 0.05,
+                    jitter: 0.01,
+                    loss: 0.1,
+                }),
+                ..default()
+            })
+            .add_plugins(NetworkPlugin { mode })
+            .add_systems(Startup, spawn_level);
+        app
+    }
+
+    // walk forward for two seconds after spawning, then stand still
+    fn input_script(mut frames: Local<u32>, mut players: Query<&mut PlayerBody, With<LocalPlayer>>) {
+        let Ok(mut body) = players.get_single_mut() else {
+            return;
+        };
+        *frames += 1;
+        let elapsed = *frames as f32 / FRAME_RATE as f32;
+        body.desired_velocity = Vec3::ZERO;
+        body.desired_direction = Vec3::ZERO;
+        if (0.5..2.5).contains(&elapsed) {
+            body.desired_direction = Vec3::NEG_Z;
+            body.desired_velocity = Vec3::NEG_Z * 3.0;
+        }
+    }
+
+    fn player_position<F: bevy::ecs::query::ReadOnlyWorldQuery>(app: &mut App) -> Option<Vec3> {
+        app.world
+            .query_filtered::<&Transform, F>()
+            .get_single(&app.world)
+            .ok()
+            .map(|transform| transform.translation)
+    }
+
+    // server and client in one process, connected over localhost with 50 ms latency and 10 % loss
+    #[test]
+    fn prediction_survives_latency_and_loss() {
+        let mut server = network_app(NetworkMode::Server { bind: "127.0.0.1:0".parse().unwrap() });
+        server.update();
+        let address: SocketAddr = server.world.resource::<NetServer>().socket.local_addr().unwrap();
+
+        let mut client = network_app(NetworkMode::Client { server: address });
+        client.add_systems(PreUpdate, input_script);
+
+        let mut moved_before_ack = false;
+        for _ in 0..(5 * FRAME_RATE) {
+            server.update();
+            client.update();
+
+            // The own movement shows up right away, before the server could have confirmed it.
+            let predicted = player_position::<With<LocalPlayer>>(&mut client);
+            let authoritative = player_position::<With<PlayerBody>>(&mut server);
+            if let (Some(predicted), Some(authoritative)) = (predicted, authoritative) {
+                moved_before_ack |= predicted.z < authoritative.z - 0.1;
+            }
+        }
+
+        let predicted = player_position::<With<LocalPlayer>>(&mut client).expect("the client should have spawned");
+        let authoritative = player_position::<With<PlayerBody>>(&mut server).expect("the server should have spawned");
+        assert!(moved_before_ack, "the client should predict its movement");
+        assert!(authoritative.z < -3.0, "the player should have walked, it is at {authoritative}");
+        assert!(
+            predicted.distance(authoritative) < 0.05,
+            "the client ended at {predicted}, the server at {authoritative}"
+        );
+
+        let prediction = client.world.resource::<Prediction>();
+        assert!(
+            prediction.visual_error.length() < 0.01,
+            "corrections should have faded out, {} is left",
+            prediction.visual_error
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game::game_modes::MatchStatus;
+use crate::game::player::{walk_velocity, PlayerBody};
+
+/// Bumped on every incompatible change of the messages below.
+pub const PROTOCOL_VERSION: u32 = 9;
+
+/// Stays below the usual MTU, so that datagrams are not fragmented.
+pub const MAX_DATAGRAM_SIZE: usize = 1200;
+
+#[derive(Component, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Identifies a replicated entity on the server and all clients.
+pub struct NetId(pub u32);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+/// Everything the client decided in one simulation tick, the fields `apply_controls` and
+/// `apply_mouse` fill in `PlayerBody`. The speed is not part of it, the server decides that.
+pub struct PlayerInput {
+    pub tick: u64,
+    pub rotation: Quat,
+    /// Rotation of the view around its x axis.
+    pub pitch: f32,
+    pub direction: Vec3,
+    pub jump: bool,
+    pub crouch: bool,
+    pub trigger_held: bool,
+    /// Set if the trigger was pressed since the previous tick.
+    pub trigger_pressed: bool,
+    pub reload_requested: bool,
+    /// The server tick the other players were shown at, shots are checked against that time.
+    pub view_tick: f64,
+}
+
+impl PlayerInput {
+    /// Moves the body like the local input would, on the server and when replaying inputs.
+    /// Inputs come from the network, so nothing in them is trusted.
+    pub fn apply_to(&self, body: &mut PlayerBody) {
+        if let Some(rotation) = Vec4::from(self.rotation).try_normalize() {
+            body.desired_rotation = Quat::from_vec4(rotation);
+        }
+        let direction = if self.direction.is_finite() { self.direction.clamp_length_max(1.0) } else { Vec3::ZERO };
+        body.desired_direction = direction;
+        body.desired_velocity = walk_velocity(direction, self.crouch);
+        body.jump = self.jump;
+        // already part of `jump`
+        body.jump_buffered = 0.0;
+        body.crouch = self.crouch;
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ClientMessage {
+    /// Sent until the server answers with `Welcome` or `Refused`.
+    Hello { version: u32 },
+    /// The newest inputs, oldest first. Each one is sent several times, in case a datagram gets lost.
+    Input(Vec<PlayerInput>),
+    /// Ready to leave the match lobby. Resent while in the lobby, in case it gets lost.
+    Ready(bool),
+    /// Answers `ServerMessage::Ping` right away, with its sequence number.
+    Pong(u32),
+    Disconnect,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PlayerState {
+    pub id: NetId,
+    /// The client controlling this player, `None` for bots.
+    pub owner: Option<u32>,
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub velocity: Vec3,
+    pub health: f32,
+    pub max_health: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+/// The full state of all replicated players. Players missing in it don't exist (anymore), so a
+/// lost snapshot doesn't need to be resent.
+pub struct Snapshot {
+    pub tick: u64,
+    /// The last input tick of the receiving client the server has applied.
+    pub ack: u64,
+    pub players: Vec<PlayerState>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+/// The shape of a hitbox, for the clients to draw the rewound ones.
+pub enum HitboxShape {
+    Ball { radius: f32 },
+    Cuboid { half_extents: Vec3 },
+    Capsule { a: Vec3, b: Vec3, radius: f32 },
+    Cylinder { half_height: f32, radius: f32 },
+}
+
+impl HitboxShape {
+    /// `None` for shapes hitboxes don't use.
+    pub fn from_collider(collider: &Collider) -> Option<Self> {
+        Some(match collider.as_typed_shape() {
+            ColliderView::Ball(ball) => HitboxShape::Ball { radius: ball.radius() },
+            ColliderView::Cuboid(cuboid) => HitboxShape::Cuboid { half_extents: cuboid.half_extents() },
+            ColliderView::Capsule(capsule) => HitboxShape::Capsule {
+                a: capsule.segment().a(),
+                b: capsule.segment().b(),
+                radius: capsule.radius(),
+            },
+            ColliderView::Cylinder(cylinder) => HitboxShape::Cylinder {
+                half_height: cylinder.half_height(),
+                radius: cylinder.radius(),
+            },
+            _ => return None,
+        })
+    }
+
+    pub fn collider(&self) -> Collider {
+        match *self {
+            HitboxShape::Ball { radius } => Collider::ball(radius),
+            HitboxShape::Cuboid { half_extents } => Collider::cuboid(half_extents.x, half_extents.y, half_extents.z),
+            HitboxShape::Capsule { a, b, radius } => Collider::capsule(a, b, radius),
+            HitboxShape::Cylinder { half_height, radius } => Collider::cylinder(half_height, radius),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ServerMessage {
+    Welcome { client: u32, tick_rate: f64 },
+    Refused { reason: String },
+    Snapshot(Snapshot),
+    /// The match as the server sees it, sent less often than the snapshots. Only servers with
+    /// a match send it.
+    Status { tick: u64, status: MatchStatus },
+    /// Hitboxes a shot was checked against, only sent with `LagCompensationSettings::debug_draw`.
+    HitboxesRewound { tick: f64, hitboxes: Vec<(HitboxShape, Transform)> },
+    /// Measures the round trip time, shown on the scoreboard.
+    Ping(u32),
+    /// The server shuts down or dropped the client.
+    Disconnect,
+}
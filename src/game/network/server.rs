@@ -0,0 +1,456 @@
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_rapier3d::prelude::*;
+
+use crate::app::AppState;
+use crate::game::game_modes::{MatchRoster, MatchStatus, Participant, Team};
+use crate::game::health::Health;
+use crate::game::network::protocol::{
+    ClientMessage, HitboxShape, NetId, PlayerInput, PlayerState, ServerMessage, Snapshot, PROTOCOL_VERSION,
+};
+use crate::game::network::lag_compensation::{HitboxesRewound, ViewTick};
+use crate::game::network::transport::NetSocket;
+use crate::game::network::{NetworkMode, NetworkSettings};
+use crate::game::player::{add_player_view, build_player_body, execute_move, PlayerBody, PlayerView};
+use crate::game::respawn::{
+    choose_spawn, kill_out_of_bounds, occupied_positions, Dead, PlayerDied, PlayerSpawn, RespawnSettings,
+};
+use crate::game::simulation::{SimulationSet, SimulationSettings, SimulationTick};
+use crate::game::weapons::{fire_weapons, Weapon};
+use crate::game::world::world_ready;
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+/// The client controlling this player.
+pub struct NetOwner(pub u32);
+
+#[derive(Debug)]
+pub struct ConnectedClient {
+    pub address: SocketAddr,
+    /// Seconds since the last message.
+    pub silence: f32,
+    pub body: Option<Entity>,
+    /// Runs while the client has no body.
+    pub respawn: Timer,
+    /// Received inputs, one is applied per tick.
+    pub inputs: VecDeque<PlayerInput>,
+    /// Tick of the newest received input.
+    pub received: Option<u64>,
+    /// The last applied input, it is repeated while no newer one has arrived.
+    pub input: PlayerInput,
+    /// Sequence number and time of the last ping without an answer.
+    pub ping_sent: Option<(u32, f64)>,
+    /// Smoothed round trip time in seconds.
+    pub ping: Option<f32>,
+    /// Button presses of the inputs applied since the weapons were updated.
+    trigger_pressed: bool,
+    reload_requested: bool,
+}
+
+/// More buffered inputs only add latency, the oldest ones are skipped then.
+const MAX_BUFFERED_INPUTS: usize = 8;
+
+#[derive(Resource, Debug)]
+pub struct NetServer {
+    pub socket: NetSocket,
+    pub clients: HashMap<u32, ConnectedClient>,
+    next_client: u32,
+    next_id: u32,
+    next_ping: u32,
+}
+
+impl NetServer {
+    fn client_at(&self, address: SocketAddr) -> Option<u32> {
+        self.clients
+            .iter()
+            .find(|(_, client)| client.address == address)
+            .map(|(id, _)| *id)
+    }
+}
+
+pub struct ServerPlugin;
+
+impl Plugin for ServerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, start_server)
+            .add_systems(PreUpdate, (
+                advance_server_socket,
+                receive_client_messages.after(advance_server_socket),
+            ).run_if(in_state(AppState::Game)).run_if(resource_exists::<NetServer>()))
+            .add_systems(Update, (
+                assign_net_ids,
+                drop_silent_clients,
+                send_pings,
+                handle_client_deaths.after(kill_out_of_bounds),
+                // Wait for the level, so that the spawn markers exist.
+                spawn_client_players.after(drop_silent_clients).after(handle_client_deaths).run_if(world_ready),
+                apply_client_weapons.before(fire_weapons),
+                send_match_status,
+                send_rewound_hitboxes,
+            ).run_if(in_state(AppState::Game)).run_if(resource_exists::<NetServer>()))
+            .add_systems(FixedUpdate, (
+                apply_client_inputs.in_set(SimulationSet::Movement).before(execute_move),
+                send_snapshots.after(SimulationSet::Record),
+            ).run_if(in_state(AppState::Game)).run_if(resource_exists::<NetServer>()))
+            .add_systems(Last, shutdown_server.run_if(on_event::<AppExit>()));
+    }
+}
+
+pub fn start_server(
+    mut commands: Commands,
+    mode: Res<NetworkMode>,
+    settings: Res<NetworkSettings>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let NetworkMode::Server { bind } = *mode else {
+        return;
+    };
+    match NetSocket::bind(bind) {
+        Ok(socket) => {
+            info!("server listening on {}", socket.local_addr().unwrap_or(bind));
+            commands.insert_resource(NetServer {
+                socket: socket.with_conditioner(settings.conditioner),
+                clients: HashMap::default(),
+                next_client: 1,
+                next_id: 1,
+                next_ping: 0,
+            });
+        }
+        Err(error) => {
+            error!("could not listen on {}: {}", bind, error);
+            exit.send(AppExit);
+        }
+    }
+}
+
+pub fn advance_server_socket(time: Res<Time<Real>>, mut server: ResMut<NetServer>) {
+    server.socket.advance(time.delta());
+}
+
+pub fn receive_client_messages(
+    mut commands: Commands,
+    time: Res<Time<Real>>,
+    settings: Res<NetworkSettings>,
+    simulation: Res<SimulationSettings>,
+    mut server: ResMut<NetServer>,
+    mut roster: ResMut<MatchRoster>,
+) {
+    let server = server.as_mut();
+    for (address, message) in server.socket.receive::<ClientMessage>() {
+        let known = server.client_at(address);
+        if let Some(client) = known.and_then(|id| server.clients.get_mut(&id)) {
+            client.silence = 0.0;
+        }
+
+        match (message, known) {
+            (ClientMessage::Hello { version }, None) => {
+                if version != PROTOCOL_VERSION {
+                    let reason = format!("the server uses protocol version {}, not {}", PROTOCOL_VERSION, version);
+                    server.socket.send(address, &ServerMessage::Refused { reason });
+                    continue;
+                }
+                if server.clients.len() >= settings.max_clients as usize {
+                    let reason = "the server is full".to_string();
+                    server.socket.send(address, &ServerMessage::Refused { reason });
+                    continue;
+                }
+
+                let id = server.next_client;
+                server.next_client += 1;
+                server.clients.insert(id, ConnectedClient {
+                    address,
+                    silence: 0.0,
+                    body: None,
+                    respawn: Timer::from_seconds(0.0, TimerMode::Once),
+                    inputs: VecDeque::new(),
+                    received: None,
+                    input: PlayerInput::default(),
+                    ping_sent: None,
+                    ping: None,
+                    trigger_pressed: false,
+                    reload_requested: false,
+                });
+                info!("client {} connected from {}", id, address);
+                server.socket.send(address, &ServerMessage::Welcome { client: id, tick_rate: simulation.tick_rate });
+            }
+            // the welcome got lost
+            (ClientMessage::Hello { .. }, Some(id)) => {
+                server.socket.send(address, &ServerMessage::Welcome { client: id, tick_rate: simulation.tick_rate });
+            }
+            (ClientMessage::Input(inputs), Some(id)) => {
+                let client = server.clients.get_mut(&id).expect("the client was just found");
+                for input in inputs {
+                    // Inputs are sent several times and UDP doesn't keep the order.
+                    if client.received.is_some_and(|received| input.tick <= received) {
+                        continue;
+                    }
+                    client.received = Some(input.tick);
+                    client.inputs.push_back(input);
+                }
+                while client.inputs.len() > MAX_BUFFERED_INPUTS {
+                    client.inputs.pop_front();
+                }
+            }
+            (ClientMessage::Ready(ready), Some(id)) => {
+                roster.set_ready(Participant::Client(id), ready);
+            }
+            (ClientMessage::Pong(sequence), Some(id)) => {
+                let client = server.clients.get_mut(&id).expect("the client was just found");
+                let Some((sent_sequence, sent_at)) = client.ping_sent else {
+                    continue;
+                };
+                // an answer to an older ping, which was resent after it
+                if sent_sequence != sequence {
+                    continue;
+                }
+                client.ping_sent = None;
+                let round_trip = (time.elapsed_seconds_f64() - sent_at) as f32;
+                // smoothed, so that a single late datagram doesn't make it jump
+                let ping = client.ping.map_or(round_trip, |ping| ping + (round_trip - ping) * 0.25);
+                client.ping = Some(ping);
+                if let Some(info) = roster.participants.get_mut(&Participant::Client(id)) {
+                    info.ping = Some((ping * 1000.0).round() as u32);
+                }
+            }
+            (ClientMessage::Disconnect, Some(id)) => {
+                if let Some(body) = server.clients.remove(&id).and_then(|client| client.body) {
+                    commands.entity(body).despawn_recursive();
+                }
+                roster.leave(Participant::Client(id));
+                info!("client {} disconnected", id);
+            }
+            // anything but a hello from an unknown address
+            (_, None) => {}
+        }
+    }
+}
+
+pub fn drop_silent_clients(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<NetworkSettings>,
+    mut server: ResMut<NetServer>,
+    mut roster: ResMut<MatchRoster>,
+) {
+    let server = server.as_mut();
+    server.clients.retain(|id, client| {
+        client.silence += time.delta_seconds();
+        if client.silence < settings.timeout {
+            return true;
+        }
+
+        info!("client {} timed out", id);
+        server.socket.send(client.address, &ServerMessage::Disconnect);
+        if let Some(body) = client.body {
+            commands.entity(body).despawn_recursive();
+        }
+        roster.leave(Participant::Client(*id));
+        false
+    });
+}
+
+pub fn send_pings(
+    time: Res<Time<Real>>,
+    settings: Res<NetworkSettings>,
+    mut server: ResMut<NetServer>,
+    mut next_ping: Local<f32>,
+) {
+    *next_ping -= time.delta_seconds();
+    if *next_ping > 0.0 {
+        return;
+    }
+    *next_ping = settings.ping_interval;
+
+    let server = server.as_mut();
+    let sequence = server.next_ping;
+    server.next_ping = server.next_ping.wrapping_add(1);
+    let sent_at = time.elapsed_seconds_f64();
+    for client in server.clients.values_mut() {
+        // an unanswered ping counts as lost
+        client.ping_sent = Some((sequence, sent_at));
+        server.socket.send(client.address, &ServerMessage::Ping(sequence));
+    }
+}
+
+pub fn assign_net_ids(
+    mut commands: Commands,
+    mut server: ResMut<NetServer>,
+    added_players: Query<Entity, (Added<PlayerBody>, Without<NetId>)>,
+) {
+    for entity in added_players.iter() {
+        commands.entity(entity).insert(NetId(server.next_id));
+        server.next_id += 1;
+    }
+}
+
+pub fn handle_client_deaths(
+    mut commands: Commands,
+    settings: Res<RespawnSettings>,
+    mut server: ResMut<NetServer>,
+    mut died: EventReader<PlayerDied>,
+    owners: Query<&NetOwner>,
+) {
+    for event in died.read() {
+        let Ok(owner) = owners.get(event.entity) else {
+            continue;
+        };
+        let Some(client) = server.clients.get_mut(&owner.0) else {
+            continue;
+        };
+        if client.body != Some(event.entity) {
+            // already handled, e.g. killed twice in the same frame
+            continue;
+        }
+
+        commands.entity(event.entity).despawn_recursive();
+        client.body = None;
+        client.respawn = Timer::from_seconds(settings.delay, TimerMode::Once);
+    }
+}
+
+pub fn spawn_client_players(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut server: ResMut<NetServer>,
+    mut roster: ResMut<MatchRoster>,
+    spawns: Query<(&PlayerSpawn, &GlobalTransform)>,
+    players: Query<&GlobalTransform, (With<PlayerBody>, Without<Dead>)>,
+) {
+    let mut occupied = occupied_positions(&players);
+    for (id, client) in server.clients.iter_mut() {
+        if client.body.is_some() || !client.respawn.tick(time.delta()).finished() {
+            continue;
+        }
+
+        let participant = Participant::Client(*id);
+        let name = format!("Client {}", id);
+        let team = roster.join(participant, &name, 0);
+        let transform = choose_spawn(team, &spawns, &occupied).unwrap_or(Transform::from_xyz(0., 5., 0.));
+        occupied.push(transform.translation);
+        let entity = build_player_body(&mut commands, transform);
+        commands.entity(entity).insert((Name::new(name), NetOwner(*id), ViewTick::default(), participant, Team(team)));
+        add_player_view(&mut commands, entity);
+        client.body = Some(entity);
+    }
+}
+
+// fills the same fields as `apply_controls` and `apply_mouse` on the client, one input per tick like `execute_move` there
+pub fn apply_client_inputs(
+    mut server: ResMut<NetServer>,
+    mut views: Query<&mut Transform, With<PlayerView>>,
+    mut bodies: Query<(&mut PlayerBody, &mut ViewTick, &Children)>,
+) {
+    for client in server.clients.values_mut() {
+        if let Some(input) = client.inputs.pop_front() {
+            client.input = input;
+            client.trigger_pressed |= input.trigger_pressed;
+            client.reload_requested |= input.reload_requested;
+        }
+        let Some((mut body, mut view_tick, children)) = client.body.and_then(|body| bodies.get_mut(body).ok()) else {
+            continue;
+        };
+        client.input.apply_to(&mut body);
+        view_tick.0 = client.input.view_tick;
+        for child in children.iter() {
+            if let Ok(mut view) = views.get_mut(*child) {
+                view.rotation = Quat::from_rotation_x(client.input.pitch);
+            }
+        }
+    }
+}
+
+// like `apply_weapon_controls` on the client
+pub fn apply_client_weapons(mut server: ResMut<NetServer>, mut weapons: Query<&mut Weapon>) {
+    for client in server.clients.values_mut() {
+        let Some(mut weapon) = client.body.and_then(|body| weapons.get_mut(body).ok()) else {
+            continue;
+        };
+        weapon.trigger_held = client.input.trigger_held;
+        weapon.trigger_pressed = client.trigger_pressed;
+        weapon.reload_requested = client.reload_requested;
+        client.trigger_pressed = false;
+        client.reload_requested = false;
+    }
+}
+
+pub fn send_snapshots(
+    tick: Res<SimulationTick>,
+    server: Res<NetServer>,
+    players: Query<(&NetId, &Transform, &Velocity, &Health, Option<&NetOwner>), With<PlayerBody>>,
+) {
+    let players: Vec<PlayerState> = players
+        .iter()
+        .map(|(id, transform, velocity, health, owner)| PlayerState {
+            id: *id,
+            owner: owner.map(|owner| owner.0),
+            translation: transform.translation,
+            rotation: transform.rotation,
+            velocity: velocity.linvel,
+            health: health.current,
+            max_health: health.max,
+        })
+        .collect();
+
+    for client in server.clients.values() {
+        let snapshot = Snapshot {
+            tick: tick.0,
+            ack: client.input.tick,
+            players: players.clone(),
+        };
+        server.socket.send(client.address, &ServerMessage::Snapshot(snapshot));
+    }
+}
+
+pub fn send_match_status(
+    time: Res<Time<Real>>,
+    settings: Res<NetworkSettings>,
+    tick: Res<SimulationTick>,
+    server: Res<NetServer>,
+    status: Option<Res<MatchStatus>>,
+    mut pending: Local<bool>,
+    mut since_sent: Local<f32>,
+) {
+    let Some(status) = status else {
+        return;
+    };
+    // The clock changes it every frame, so changes wait for the interval. Without changes it is
+    // still resent now and then, for lost messages and new clients.
+    *pending |= status.is_changed();
+    *since_sent += time.delta_seconds();
+    if *since_sent < settings.status_interval || (!*pending && *since_sent < settings.status_resend_interval) {
+        return;
+    }
+    *pending = false;
+    *since_sent = 0.0;
+
+    let message = ServerMessage::Status { tick: tick.0, status: status.clone() };
+    for client in server.clients.values() {
+        server.socket.send(client.address, &message);
+    }
+}
+
+// Only sent with `LagCompensationSettings::debug_draw`, nothing is drawn on the server.
+pub fn send_rewound_hitboxes(server: Res<NetServer>, mut rewound: EventReader<HitboxesRewound>) {
+    for event in rewound.read() {
+        let hitboxes = event.colliders
+            .iter()
+            .filter_map(|(collider, transform)| Some((HitboxShape::from_collider(collider)?, *transform)))
+            .collect();
+        let message = ServerMessage::HitboxesRewound { tick: event.tick, hitboxes };
+        for client in server.clients.values() {
+            server.socket.send(client.address, &message);
+        }
+    }
+}
+
+pub fn shutdown_server(server: Option<Res<NetServer>>) {
+    let Some(server) = server else {
+        return;
+    };
+    for client in server.clients.values() {
+        server.socket.send(client.address, &ServerMessage::Disconnect);
+    }
+}
@@ -0,0 +1,134 @@
+use std::io::ErrorKind;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use rand::Rng;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::game::network::protocol::MAX_DATAGRAM_SIZE;
+
+#[derive(Reflect, Debug, Clone, Copy, Default)]
+/// Simulates a bad connection for everything a socket sends.
+pub struct LinkConditioner {
+    /// Seconds every datagram is delayed.
+    pub latency: f32,
+    /// Random additional delay up to this many seconds, which also reorders datagrams.
+    pub jitter: f32,
+    /// Part of the datagrams which is dropped, between `0` and `1`.
+    pub loss: f32,
+}
+
+#[derive(Debug)]
+struct DelayedDatagram {
+    /// Socket clock time to send it at.
+    send_at: f64,
+    address: SocketAddr,
+    bytes: Vec<u8>,
+}
+
+#[derive(Debug)]
+/// A non-blocking UDP socket which sends and receives bincode encoded messages.
+pub struct NetSocket {
+    socket: UdpSocket,
+    conditioner: Option<LinkConditioner>,
+    /// Seconds passed, only needed by the conditioner.
+    clock: f64,
+    delayed: Mutex<Vec<DelayedDatagram>>,
+}
+
+impl NetSocket {
+    pub fn bind(address: impl ToSocketAddrs) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(NetSocket {
+            socket,
+            conditioner: None,
+            clock: 0.0,
+            delayed: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub fn with_conditioner(mut self, conditioner: Option<LinkConditioner>) -> Self {
+        self.conditioner = conditioner;
+        self
+    }
+
+    /// Sends the delayed datagrams which are due. Does nothing without a conditioner.
+    pub fn advance(&mut self, delta: Duration) {
+        self.clock += delta.as_secs_f64();
+        let clock = self.clock;
+        let delayed = self.delayed.get_mut().expect("the lock is never held across a panic");
+        delayed.sort_by(|a, b| a.send_at.total_cmp(&b.send_at));
+        let due = delayed.iter().take_while(|datagram| datagram.send_at <= clock).count();
+        let due: Vec<DelayedDatagram> = delayed.drain(..due).collect();
+        for datagram in due {
+            self.send_bytes(datagram.address, &datagram.bytes);
+        }
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn send<T: Serialize>(&self, address: SocketAddr, message: &T) {
+        let bytes = match bincode::serialize(message) {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                error!("could not encode a message: {}", error);
+                return;
+            }
+        };
+        if bytes.len() > MAX_DATAGRAM_SIZE {
+            warn!("sending a datagram of {} bytes, it may get fragmented", bytes.len());
+        }
+
+        let Some(conditioner) = self.conditioner else {
+            self.send_bytes(address, &bytes);
+            return;
+        };
+        let mut rng = rand::thread_rng();
+        if rng.gen::<f32>() < conditioner.loss {
+            return;
+        }
+        let delay = conditioner.latency + rng.gen_range(0.0..=conditioner.jitter.max(0.0));
+        self.delayed.lock().expect("the lock is never held across a panic").push(DelayedDatagram {
+            send_at: self.clock + delay as f64,
+            address,
+            bytes,
+        });
+    }
+
+    fn send_bytes(&self, address: SocketAddr, bytes: &[u8]) {
+        match self.socket.send_to(bytes, address) {
+            Ok(_) => {}
+            // UDP may drop anything, a full buffer is not different from a lost packet
+            Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+            Err(error) => warn!("could not send to {}: {}", address, error),
+        }
+    }
+
+    /// All messages which arrived since the last call. Malformed datagrams are skipped.
+    pub fn receive<T: DeserializeOwned>(&self) -> Vec<(SocketAddr, T)> {
+        let mut messages = Vec::new();
+        let mut buffer = [0u8; MAX_DATAGRAM_SIZE * 4];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((length, address)) => match bincode::deserialize(&buffer[..length]) {
+                    Ok(message) => messages.push((address, message)),
+                    Err(error) => debug!("dropping a malformed datagram from {}: {}", address, error),
+                },
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                // e.g. ICMP port unreachable on Windows while the other side isn't up yet
+                Err(error) if error.kind() == ErrorKind::ConnectionReset => continue,
+                Err(error) => {
+                    warn!("could not receive: {}", error);
+                    break;
+                }
+            }
+        }
+        messages
+    }
+}
@@ -0,0 +1,285 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::app::AppState;
+use crate::game::health::{Armor, Health};
+use crate::game::hud::prompts::ContextPrompts;
+use crate::game::inventory::Inventory;
+use crate::game::network::has_authority;
+use crate::game::player::{LocalPlayer, PlayerBody, PlayerCamera};
+
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+/// Collected by touching it. Add a `*_sensor` collider to the object (or one of its children) in Blender.
+///
+/// `health`, `armor` and `ammo:<ammo type>` items are applied directly,
+/// everything else (e.g. keys) ends up in the `Inventory` items.
+pub struct Pickup {
+    pub item: String,
+    pub quantity: u32,
+    /// Seconds until it appears again, it never does if zero.
+    pub respawn_time: f32,
+}
+
+impl Default for Pickup {
+    fn default() -> Self {
+        Pickup {
+            item: String::new(),
+            quantity: 1,
+            respawn_time: 30.0,
+        }
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct PickupState {
+    /// Running while the pickup is gone.
+    respawn: Option<Timer>,
+    collected: bool,
+}
+
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+/// Anything the player can use by looking at it and pressing `E`.
+pub struct Interactable {
+    /// Shown while looking at it, e.g. "Open door".
+    pub prompt: String,
+    pub enabled: bool,
+}
+
+impl Default for Interactable {
+    fn default() -> Self {
+        Interactable {
+            prompt: "Use".to_string(),
+            enabled: true,
+        }
+    }
+}
+
+#[derive(Resource, Reflect, Debug)]
+#[reflect(Resource)]
+pub struct InteractionSettings {
+    /// Maximum distance from the camera.
+    pub range: f32,
+}
+
+impl Default for InteractionSettings {
+    fn default() -> Self {
+        InteractionSettings { range: 2.5 }
+    }
+}
+
+#[derive(Resource, Default, Debug, PartialEq)]
+/// The interactable the player currently looks at.
+pub struct FocusedInteractable(pub Option<Entity>);
+
+#[derive(Event, Debug, Clone)]
+pub struct PickedUp {
+    pub pickup: Entity,
+    pub collector: Entity,
+    pub item: String,
+    /// What the collector accepted, e.g. less than the pickup has near the carry limit.
+    pub quantity: u32,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct Interacted {
+    pub entity: Entity,
+    pub interactor: Entity,
+}
+
+pub struct PickupsPlugin;
+
+impl Plugin for PickupsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Pickup>()
+            .register_type::<Interactable>()
+            .register_type::<InteractionSettings>()
+            .init_resource::<InteractionSettings>()
+            .init_resource::<FocusedInteractable>()
+            .add_event::<PickedUp>()
+            .add_event::<Interacted>()
+            .add_systems(Update, pickup_replace_proxies)
+            .add_systems(Update, (
+                // only the server knows who collected what
+                (collect_pickups, respawn_pickups).run_if(has_authority),
+                focus_interactables,
+                interact.after(focus_interactables),
+                update_interaction_prompt.after(focus_interactables),
+            ).run_if(in_state(AppState::Game)));
+    }
+}
+
+pub fn pickup_replace_proxies(mut commands: Commands, added_pickups: Query<Entity, Added<Pickup>>) {
+    for entity in added_pickups.iter() {
+        commands.entity(entity).insert(PickupState {
+            respawn: None,
+            collected: false,
+        });
+    }
+}
+
+fn find_ancestor<T: Component>(entity: Entity, query: &Query<(), With<T>>, parents: &Query<&Parent>) -> Option<Entity> {
+    std::iter::once(entity)
+        .chain(parents.iter_ancestors(entity))
+        .find(|entity| query.contains(*entity))
+}
+
+pub fn collect_pickups(
+    rapier_context: Res<RapierContext>,
+    players: Query<Entity, With<PlayerBody>>,
+    pickup_query: Query<(), With<Pickup>>,
+    parents: Query<&Parent>,
+    mut pickups: Query<(&Pickup, &mut PickupState, &mut Visibility)>,
+    mut collectors: Query<(Option<&mut Health>, Option<&mut Armor>, Option<&mut Inventory>)>,
+    mut picked_up: EventWriter<PickedUp>,
+) {
+    for player in players.iter() {
+        for (first, second, intersecting) in rapier_context.intersections_with(player) {
+            if !intersecting {
+                continue;
+            }
+            let other = if first == player { second } else { first };
+            let Some(entity) = find_ancestor(other, &pickup_query, &parents) else {
+                continue;
+            };
+            let Ok((pickup, mut state, mut visibility)) = pickups.get_mut(entity) else {
+                continue;
+            };
+            if state.collected {
+                continue;
+            }
+            // Players which are already full leave it for someone else.
+            let Ok((health, armor, inventory)) = collectors.get_mut(player) else {
+                continue;
+            };
+            let accepted = give_pickup(pickup, health, armor, inventory);
+            if accepted == 0 {
+                continue;
+            }
+
+            state.collected = true;
+            if pickup.respawn_time > 0.0 {
+                state.respawn = Some(Timer::from_seconds(pickup.respawn_time, TimerMode::Once));
+            }
+            *visibility = Visibility::Hidden;
+
+            picked_up.send(PickedUp {
+                pickup: entity,
+                collector: player,
+                item: pickup.item.clone(),
+                quantity: accepted,
+            });
+        }
+    }
+}
+
+/// Adds the pickup to the collector and returns how much of it was accepted.
+pub fn give_pickup(
+    pickup: &Pickup,
+    health: Option<Mut<Health>>,
+    armor: Option<Mut<Armor>>,
+    inventory: Option<Mut<Inventory>>,
+) -> u32 {
+    match pickup.item.as_str() {
+        "health" => {
+            let Some(mut health) = health else {
+                return 0;
+            };
+            let accepted = (health.max - health.current).max(0.0).min(pickup.quantity as f32);
+            health.current += accepted;
+            accepted.ceil() as u32
+        }
+        "armor" => {
+            let Some(mut armor) = armor else {
+                return 0;
+            };
+            let accepted = (armor.max - armor.current).max(0.0).min(pickup.quantity as f32);
+            armor.current += accepted;
+            accepted.ceil() as u32
+        }
+        item => {
+            let Some(mut inventory) = inventory else {
+                return 0;
+            };
+            match item.strip_prefix("ammo:") {
+                Some(ammo_type) => inventory.add_ammo(ammo_type, pickup.quantity),
+                None => {
+                    inventory.add_item(item, pickup.quantity);
+                    pickup.quantity
+                }
+            }
+        }
+    }
+}
+
+pub fn respawn_pickups(time: Res<Time>, mut pickups: Query<(&mut PickupState, &mut Visibility)>) {
+    for (mut state, mut visibility) in pickups.iter_mut() {
+        let Some(timer) = state.respawn.as_mut() else {
+            continue;
+        };
+        if timer.tick(time.delta()).finished() {
+            state.respawn = None;
+            state.collected = false;
+            *visibility = Visibility::Inherited;
+        }
+    }
+}
+
+pub fn focus_interactables(
+    rapier_context: Res<RapierContext>,
+    settings: Res<InteractionSettings>,
+    camera_query: Query<(&GlobalTransform, &Parent), With<PlayerCamera>>,
+    interactable_query: Query<(), With<Interactable>>,
+    interactables: Query<&Interactable>,
+    sensors: Query<(), With<Sensor>>,
+    parents: Query<&Parent>,
+    mut focused: ResMut<FocusedInteractable>,
+) {
+    let Ok((camera_transform, player)) = camera_query.get_single() else {
+        focused.set_if_neq(FocusedInteractable(None));
+        return;
+    };
+
+    // Sensors are only hit if they belong to an interactable, so water or ladders don't get in the way.
+    let predicate = |entity: Entity| {
+        !sensors.contains(entity) || find_ancestor(entity, &interactable_query, &parents).is_some()
+    };
+    let filter = QueryFilter::new().exclude_rigid_body(player.get()).predicate(&predicate);
+    let target = rapier_context
+        .cast_ray(camera_transform.translation(), camera_transform.forward(), settings.range, true, filter)
+        .and_then(|(hit, _)| find_ancestor(hit, &interactable_query, &parents))
+        .filter(|entity| interactables.get(*entity).is_ok_and(|interactable| interactable.enabled));
+
+    // only mark it as changed when looking at something else, the prompt is updated on changes
+    focused.set_if_neq(FocusedInteractable(target));
+}
+
+pub fn interact(
+    keyboard: Res<Input<KeyCode>>,
+    focused: Res<FocusedInteractable>,
+    players: Query<Entity, With<LocalPlayer>>,
+    mut interacted: EventWriter<Interacted>,
+) {
+    if !keyboard.just_pressed(KeyCode::E) {
+        return;
+    }
+    let (Some(entity), Ok(interactor)) = (focused.0, players.get_single()) else {
+        return;
+    };
+    interacted.send(Interacted { entity, interactor });
+}
+
+pub fn update_interaction_prompt(
+    focused: Res<FocusedInteractable>,
+    interactables: Query<&Interactable>,
+    mut prompts: ResMut<ContextPrompts>,
+) {
+    if !focused.is_changed() {
+        return;
+    }
+    match focused.0.and_then(|entity| interactables.get(entity).ok()) {
+        Some(interactable) => prompts.show("interaction", 0, format!("[E] {}", interactable.prompt)),
+        None => prompts.hide("interaction"),
+    }
+}
@@ -0,0 +1,310 @@
+use bevy::animation::{EntityPath, Keyframes, VariableCurve};
+use bevy::gltf::Gltf;
+use bevy::prelude::*;
+use bevy::scene::SceneInstance;
+use bevy_rapier3d::prelude::*;
+use bevy_tnua::TnuaProximitySensor;
+
+use crate::app::{AppState, MyAssets};
+use crate::game::player::{execute_move, PlayerBody};
+use crate::game::simulation::SimulationSet;
+
+#[derive(Reflect, Default, Debug, Clone, Copy, PartialEq)]
+pub enum PlatformPathMode {
+    /// Go back along the waypoints after reaching the last one, animations play backwards.
+    #[default]
+    PingPong,
+    /// Continue with the first waypoint after reaching the last one, animations start over.
+    Loop,
+}
+
+#[derive(Component, Reflect, Debug)]
+#[reflect(Component)]
+/// Kinematic platform proxy which follows waypoints (relative to its initial position)
+/// and optionally rotates, or plays an animation of its node. Everything standing on it is carried along.
+pub struct Platform {
+    pub waypoints: Vec<Vec3>,
+    /// Animation in `World.glb` which moves the platform instead of the waypoints and the
+    /// rotation. Only translation and rotation of the platform node itself are used.
+    pub animation: String,
+    pub speed: f32,
+    pub mode: PlatformPathMode,
+    /// Seconds to wait at each waypoint.
+    pub wait_time: f32,
+    /// Constant rotation in radians per second.
+    pub angular_velocity: Vec3,
+}
+
+impl Default for Platform {
+    fn default() -> Self {
+        Platform {
+            waypoints: vec![Vec3::ZERO],
+            animation: String::new(),
+            speed: 2.0,
+            mode: PlatformPathMode::default(),
+            wait_time: 1.0,
+            angular_velocity: Vec3::ZERO,
+        }
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct PlatformState {
+    origin: Vec3,
+    target: usize,
+    backwards: bool,
+    wait: Timer,
+    animation: Option<PlatformAnimation>,
+}
+
+#[derive(Debug)]
+pub struct PlatformAnimation {
+    clip: Handle<AnimationClip>,
+    /// Names from the scene root down to the platform, as the clip refers to its nodes.
+    path: EntityPath,
+    /// Seconds into the clip.
+    time: f32,
+}
+
+pub struct PlatformsPlugin;
+
+impl Plugin for PlatformsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Platform>()
+            .add_systems(Update, (
+                platform_replace_proxies,
+                find_platform_animations
+                    .after(platform_replace_proxies)
+                    .run_if(in_state(AppState::Game)),
+            ))
+            .add_systems(FixedUpdate, (
+                move_platforms,
+                animate_platforms,
+                carry_player_rotation.before(execute_move),
+            ).in_set(SimulationSet::Movement).run_if(in_state(AppState::Game)));
+    }
+}
+
+// turns platform stand-ins into kinematic rigid bodies
+pub fn platform_replace_proxies(
+    mut commands: Commands,
+    added_platforms: Query<(Entity, &Platform, &Transform), Added<Platform>>,
+) {
+    for (entity, platform, transform) in added_platforms.iter() {
+        // Velocity based, so that tnua sees the platform velocity and the player inherits it.
+        commands.entity(entity).insert((
+            RigidBody::KinematicVelocityBased,
+            Velocity::zero(),
+            PlatformState {
+                origin: transform.translation,
+                target: 0,
+                backwards: false,
+                wait: Timer::from_seconds(platform.wait_time, TimerMode::Once),
+                animation: None,
+            },
+        ));
+    }
+}
+
+pub fn move_platforms(
+    time: Res<Time>,
+    mut platforms: Query<(&Platform, &mut PlatformState, &mut Velocity, &Transform)>,
+) {
+    let delta = time.delta_seconds();
+    if delta <= 0.0 {
+        return;
+    }
+
+    for (platform, mut state, mut velocity, transform) in platforms.iter_mut() {
+        if !platform.animation.is_empty() {
+            continue;
+        }
+        velocity.angvel = platform.angular_velocity;
+
+        if platform.waypoints.is_empty() {
+            velocity.linvel = Vec3::ZERO;
+            continue;
+        }
+
+        if !state.wait.finished() {
+            state.wait.tick(time.delta());
+            velocity.linvel = Vec3::ZERO;
+            continue;
+        }
+
+        let target = state.origin + platform.waypoints[state.target];
+        let to_target = target - transform.translation;
+        let step = platform.speed * delta;
+
+        if to_target.length() > step {
+            velocity.linvel = to_target.normalize() * platform.speed;
+            continue;
+        }
+
+        // Arrive exactly at the waypoint in this step, then wait and pick the next one.
+        velocity.linvel = to_target / delta;
+        state.wait.reset();
+        let (next, backwards) = next_waypoint(platform, state.target, state.backwards);
+        state.target = next;
+        state.backwards = backwards;
+    }
+}
+
+fn next_waypoint(platform: &Platform, current: usize, backwards: bool) -> (usize, bool) {
+    let last = platform.waypoints.len() - 1;
+    match platform.mode {
+        PlatformPathMode::Loop => ((current + 1) % (last + 1), false),
+        PlatformPathMode::PingPong if last == 0 => (0, false),
+        PlatformPathMode::PingPong => match (backwards, current) {
+            (false, c) if c == last => (c - 1, true),
+            (true, 0) => (1, false),
+            (false, c) => (c + 1, false),
+            (true, c) => (c - 1, true),
+        },
+    }
+}
+
+// Once the world scene is spawned, as the clips refer to the platforms by their path in it.
+pub fn find_platform_animations(
+    assets: Res<MyAssets>,
+    assets_gltf: Res<Assets<Gltf>>,
+    names: Query<&Name>,
+    parents: Query<&Parent>,
+    scenes: Query<(), With<SceneInstance>>,
+    mut platforms: Query<(Entity, &Platform, &mut PlatformState)>,
+    mut warned: Local<Vec<Entity>>,
+) {
+    for (entity, platform, mut state) in platforms.iter_mut() {
+        if platform.animation.is_empty() || state.animation.is_some() || warned.contains(&entity) {
+            continue;
+        }
+        let Some(path) = scene_path(entity, &names, &parents, &scenes) else {
+            continue;
+        };
+        let Some(clip) = assets_gltf
+            .get(&assets.world)
+            .and_then(|gltf| gltf.named_animations.get(platform.animation.as_str()))
+        else {
+            warn!("no animation `{}` found for the platform {:?}", platform.animation, path.parts);
+            warned.push(entity);
+            continue;
+        };
+        state.animation = Some(PlatformAnimation {
+            clip: clip.clone(),
+            path,
+            time: 0.0,
+        });
+    }
+}
+
+/// The names from the scene root down to the entity, `None` if it is not part of a spawned scene (yet).
+fn scene_path(
+    entity: Entity,
+    names: &Query<&Name>,
+    parents: &Query<&Parent>,
+    scenes: &Query<(), With<SceneInstance>>,
+) -> Option<EntityPath> {
+    let mut parts = Vec::new();
+    for ancestor in std::iter::once(entity).chain(parents.iter_ancestors(entity)) {
+        if scenes.contains(ancestor) {
+            parts.reverse();
+            return Some(EntityPath { parts });
+        }
+        parts.push(names.get(ancestor).ok()?.clone());
+    }
+    None
+}
+
+// The velocities move the platform to the pose of the clip at the end of the tick, so that tnua
+// carries the player like on the waypoint platforms.
+pub fn animate_platforms(
+    time: Res<Time>,
+    clips: Res<Assets<AnimationClip>>,
+    parent_transforms: Query<&GlobalTransform>,
+    mut platforms: Query<(&Platform, &mut PlatformState, &mut Velocity, &Transform, Option<&Parent>)>,
+) {
+    let delta = time.delta_seconds();
+    if delta <= 0.0 {
+        return;
+    }
+
+    for (platform, mut state, mut velocity, transform, parent) in platforms.iter_mut() {
+        let Some(animation) = state.animation.as_mut() else {
+            continue;
+        };
+        let Some(clip) = clips.get(&animation.clip) else {
+            continue;
+        };
+        // e.g. the animation of another node
+        let Some(curves) = clip.get_curves_by_path(&animation.path) else {
+            velocity.linvel = Vec3::ZERO;
+            velocity.angvel = Vec3::ZERO;
+            continue;
+        };
+
+        let duration = clip.duration();
+        animation.time += delta;
+        let time = match platform.mode {
+            _ if duration <= 0.0 => 0.0,
+            PlatformPathMode::Loop => animation.time % duration,
+            PlatformPathMode::PingPong => duration - (animation.time % (2.0 * duration) - duration).abs(),
+        };
+        let mut target = *transform;
+        for curve in curves.iter() {
+            sample_curve(curve, time, &mut target);
+        }
+
+        // The clip is in the space of the parent, rapier wants world space velocities.
+        let parent_rotation = parent
+            .and_then(|parent| parent_transforms.get(parent.get()).ok())
+            .map_or(Quat::IDENTITY, |parent| parent.to_scale_rotation_translation().1);
+        let mut turn = target.rotation * transform.rotation.inverse();
+        // the short way around
+        if turn.w < 0.0 {
+            turn = -turn;
+        }
+        velocity.linvel = parent_rotation * (target.translation - transform.translation) / delta;
+        velocity.angvel = parent_rotation * turn.to_scaled_axis() / delta;
+    }
+}
+
+/// Sets the translation or rotation of the curve at the given time, interpolating linearly.
+fn sample_curve(curve: &VariableCurve, time: f32, transform: &mut Transform) {
+    let timestamps = &curve.keyframe_timestamps;
+    let Some(last) = timestamps.len().checked_sub(1) else {
+        return;
+    };
+    let step = timestamps.partition_point(|timestamp| *timestamp <= time).clamp(1, last.max(1)) - 1;
+    let next = (step + 1).min(last);
+    let alpha = if next == step {
+        0.0
+    } else {
+        ((time - timestamps[step]) / (timestamps[next] - timestamps[step])).clamp(0.0, 1.0)
+    };
+    match &curve.keyframes {
+        Keyframes::Translation(translations) => {
+            transform.translation = translations[step].lerp(translations[next], alpha);
+        }
+        Keyframes::Rotation(rotations) => {
+            transform.rotation = rotations[step].slerp(rotations[next], alpha);
+        }
+        Keyframes::Scale(_) | Keyframes::Weights(_) => {}
+    }
+}
+
+/// Tnua already inherits the linear velocity of the ground, but the first person rotation is set
+/// manually, so the yaw of rotating platforms has to be added here.
+pub fn carry_player_rotation(
+    time: Res<Time>,
+    platforms: Query<&Velocity, With<Platform>>,
+    mut player_query: Query<(&mut PlayerBody, &TnuaProximitySensor)>,
+) {
+    for (mut body, sensor) in player_query.iter_mut() {
+        let Some(ground) = sensor.output.as_ref() else {
+            continue;
+        };
+        if let Ok(platform_velocity) = platforms.get(ground.entity) {
+            body.add_yaw(platform_velocity.angvel.y * time.delta_seconds());
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use std::f32::consts::FRAC_2_PI;
 
+use bevy::ecs::system::EntityCommands;
 use bevy::input::mouse::MouseMotion;
 use bevy::prelude::*;
 use bevy::window::{CursorGrabMode, PrimaryWindow};
@@ -7,20 +8,68 @@ use bevy_rapier3d::prelude::*;
 use bevy_tnua::builtins::TnuaBuiltinCrouch;
 use bevy_tnua::control_helpers::TnuaCrouchEnforcer;
 use bevy_tnua::prelude::*;
+use bevy_tnua::{TnuaProximitySensor, TnuaToggle};
 use bevy_tnua_rapier3d::{TnuaRapier3dIOBundle, TnuaRapier3dSensorShape};
 
-#[derive(Component, Default)]
+use crate::game::aim::AimState;
+use crate::game::health::{Armor, Health};
+use crate::game::movement_events::MovementTracker;
+use crate::game::movement_volumes::{MovementMode, MovementVolumeState, Oxygen};
+use crate::game::projectiles::PROJECTILE_GROUP;
+use crate::game::simulation::{InterpolatedOffset, PhysicsInterpolation};
+use crate::game::view_model::Recoil;
+use crate::game::world::GameEntity;
+
+#[derive(Component, Default, Clone)]
 pub struct PlayerBody {
-    desired_rotation: Quat,
-    desired_velocity: Vec3,
+    pub(crate) desired_rotation: Quat,
+    /// Normalized movement input in world space.
+    pub(crate) desired_direction: Vec3,
+    pub(crate) desired_velocity: Vec3,
 
-    jump: bool,
-    crouch: bool,
+    pub(crate) jump: bool,
+    /// Seconds a jump press is kept until the body stands on the ground, so that short taps
+    /// between two ticks or shortly before landing are not lost.
+    pub(crate) jump_buffered: f32,
+    /// Whether the last tick asked the controller to jump, held or buffered.
+    pub(crate) jumping: bool,
+    pub(crate) crouch: bool,
+}
+
+impl PlayerBody {
+    /// Turns the desired rotation around the up axis, e.g. when standing on a rotating platform.
+    pub fn add_yaw(&mut self, angle: f32) {
+        self.desired_rotation = Quat::from_rotation_y(angle) * self.desired_rotation;
+    }
+}
+
+/// Distance to the ground up to which a buffered jump is used.
+pub const JUMP_BUFFER_PROXIMITY: f32 = 0.6;
+/// Seconds a jump pressed in the air is remembered, so that it doesn't fire long after the press.
+pub const JUMP_BUFFER_TIME: f32 = 0.2;
+
+/// Meters per second on foot.
+pub const WALK_SPEED: f32 = 3.0;
+/// Part of the walk speed left while crouching.
+pub const CROUCH_SPEED_FACTOR: f32 = 0.2;
+
+/// Velocity for a movement input, the same for local input and for inputs of network clients.
+pub fn walk_velocity(direction: Vec3, crouch: bool) -> Vec3 {
+    let speed = if crouch { WALK_SPEED * CROUCH_SPEED_FACTOR } else { WALK_SPEED };
+    direction.clamp_length_max(1.0) * speed
 }
 
 #[derive(Component)]
 pub struct PlayerCamera {}
 
+#[derive(Component, Default, Debug)]
+/// The player controlled by this machine's keyboard and mouse.
+pub struct LocalPlayer;
+
+#[derive(Component, Default, Debug)]
+/// Child of a player the weapons fire from, the camera of the local player and the eyes of bots.
+pub struct PlayerView;
+
 pub fn toggle_cursor_lock(
     input: Res<Input<KeyCode>>,
     mut windows: Query<&mut Window, With<PrimaryWindow>>,
@@ -40,19 +89,26 @@ pub fn toggle_cursor_lock(
     }
 }
 
-pub fn spawn_player(mut commands: Commands) {
-    let mut cmd = commands.spawn(Name::new("Player"));
+/// Membership of the hitboxes of players and enemies.
+pub const HITBOX_GROUP: Group = Group::GROUP_2;
 
-    // Insert the player mesh
-    cmd.insert((
-        TransformBundle::from_transform(Transform::from_xyz(0., 5., 0.0)),
-        PlayerBody::default()
-    )).with_children(|builder| {
+/// Height of the eyes above the body origin.
+pub const VIEW_HEIGHT: f32 = 0.4;
+
+pub fn build_player(commands: &mut Commands, transform: Transform) -> Entity {
+    let entity = build_player_body(commands, transform);
+    let camera_offset = Vec3::new(0.0, VIEW_HEIGHT, 0.0);
+
+    let mut cmd = commands.entity(entity);
+    cmd.insert((Name::new("Player"), LocalPlayer, Recoil::default()));
+    cmd.with_children(|builder| {
         // Attach the camera to the player
         builder.spawn((
             PlayerCamera {},
+            PlayerView,
+            InterpolatedOffset(camera_offset),
             Camera3dBundle {
-                transform: Transform::from_xyz(0.0, 0.4, 0.0),
+                transform: Transform::from_translation(camera_offset),
                 projection: Projection::Perspective(PerspectiveProjection {
                     fov: 90.0 * (std::f32::consts::PI / 180.0),
                     aspect_ratio: 1.0,
@@ -63,11 +119,71 @@ pub fn spawn_player(mut commands: Commands) {
             }));
     });
 
+    entity
+}
+
+/// Everything a player needs to move and take damage, without input or a view.
+pub fn build_player_body(commands: &mut Commands, transform: Transform) -> Entity {
+    let mut cmd = commands.spawn((Name::new("PlayerBody"), GameEntity));
+
+    // Insert the player mesh
+    cmd.insert((
+        TransformBundle::from_transform(transform),
+        PlayerBody {
+            desired_rotation: transform.rotation,
+            ..default()
+        },
+        // The body only moves in fixed ticks, the camera renders in-between them.
+        PhysicsInterpolation::new(transform.translation),
+    ));
+
     // Add physics to the player
+    insert_character_physics(&mut cmd);
+
+    cmd.with_children(spawn_hitbox);
+
+    // Ladders and water switch the player away from walking.
+    cmd.insert((MovementVolumeState::default(), Oxygen::default()));
+    cmd.insert(MovementTracker::default());
+    cmd.insert((Health::new(100.0), Armor::default()));
+
+    cmd.id()
+}
+
+/// The movement collider is tiny, shots need something bigger to hit. Ray casts and explosions
+/// find the hitbox, projectiles touch it, but its contacts are never solved. So it doesn't block
+/// the movement of anyone and doesn't rest on the world.
+pub fn spawn_hitbox(parent: &mut ChildBuilder) {
+    parent.spawn((
+        Name::new("Hitbox"),
+        TransformBundle::from_transform(Transform::from_xyz(0.0, 0.2, 0.0)),
+        Collider::capsule_y(0.3, 0.25),
+        ColliderMassProperties::Mass(0.0),
+        CollisionGroups::new(HITBOX_GROUP, PROJECTILE_GROUP),
+        SolverGroups::new(HITBOX_GROUP, Group::NONE),
+    ));
+}
+
+/// The eyes of a player without a camera, e.g. bots and players of other machines.
+pub fn add_player_view(commands: &mut Commands, entity: Entity) {
+    let view_offset = Vec3::new(0.0, VIEW_HEIGHT, 0.0);
+    commands.entity(entity).with_children(|builder| {
+        builder.spawn((
+            PlayerView,
+            InterpolatedOffset(view_offset),
+            TransformBundle::from_transform(Transform::from_translation(view_offset)),
+        ));
+    });
+}
+
+/// The tnua controller stack of everything which moves like the player.
+pub fn insert_character_physics(cmd: &mut EntityCommands) {
     cmd.insert(RigidBody::Dynamic);
     cmd.insert(Collider::cylinder(0.1, 0.1));
     cmd.insert(TnuaRapier3dIOBundle::default());
     cmd.insert(TnuaControllerBundle::default());
+    cmd.insert(TnuaToggle::Enabled);
+    cmd.insert(GravityScale(1.0));
     cmd.insert(TnuaCrouchEnforcer::new(0.5 * Vec3::Y, |cmd| {
         cmd.insert(TnuaRapier3dSensorShape(Collider::cylinder(0.0, 0.5)));
     }));
@@ -76,9 +192,42 @@ pub fn spawn_player(mut commands: Commands) {
     cmd.insert(LockedAxes::ROTATION_LOCKED_X | LockedAxes::ROTATION_LOCKED_Z | LockedAxes::ROTATION_LOCKED_Y);
 }
 
+/// Feeds the walking movement into the tnua controller.
+pub fn walk(
+    controller: &mut TnuaController,
+    crouch_enforcer: &mut TnuaCrouchEnforcer,
+    desired_velocity: Vec3,
+    jump: bool,
+    crouch: bool,
+) {
+    if jump {
+        controller.action(TnuaBuiltinJump {
+            height: 1.5,
+            fall_extra_gravity: 10.0,
+            ..default()
+        });
+    }
+
+    if crouch {
+        controller.action(crouch_enforcer.enforcing(TnuaBuiltinCrouch {
+            float_offset: -0.2,
+
+            ..default()
+        }));
+    }
+
+    controller.basis(TnuaBuiltinWalk {
+        spring_strengh: 1000.0,
+        desired_velocity,
+        desired_forward: Vec3::ZERO, // Rotation must be instant in FP - not by physics.
+        float_height: 0.45,
+        ..default()
+    });
+}
+
 pub fn apply_controls(
     keyboard: Res<Input<KeyCode>>,
-    mut player_query: Query<(&mut PlayerBody, &Transform)>,
+    mut player_query: Query<(&mut PlayerBody, &Transform), With<LocalPlayer>>,
 ) {
     for (mut body, transform) in player_query.iter_mut() {
         let mut direction = Vec3::ZERO;
@@ -99,27 +248,39 @@ pub fn apply_controls(
         direction = direction.clamp_length_max(1.0);
 
         body.jump = keyboard.pressed(KeyCode::Space);
+        if keyboard.just_pressed(KeyCode::Space) {
+            body.jump_buffered = JUMP_BUFFER_TIME;
+        }
 
         let crouch_buttons = [KeyCode::ShiftLeft, KeyCode::ShiftLeft];
         body.crouch = keyboard.any_pressed(crouch_buttons);
 
-        let mut speed_factor = 3.0;
-        if body.crouch {
-            speed_factor *= 0.2;
-        }
-        body.desired_velocity = direction * speed_factor;
+        body.desired_direction = direction;
+        body.desired_velocity = walk_velocity(direction, body.crouch);
     }
 }
 
 pub fn apply_mouse(
+    aim_state: Res<AimState>,
     mut camera_query: Query<&mut Transform, With<PlayerCamera>>,
-    mut player_query: Query<(&mut PlayerBody, &Transform), Without<PlayerCamera>>,
+    mut player_query: Query<
+        (&mut PlayerBody, &mut Transform, Option<&mut Recoil>),
+        (With<LocalPlayer>, Without<PlayerCamera>),
+    >,
     mut input: EventReader<MouseMotion>,
 ) {
-    let mut camera_transform = camera_query.single_mut();
-    let mut mouse_move: Vec2 = -(input.read().map(|motion| &motion.delta).sum::<Vec2>());
+    // There is no camera while waiting for the respawn.
+    let Ok(mut camera_transform) = camera_query.get_single_mut() else {
+        return;
+    };
+    let mut mouse_move: Vec2 = -(input.read().map(|motion| &motion.delta).sum::<Vec2>()) * aim_state.sensitivity;
+
+    for (mut body, mut body_transform, recoil) in player_query.iter_mut() {
+        // Recoil turns the view like the mouse does, in the same units.
+        if let Some(mut recoil) = recoil {
+            mouse_move += recoil.take_look() * 180.0;
+        }
 
-    for (mut body, body_transform) in player_query.iter_mut() {
         // Vertical
         let rot = camera_transform.rotation;
 
@@ -143,34 +304,77 @@ pub fn apply_mouse(
         ));
 
         body.desired_rotation = new_rotation.rotation;
+
+        // Apply the look direction right away, movement only happens in the next simulation tick.
+        body_transform.rotation = body.desired_rotation;
     }
 }
 
-pub fn execute_move(mut player_query: Query<(&mut TnuaController, &mut TnuaCrouchEnforcer, &mut Transform, &PlayerBody)>) {
-    for (mut controller, mut crouch_enforcer, mut transform, body) in player_query.iter_mut() {
-        if body.jump {
-            controller.action(TnuaBuiltinJump {
-                height: 1.5,
-                fall_extra_gravity: 10.0,
-                ..default()
-            });
-        }
+pub fn execute_move(
+    time: Res<Time>,
+    view_query: Query<&Transform, With<PlayerView>>,
+    mut player_query: Query<(
+        &mut TnuaController,
+        &mut TnuaCrouchEnforcer,
+        &mut Transform,
+        &mut Velocity,
+        &mut MovementVolumeState,
+        &mut PlayerBody,
+        &TnuaProximitySensor,
+        Option<&Children>,
+    ), Without<PlayerView>>,
+) {
+    for (mut controller, mut crouch_enforcer, mut transform, mut velocity, mut volume_state, mut body, sensor, children) in player_query.iter_mut() {
+        transform.rotation = body.desired_rotation;
 
-        if body.crouch {
-            controller.action(crouch_enforcer.enforcing(TnuaBuiltinCrouch {
-                float_offset: -0.2,
+        // the buffered jump is used up on the first tick on the ground, held jumps go on as before
+        let grounded = sensor.output.as_ref().is_some_and(|output| output.proximity <= JUMP_BUFFER_PROXIMITY);
+        let jump = body.jump || (body.jump_buffered > 0.0 && grounded);
+        body.jump_buffered = if grounded || volume_state.mode != MovementMode::Walk {
+            0.0
+        } else {
+            (body.jump_buffered - time.delta_seconds()).max(0.0)
+        };
+        body.jumping = jump && volume_state.mode == MovementMode::Walk;
 
-                ..default()
-            }));
-        }
+        match volume_state.mode {
+            MovementMode::Walk => {
+                walk(&mut controller, &mut crouch_enforcer, body.desired_velocity, jump, body.crouch);
+            }
+            MovementMode::Climb { speed } => {
+                if body.jump {
+                    // Push away from the ladder and don't grab it again immediately.
+                    velocity.linvel = transform.back() * 2.0 + Vec3::Y * 2.0;
+                    volume_state.ladder_cooldown = 0.5;
+                    continue;
+                }
 
-        controller.basis(TnuaBuiltinWalk {
-            spring_strengh: 1000.0,
-            desired_velocity: body.desired_velocity,
-            desired_forward: Vec3::ZERO, // Rotation must be instant in FP - not by physics.
-            float_height: 0.45,
-            ..default()
-        });
-        transform.rotation = body.desired_rotation;
+                // Forward input climbs, sideways input moves slowly along the ladder.
+                let forward = transform.forward();
+                let climb = body.desired_direction.dot(forward);
+                let sideways = body.desired_direction - forward * climb;
+                velocity.linvel = Vec3::Y * climb * speed + sideways * speed * 0.5;
+            }
+            MovementMode::Swim { speed, drag, .. } => {
+                // Swim into the direction the camera looks at, jump and crouch move straight up and down.
+                let look = children
+                    .and_then(|children| children.iter().find_map(|child| view_query.get(*child).ok()))
+                    .map(|view| (transform.rotation * view.rotation) * Vec3::NEG_Z)
+                    .unwrap_or(transform.forward());
+                let forward = transform.forward();
+                let forward_input = body.desired_direction.dot(forward);
+                let mut direction = look * forward_input + (body.desired_direction - forward * forward_input);
+                if body.jump {
+                    direction += Vec3::Y;
+                }
+                if body.crouch {
+                    direction -= Vec3::Y;
+                }
+
+                let target = direction.clamp_length_max(1.0) * speed;
+                let blend = (drag * time.delta_seconds()).min(1.0);
+                velocity.linvel = velocity.linvel.lerp(target, blend);
+            }
+        }
     }
-}
\ No newline at end of file
+}
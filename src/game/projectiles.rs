@@ -0,0 +1,423 @@
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use bevy_rapier3d::prelude::*;
+use bevy_rapier3d::rapier::geometry::CollisionEventFlags;
+use serde::Deserialize;
+
+use crate::app::AppState;
+use crate::game::explosions::ExplosionDefinition;
+use crate::game::health::{apply_damage, find_health_owner, DamageEvent, DamageKind, Health};
+use crate::game::network::has_authority;
+use crate::game::simulation::SimulationSet;
+use crate::game::world::GameEntity;
+
+/// Membership of projectiles, the only bodies the hitboxes of players and enemies touch.
+pub const PROJECTILE_GROUP: Group = Group::GROUP_3;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ImpactBehaviour {
+    /// Detonate on the first impact, e.g. rockets.
+    Detonate,
+    /// Bounce off, detonate once bounced more than `max_bounces` times, e.g. grenades.
+    Bounce { max_bounces: u32 },
+    /// Stay where it hit until the lifetime ends, e.g. arrows.
+    Stick,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+/// Projectile stats, part of a `WeaponDefinition`.
+pub struct ProjectileDefinition {
+    pub speed: f32,
+    pub radius: f32,
+    pub gravity_scale: f32,
+    /// Linear damping of the projectile body.
+    pub drag: f32,
+    /// Bounciness, only relevant for `ImpactBehaviour::Bounce`.
+    #[serde(default)]
+    pub restitution: f32,
+    pub impact: ImpactBehaviour,
+    /// Detonate after this many seconds.
+    #[serde(default)]
+    pub fuse: Option<f32>,
+    /// Seconds until the projectile is removed without detonating.
+    pub lifetime: f32,
+    /// Damage to whatever is hit directly.
+    pub damage: f32,
+    /// Explosion when detonating.
+    #[serde(default)]
+    pub explosion: Option<ExplosionDefinition>,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct LaunchProjectile {
+    pub shooter: Entity,
+    pub definition: ProjectileDefinition,
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ProjectileHit {
+    pub projectile: Entity,
+    pub shooter: Entity,
+    /// The collider which was hit.
+    pub entity: Entity,
+    pub point: Vec3,
+    pub normal: Vec3,
+    /// Velocity of the projectile right before the impact.
+    pub velocity: Vec3,
+    /// Damage to the hit entity.
+    pub damage: f32,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ProjectileDetonated {
+    pub projectile: Entity,
+    pub shooter: Entity,
+    pub point: Vec3,
+    pub explosion: Option<ExplosionDefinition>,
+}
+
+#[derive(Component, Debug)]
+pub struct Projectile {
+    pub shooter: Entity,
+    pub definition: ProjectileDefinition,
+    pub age: f32,
+    pub bounces: u32,
+    /// Set once an arrow got stuck somewhere.
+    pub stuck: bool,
+    last_velocity: Vec3,
+}
+
+#[derive(Component, Debug)]
+/// The visible part of a projectile, scaled by its radius.
+pub struct ProjectileVisual;
+
+#[derive(Resource, Default, Debug)]
+/// Inactive projectile entities, reused instead of spawning a new entity for every shot.
+pub struct ProjectilePool {
+    free: Vec<Entity>,
+}
+
+#[derive(Resource)]
+pub struct ProjectileAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+pub struct ProjectilesPlugin;
+
+impl Plugin for ProjectilesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ProjectilePool>()
+            .add_event::<LaunchProjectile>()
+            .add_event::<ProjectileHit>()
+            .add_event::<ProjectileDetonated>()
+            .add_systems(Startup, setup_projectile_assets)
+            // the pooled projectiles are despawned with the other game entities
+            .add_systems(OnExit(AppState::Game), reset_projectile_pool)
+            .add_systems(Update, (
+                launch_projectiles,
+                // on clients the projectiles are only for show
+                apply_projectile_hits.before(apply_damage).run_if(has_authority),
+            ).run_if(in_state(AppState::Game)))
+            .add_systems(FixedUpdate, update_projectiles
+                .in_set(SimulationSet::Record)
+                .run_if(in_state(AppState::Game)));
+    }
+}
+
+pub fn reset_projectile_pool(mut pool: ResMut<ProjectilePool>) {
+    pool.free.clear();
+}
+
+pub fn setup_projectile_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(ProjectileAssets {
+        mesh: meshes.add(Mesh::from(shape::UVSphere {
+            radius: 1.0,
+            sectors: 12,
+            stacks: 8,
+        })),
+        material: materials.add(StandardMaterial {
+            base_color: Color::rgb(0.9, 0.6, 0.2),
+            emissive: Color::rgb(0.9, 0.4, 0.1),
+            ..default()
+        }),
+    });
+}
+
+pub fn launch_projectiles(
+    mut commands: Commands,
+    mut pool: ResMut<ProjectilePool>,
+    projectile_assets: Res<ProjectileAssets>,
+    mut launches: EventReader<LaunchProjectile>,
+    children: Query<&Children>,
+    visuals: Query<(), With<ProjectileVisual>>,
+) {
+    for launch in launches.read() {
+        let definition = &launch.definition;
+        let velocity = launch.direction * definition.speed;
+        // start a bit in front of the shooter, so it does not hit itself
+        let transform = Transform::from_translation(launch.origin + launch.direction * (definition.radius + 0.5));
+
+        let entity = match pool.free.pop() {
+            Some(entity) => {
+                commands.entity(entity).remove::<(RigidBodyDisabled, ColliderDisabled)>();
+                // the visual is the only child of a pooled projectile
+                for child in children.iter_descendants(entity).filter(|child| visuals.contains(*child)) {
+                    commands.entity(child).insert(Transform::from_scale(Vec3::splat(definition.radius)));
+                }
+                entity
+            }
+            None => commands
+                .spawn((Name::new("Projectile"), GameEntity, RigidBody::Dynamic, Ccd::enabled()))
+                .insert(ActiveEvents::COLLISION_EVENTS)
+                .with_children(|builder| {
+                    builder.spawn((
+                        ProjectileVisual,
+                        PbrBundle {
+                            mesh: projectile_assets.mesh.clone(),
+                            material: projectile_assets.material.clone(),
+                            transform: Transform::from_scale(Vec3::splat(definition.radius)),
+                            ..default()
+                        },
+                    ));
+                })
+                .id(),
+        };
+
+        commands.entity(entity).insert((
+            SpatialBundle::from_transform(transform),
+            Collider::ball(definition.radius),
+            CollisionGroups::new(PROJECTILE_GROUP, Group::ALL),
+            Velocity::linear(velocity),
+            GravityScale(definition.gravity_scale),
+            Damping {
+                linear_damping: definition.drag,
+                angular_damping: 0.5,
+            },
+            Restitution::coefficient(definition.restitution),
+            Projectile {
+                shooter: launch.shooter,
+                definition: definition.clone(),
+                age: 0.0,
+                bounces: 0,
+                stuck: false,
+                last_velocity: velocity,
+            },
+        ));
+    }
+}
+
+pub fn update_projectiles(
+    mut commands: Commands,
+    time: Res<Time>,
+    rapier_context: Res<RapierContext>,
+    mut pool: ResMut<ProjectilePool>,
+    mut collisions: EventReader<CollisionEvent>,
+    mut projectiles: Query<(Entity, &mut Projectile, &Transform, &Velocity)>,
+    mut hits: EventWriter<ProjectileHit>,
+    mut detonations: EventWriter<ProjectileDetonated>,
+) {
+    let mut finished = Vec::new();
+    // a projectile can touch several colliders in one tick, only the first one counts
+    let mut impacted = HashSet::default();
+
+    for event in collisions.read() {
+        let CollisionEvent::Started(first, second, flags) = *event else {
+            continue;
+        };
+        // water, ladders, pickups and triggers are passed through
+        if flags.contains(CollisionEventFlags::SENSOR) {
+            continue;
+        }
+        let (entity, other) = if projectiles.contains(first) { (first, second) } else { (second, first) };
+        let Ok((_, mut projectile, transform, _)) = projectiles.get_mut(entity) else {
+            continue;
+        };
+        if projectile.stuck || !impacted.insert(entity) {
+            continue;
+        }
+
+        // Find the exact impact by tracing back along the velocity before the collision.
+        let direction = projectile.last_velocity.normalize_or_zero();
+        let reach = projectile.definition.radius * 4.0;
+        let is_other = |candidate: Entity| candidate == other;
+        let (point, normal) = rapier_context
+            .cast_ray_and_get_normal(
+                transform.translation - direction * reach,
+                direction,
+                reach * 2.0,
+                false,
+                QueryFilter::new().predicate(&is_other),
+            )
+            .map_or((transform.translation, -direction), |(_, intersection)| {
+                (intersection.point, intersection.normal)
+            });
+
+        hits.send(ProjectileHit {
+            projectile: entity,
+            shooter: projectile.shooter,
+            entity: other,
+            point,
+            normal,
+            velocity: projectile.last_velocity,
+            damage: projectile.definition.damage,
+        });
+
+        match projectile.definition.impact {
+            ImpactBehaviour::Detonate => finished.push((entity, true)),
+            ImpactBehaviour::Bounce { max_bounces } => {
+                projectile.bounces += 1;
+                if projectile.bounces > max_bounces {
+                    finished.push((entity, true));
+                }
+            }
+            ImpactBehaviour::Stick => {
+                projectile.stuck = true;
+                commands.entity(entity).insert((RigidBodyDisabled, ColliderDisabled));
+            }
+        }
+    }
+
+    for (entity, mut projectile, _, velocity) in projectiles.iter_mut() {
+        projectile.age += time.delta_seconds();
+        projectile.last_velocity = velocity.linvel;
+
+        if projectile.definition.fuse.is_some_and(|fuse| projectile.age >= fuse) {
+            finished.push((entity, true));
+        } else if projectile.age >= projectile.definition.lifetime {
+            finished.push((entity, false));
+        }
+    }
+
+    for (entity, detonate) in finished {
+        let Ok((_, projectile, transform, _)) = projectiles.get(entity) else {
+            continue;
+        };
+        if pool.free.contains(&entity) {
+            // already finished by an earlier impact in this tick
+            continue;
+        }
+        if detonate {
+            detonations.send(ProjectileDetonated {
+                projectile: entity,
+                shooter: projectile.shooter,
+                point: transform.translation,
+                explosion: projectile.definition.explosion,
+            });
+        }
+
+        commands.entity(entity)
+            .remove::<Projectile>()
+            .insert((RigidBodyDisabled, ColliderDisabled, Visibility::Hidden));
+        pool.free.push(entity);
+    }
+}
+
+pub fn apply_projectile_hits(
+    mut hits: EventReader<ProjectileHit>,
+    health_query: Query<(), With<Health>>,
+    parents: Query<&Parent>,
+    mut damage: EventWriter<DamageEvent>,
+) {
+    for hit in hits.read() {
+        if hit.damage <= 0.0 {
+            continue;
+        }
+        let Some(target) = find_health_owner(hit.entity, &health_query, &parents) else {
+            continue;
+        };
+
+        damage.send(DamageEvent {
+            target,
+            source: Some(hit.shooter),
+            amount: hit.damage,
+            kind: DamageKind::Projectile,
+            hit_point: Some(hit.point),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::player::build_player_body;
+    use crate::game::simulation::headless_app;
+
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct Target(Option<Entity>);
+
+    #[derive(Resource, Default)]
+    struct Damaged(Vec<Entity>);
+
+    fn spawn_range(mut commands: Commands, mut target: ResMut<Target>) {
+        commands.spawn((
+            TransformBundle::from_transform(Transform::from_xyz(0.0, -0.5, 0.0)),
+            Collider::cuboid(30.0, 0.5, 30.0),
+        ));
+        target.0 = Some(build_player_body(&mut commands, Transform::from_xyz(0.0, 1.0, 0.0)));
+    }
+
+    // once the body stands, shoot from the side at the chest, well above the movement collider
+    fn shoot(
+        mut frames: Local<u32>,
+        target: Res<Target>,
+        bodies: Query<&GlobalTransform>,
+        mut launches: EventWriter<LaunchProjectile>,
+    ) {
+        *frames += 1;
+        if *frames != 64 {
+            return;
+        }
+        let body = target.0.expect("the target was spawned");
+        let chest = bodies.get(body).expect("the target has a transform").translation() + Vec3::Y * 0.45;
+        launches.send(LaunchProjectile {
+            shooter: Entity::PLACEHOLDER,
+            definition: ProjectileDefinition {
+                speed: 30.0,
+                radius: 0.05,
+                gravity_scale: 0.0,
+                drag: 0.0,
+                restitution: 0.0,
+                impact: ImpactBehaviour::Detonate,
+                fuse: None,
+                lifetime: 2.0,
+                damage: 25.0,
+                explosion: None,
+            },
+            origin: chest + Vec3::Z * 5.0,
+            direction: Vec3::NEG_Z,
+        });
+    }
+
+    fn record_damage(mut damage: EventReader<DamageEvent>, mut damaged: ResMut<Damaged>) {
+        damaged.0.extend(damage.read().map(|event| event.target));
+    }
+
+    #[test]
+    fn projectiles_hit_the_hitbox_of_players() {
+        let mut app = headless_app(64);
+        app.init_resource::<Target>()
+            .init_resource::<Damaged>()
+            .add_event::<DamageEvent>()
+            .add_plugins(ProjectilesPlugin)
+            .add_systems(Startup, spawn_range)
+            .add_systems(Update, (shoot.before(launch_projectiles), record_damage.after(apply_projectile_hits)));
+
+        for _ in 0..(2 * 64) {
+            app.update();
+        }
+
+        let target = app.world.resource::<Target>().0.expect("the target was spawned");
+        assert!(
+            app.world.resource::<Damaged>().0.contains(&target),
+            "the projectile went through the player",
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+
+use crate::app::AppState;
+use crate::game::game_modes::{MatchPhase, MatchRoster, Participant, Team};
+use crate::game::network::{has_authority, is_offline};
+use crate::game::player::{build_player, LocalPlayer, PlayerBody};
+use crate::game::world::world_ready;
+
+#[derive(Component, Reflect, Default, Debug)]
+#[reflect(Component)]
+/// Player spawn point placed in Blender.
+/// A random one of the spawns with the highest priority is used, occupied ones only if all are.
+/// Team `0` can be used by everyone.
+pub struct PlayerSpawn {
+    pub team: u8,
+    pub priority: i32,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct PlayerDied {
+    pub entity: Entity,
+}
+
+#[derive(Component, Debug)]
+/// A player body which died and waits to be despawned, so that it only dies once.
+pub struct Dead;
+
+#[derive(Resource, Reflect, Debug)]
+#[reflect(Resource)]
+pub struct RespawnSettings {
+    /// Seconds between the death and the respawn.
+    pub delay: f32,
+    /// Seconds between the world being loaded and the first spawn.
+    pub initial_delay: f32,
+    /// Anything falling below this height dies.
+    pub kill_height: f32,
+    pub lives: u32,
+    /// Team of the local player, used to pick the spawn.
+    pub team: u8,
+}
+
+impl Default for RespawnSettings {
+    fn default() -> Self {
+        RespawnSettings {
+            delay: 3.0,
+            initial_delay: 0.5,
+            kill_height: -50.0,
+            lives: 3,
+            team: 0,
+        }
+    }
+}
+
+#[derive(Resource, Debug)]
+pub struct RespawnState {
+    pub lives: u32,
+    pub timer: Timer,
+}
+
+pub struct RespawnPlugin;
+
+impl Plugin for RespawnPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<PlayerSpawn>()
+            .register_type::<RespawnSettings>()
+            .init_resource::<RespawnSettings>()
+            .add_event::<PlayerDied>()
+            .add_systems(OnEnter(AppState::Game), reset_respawn)
+            .add_systems(Update, (
+                kill_out_of_bounds.run_if(has_authority),
+                // On a server the clients are respawned by `ServerPlugin`.
+                (
+                    handle_player_death.after(kill_out_of_bounds),
+                    // Wait for the level, so that the spawn markers exist.
+                    respawn_player.after(handle_player_death).run_if(world_ready),
+                ).run_if(is_offline),
+            ).run_if(in_state(AppState::Game)));
+    }
+}
+
+pub fn reset_respawn(mut commands: Commands, settings: Res<RespawnSettings>) {
+    commands.insert_resource(RespawnState {
+        lives: settings.lives,
+        timer: Timer::from_seconds(settings.initial_delay, TimerMode::Once),
+    });
+}
+
+pub fn kill_out_of_bounds(
+    mut commands: Commands,
+    settings: Res<RespawnSettings>,
+    player_query: Query<(Entity, &GlobalTransform), (With<PlayerBody>, Without<Dead>)>,
+    mut died: EventWriter<PlayerDied>,
+) {
+    for (entity, transform) in player_query.iter() {
+        if transform.translation().y < settings.kill_height {
+            commands.entity(entity).insert(Dead);
+            died.send(PlayerDied { entity });
+        }
+    }
+}
+
+pub fn handle_player_death(
+    mut commands: Commands,
+    settings: Res<RespawnSettings>,
+    mut state: ResMut<RespawnState>,
+    mut died: EventReader<PlayerDied>,
+    local_players: Query<(), With<LocalPlayer>>,
+    match_phase: Res<State<MatchPhase>>,
+    mut app_state_next_state: ResMut<NextState<AppState>>,
+) {
+    for event in died.read() {
+        // only the local player uses up lives, bots and remote players respawn on their own
+        if !local_players.contains(event.entity) {
+            continue;
+        }
+        let Some(entity) = commands.get_entity(event.entity) else {
+            // already handled, e.g. killed twice in the same frame
+            continue;
+        };
+        entity.despawn_recursive();
+        state.timer = Timer::from_seconds(settings.delay, TimerMode::Once);
+
+        // lives only count in the survival mode, matches end by their own rules
+        if *match_phase.get() != MatchPhase::Inactive {
+            continue;
+        }
+        state.lives = state.lives.saturating_sub(1);
+        if state.lives == 0 {
+            app_state_next_state.set(AppState::GameOver);
+            return;
+        }
+    }
+}
+
+pub fn respawn_player(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<RespawnSettings>,
+    mut state: ResMut<RespawnState>,
+    mut roster: ResMut<MatchRoster>,
+    spawns: Query<(&PlayerSpawn, &GlobalTransform)>,
+    players: Query<&GlobalTransform, (With<PlayerBody>, Without<Dead>)>,
+    player_query: Query<(), With<LocalPlayer>>,
+) {
+    if !player_query.is_empty() || state.lives == 0 {
+        return;
+    }
+
+    if !state.timer.tick(time.delta()).finished() {
+        return;
+    }
+
+    let team = roster.join(Participant::Local, "Player", settings.team);
+    let transform = choose_spawn(team, &spawns, &occupied_positions(&players)).unwrap_or_else(|| {
+        warn!("no player spawn found, falling back to the origin");
+        Transform::from_xyz(0., 5., 0.)
+    });
+    let entity = build_player(&mut commands, transform);
+    commands.entity(entity).insert((Participant::Local, Team(team)));
+}
+
+/// Spawns closer than this to an occupied position are only used if there is no other.
+pub const SPAWN_CLEARANCE: f32 = 1.0;
+
+pub fn choose_spawn(
+    team: u8,
+    spawns: &Query<(&PlayerSpawn, &GlobalTransform)>,
+    occupied: &[Vec3],
+) -> Option<Transform> {
+    let usable: Vec<_> = spawns.iter().filter(|(spawn, _)| spawn.team == 0 || spawn.team == team).collect();
+    let free: Vec<_> = usable
+        .iter()
+        .copied()
+        .filter(|(_, transform)| {
+            occupied.iter().all(|position| position.distance(transform.translation()) > SPAWN_CLEARANCE)
+        })
+        .collect();
+    let candidates = if free.is_empty() { usable } else { free };
+    let priority = candidates.iter().map(|(spawn, _)| spawn.priority).max()?;
+    let best: Vec<_> = candidates.into_iter().filter(|(spawn, _)| spawn.priority == priority).collect();
+    let (_, transform) = best.choose(&mut rand::thread_rng())?;
+
+    // Only keep the heading, the player must stand upright.
+    let (_, rotation, translation) = transform.to_scale_rotation_translation();
+    let (yaw, _, _) = rotation.to_euler(EulerRot::YXZ);
+    Some(Transform::from_translation(translation).with_rotation(Quat::from_rotation_y(yaw)))
+}
+
+/// Where the living players are, for `choose_spawn`.
+pub fn occupied_positions(players: &Query<&GlobalTransform, (With<PlayerBody>, Without<Dead>)>) -> Vec<Vec3> {
+    players.iter().map(|transform| transform.translation()).collect()
+}
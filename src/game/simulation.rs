@@ -0,0 +1,259 @@
+use bevy::ecs::schedule::ScheduleLabel;
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use bevy_rapier3d::prelude::*;
+use bevy_tnua::control_helpers::TnuaCrouchEnforcerPlugin;
+use bevy_tnua::prelude::*;
+use bevy_tnua_rapier3d::TnuaRapier3dPlugin;
+
+use crate::app::AppState;
+use crate::game::player::{execute_move, PlayerBody};
+
+#[derive(Resource, Reflect, Debug)]
+#[reflect(Resource)]
+/// Settings of the fixed player / physics simulation. Input is still sampled every frame.
+pub struct SimulationSettings {
+    /// Simulation ticks per second.
+    pub tick_rate: f64,
+}
+
+impl Default for SimulationSettings {
+    fn default() -> Self {
+        SimulationSettings { tick_rate: 64.0 }
+    }
+}
+
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+/// Stages of one simulation tick in `FixedUpdate`.
+pub enum SimulationSet {
+    /// Everything which feeds the character controller, e.g. `execute_move`.
+    Movement,
+    /// The tnua controller pipeline.
+    Controller,
+    /// Runs after rapier has written back the new positions.
+    Record,
+}
+
+#[derive(Resource, Default, Debug, Deref)]
+/// Number of the current simulation tick, counted up after every tick.
+pub struct SimulationTick(pub u64);
+
+#[derive(Component, Default, Debug)]
+/// Physics positions of the last two ticks, used to render in-between them.
+pub struct PhysicsInterpolation {
+    previous: Vec3,
+    current: Vec3,
+}
+
+impl PhysicsInterpolation {
+    pub fn new(translation: Vec3) -> Self {
+        PhysicsInterpolation {
+            previous: translation,
+            current: translation,
+        }
+    }
+}
+
+#[derive(Component, Debug, Deref, DerefMut)]
+/// Local offset of a child of an interpolated body (e.g. the camera).
+/// The child is moved every frame so that it follows the interpolated position of its parent.
+pub struct InterpolatedOffset(pub Vec3);
+
+#[derive(ScheduleLabel, Debug, Hash, PartialEq, Eq, Clone)]
+/// Replays one tick of the player movement without anything else of `FixedUpdate`,
+/// run by the client prediction to catch up again after a correction of the server.
+pub struct Resimulate;
+
+/// Tnua 0.13 only knows how to run in `Update`. To step it together with rapier, its plugins are
+/// built into a scratch app and the resulting schedule is run from `FixedUpdate` instead.
+#[derive(Resource)]
+struct TnuaFixedSchedule(Schedule);
+
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        let mut tnua_app = App::new();
+        tnua_app.add_plugins((TnuaRapier3dPlugin, TnuaControllerPlugin, TnuaCrouchEnforcerPlugin));
+        let tnua_schedule = tnua_app.world.resource_mut::<Schedules>()
+            .remove(Update)
+            .expect("tnua should add its systems to Update");
+
+        app.register_type::<SimulationSettings>()
+            .init_resource::<SimulationSettings>()
+            .init_resource::<SimulationTick>()
+            .insert_resource(TnuaFixedSchedule(tnua_schedule))
+            .add_plugins(RapierPhysicsPlugin::<NoUserData>::default().with_default_system_setup(false))
+            .init_schedule(Resimulate)
+
+            .add_systems(FixedUpdate, (
+                record_physics_positions.in_set(SimulationSet::Record),
+                advance_tick.in_set(SimulationSet::Record).after(record_physics_positions),
+            ).run_if(in_state(AppState::Game)))
+
+            .add_systems(PreUpdate, apply_simulation_settings.run_if(resource_changed::<SimulationSettings>()))
+            .add_systems(PostUpdate, interpolate_offsets.before(TransformSystem::TransformPropagate));
+
+        add_step_systems(app, FixedUpdate);
+        add_step_systems(app, Resimulate);
+    }
+}
+
+/// The player movement and physics part of a tick, shared by `FixedUpdate` and `Resimulate`.
+fn add_step_systems(app: &mut App, schedule: impl ScheduleLabel + Clone) {
+    app.configure_sets(schedule.clone(), (
+        SimulationSet::Movement,
+        SimulationSet::Controller,
+        PhysicsSet::SyncBackend,
+        PhysicsSet::SyncBackendFlush,
+        PhysicsSet::StepSimulation,
+        PhysicsSet::Writeback,
+        SimulationSet::Record,
+    ).chain())
+        .add_systems(schedule.clone(), (
+            RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::SyncBackend)
+                .in_set(PhysicsSet::SyncBackend),
+            RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::SyncBackendFlush)
+                .in_set(PhysicsSet::SyncBackendFlush),
+            RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::StepSimulation)
+                .in_set(PhysicsSet::StepSimulation),
+            RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::Writeback)
+                .in_set(PhysicsSet::Writeback),
+        ))
+        .add_systems(schedule, (
+            execute_move.in_set(SimulationSet::Movement),
+            run_tnua_schedule.in_set(SimulationSet::Controller),
+        ).run_if(in_state(AppState::Game)));
+}
+
+pub fn apply_simulation_settings(
+    settings: Res<SimulationSettings>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+) {
+    fixed_time.set_timestep_hz(settings.tick_rate);
+    rapier_config.timestep_mode = TimestepMode::Fixed {
+        dt: (1.0 / settings.tick_rate) as f32,
+        substeps: 1,
+    };
+}
+
+fn run_tnua_schedule(world: &mut World) {
+    world.resource_scope(|world, mut schedule: Mut<TnuaFixedSchedule>| {
+        schedule.0.run(world);
+    });
+}
+
+pub fn record_physics_positions(mut bodies: Query<(&mut PhysicsInterpolation, &Transform)>) {
+    for (mut interpolation, transform) in bodies.iter_mut() {
+        interpolation.previous = interpolation.current;
+        interpolation.current = transform.translation;
+    }
+}
+
+pub fn advance_tick(mut tick: ResMut<SimulationTick>) {
+    tick.0 += 1;
+}
+
+pub fn interpolate_offsets(
+    fixed_time: Res<Time<Fixed>>,
+    bodies: Query<(&PhysicsInterpolation, &Transform), Without<InterpolatedOffset>>,
+    mut children: Query<(&mut Transform, &InterpolatedOffset, &Parent)>,
+) {
+    let alpha = fixed_time.overstep_percentage();
+    for (mut transform, offset, parent) in children.iter_mut() {
+        let Ok((interpolation, body_transform)) = bodies.get(parent.get()) else {
+            continue;
+        };
+
+        // Move the child back by the distance the body is ahead of the rendered time.
+        let interpolated = interpolation.previous.lerp(interpolation.current, alpha);
+        let error = interpolated - body_transform.translation;
+        transform.translation = offset.0 + body_transform.rotation.inverse() * error;
+    }
+}
+
+/// An app with the simulation but without a window or assets, already on its way into
+/// `AppState::Game`. Time advances by one frame at the given rate on every update.
+#[cfg(test)]
+pub(crate) fn headless_app(frame_rate: u32) -> App {
+    use std::time::Duration;
+
+    use bevy::scene::ScenePlugin;
+    use bevy::time::TimeUpdateStrategy;
+
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, TransformPlugin, HierarchyPlugin, AssetPlugin::default(), ScenePlugin))
+        .add_state::<AppState>()
+        .init_asset::<Mesh>()
+        .init_asset::<StandardMaterial>()
+        .insert_resource(SimulationSettings { tick_rate: 64.0 })
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs(1) / frame_rate))
+        .add_plugins(SimulationPlugin);
+    app.world.resource_mut::<NextState<AppState>>().set(AppState::Game);
+    app
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::player::build_player;
+
+    use super::*;
+
+    fn spawn_level(mut commands: Commands) {
+        commands.spawn((
+            TransformBundle::from_transform(Transform::from_xyz(0.0, -0.5, 0.0)),
+            Collider::cuboid(50.0, 0.5, 50.0),
+        ));
+        build_player(&mut commands, Transform::from_xyz(0.0, 5.0, 0.0));
+    }
+
+    // Inputs only change at multiples of the slowest frame time, so every render rate
+    // sees the same input at every tick.
+    fn input_script(time: Res<Time<Virtual>>, mut players: Query<&mut PlayerBody>) {
+        let elapsed = time.elapsed_seconds();
+        for mut body in players.iter_mut() {
+            body.jump = false;
+            body.desired_velocity = Vec3::ZERO;
+            if elapsed <= 1.0 {
+                // wait until landed
+            } else if elapsed <= 2.0 {
+                body.desired_velocity = Vec3::NEG_Z * 3.0;
+            } else if elapsed <= 2.25 {
+                body.desired_velocity = Vec3::NEG_Z * 3.0;
+                body.jump = true;
+            } else if elapsed <= 3.0 {
+                body.desired_velocity = Vec3::X * 3.0;
+            }
+        }
+    }
+
+    fn simulate(frame_rate: u32) -> Vec3 {
+        let mut app = headless_app(frame_rate);
+        app.add_systems(Startup, spawn_level)
+            .add_systems(PreUpdate, input_script);
+
+        for _ in 0..(4 * frame_rate) {
+            app.update();
+        }
+
+        app.world
+            .query_filtered::<&Transform, With<PlayerBody>>()
+            .single(&app.world)
+            .translation
+    }
+
+    #[test]
+    fn movement_does_not_depend_on_frame_rate() {
+        let reference = simulate(64);
+        assert!(reference.distance(Vec3::new(0.0, 5.0, 0.0)) > 1.0, "the player should have moved");
+
+        for frame_rate in [32, 128, 256] {
+            let position = simulate(frame_rate);
+            assert!(
+                position.distance(reference) < 1e-3,
+                "{frame_rate} fps ended at {position}, 64 fps at {reference}"
+            );
+        }
+    }
+}
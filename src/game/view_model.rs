@@ -0,0 +1,224 @@
+use bevy::core_pipeline::clear_color::ClearColorConfig;
+use bevy::core_pipeline::core_3d::Camera3dDepthLoadOp;
+use bevy::input::mouse::MouseMotion;
+use bevy::prelude::*;
+use bevy::render::view::RenderLayers;
+use bevy_rapier3d::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::app::AppState;
+use crate::game::player::{apply_mouse, LocalPlayer, PlayerCamera};
+use crate::game::weapons::{fire_weapons, Weapon, WeaponDefinition, WeaponFired};
+
+/// Render layer of everything held in first person.
+pub const VIEW_MODEL_LAYER: u8 = 1;
+
+#[derive(Resource, Reflect, Debug)]
+#[reflect(Resource)]
+pub struct ViewModelSettings {
+    /// Vertical field of view of the view model camera in degrees.
+    pub fov: f32,
+    /// Offset of the view model from the camera.
+    pub offset: Vec3,
+    /// How far the view model lags behind mouse movement.
+    pub sway_amount: f32,
+    pub max_sway: f32,
+    /// How fast sway and recoil kick return to the rest position.
+    pub recovery: f32,
+    pub bob_amount: f32,
+    /// Bob cycles per meter moved.
+    pub bob_frequency: f32,
+}
+
+impl Default for ViewModelSettings {
+    fn default() -> Self {
+        ViewModelSettings {
+            fov: 70.0,
+            offset: Vec3::new(0.25, -0.2, -0.5),
+            sway_amount: 0.0005,
+            max_sway: 0.05,
+            recovery: 10.0,
+            bob_amount: 0.015,
+            bob_frequency: 0.6,
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Debug, Clone, Copy)]
+/// Recoil per shot, part of a `WeaponDefinition`.
+pub struct RecoilDefinition {
+    /// Upwards look rotation in radians.
+    pub pitch: f32,
+    /// Maximum sideways look rotation in radians, the direction is random.
+    pub yaw: f32,
+    /// Distance the view model is pushed back.
+    pub kick: f32,
+}
+
+#[derive(Component, Reflect, Default, Debug)]
+#[reflect(Component)]
+/// Recoil of a player, kicks the look direction and the view model.
+pub struct Recoil {
+    /// Look rotation (yaw, pitch) in radians which `apply_mouse` still has to apply.
+    look: Vec2,
+    /// Current view model kick, recovers over time.
+    pub kick: f32,
+}
+
+impl Recoil {
+    pub fn add_impulse(&mut self, pitch: f32, yaw: f32, kick: f32) {
+        self.look += Vec2::new(yaw, pitch);
+        self.kick += kick;
+    }
+
+    /// Returns the pending look rotation and resets it.
+    pub fn take_look(&mut self) -> Vec2 {
+        std::mem::take(&mut self.look)
+    }
+}
+
+#[derive(Component, Debug)]
+/// Renders the view model on top of the world.
+pub struct ViewModelCamera;
+
+#[derive(Component, Default, Debug)]
+/// The entity held in first person, a child of the `PlayerCamera`.
+pub struct ViewModel {
+    sway: Vec2,
+    bob_phase: f32,
+}
+
+pub struct ViewModelPlugin;
+
+impl Plugin for ViewModelPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<ViewModelSettings>()
+            .register_type::<Recoil>()
+            .init_resource::<ViewModelSettings>()
+            .add_systems(Update, (
+                spawn_view_model,
+                apply_weapon_recoil.after(fire_weapons).before(apply_mouse),
+                update_view_model.after(apply_weapon_recoil),
+            ).run_if(in_state(AppState::Game)));
+    }
+}
+
+pub fn spawn_view_model(
+    mut commands: Commands,
+    settings: Res<ViewModelSettings>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    added_cameras: Query<Entity, Added<PlayerCamera>>,
+) {
+    for camera in added_cameras.iter() {
+        commands.entity(camera).with_children(|builder| {
+            // Drawn after the main camera with a cleared depth buffer, so it never clips into walls.
+            builder.spawn((
+                Name::new("ViewModelCamera"),
+                ViewModelCamera,
+                Camera3dBundle {
+                    camera: Camera {
+                        order: 1,
+                        ..default()
+                    },
+                    camera_3d: Camera3d {
+                        clear_color: ClearColorConfig::None,
+                        depth_load_op: Camera3dDepthLoadOp::Clear(0.0),
+                        ..default()
+                    },
+                    projection: Projection::Perspective(PerspectiveProjection {
+                        fov: settings.fov.to_radians(),
+                        near: 0.01,
+                        far: 10.0,
+                        ..default()
+                    }),
+                    ..default()
+                },
+                UiCameraConfig { show_ui: false },
+                RenderLayers::layer(VIEW_MODEL_LAYER),
+            ));
+
+            // Placeholder until there are weapon models.
+            builder.spawn((
+                Name::new("ViewModel"),
+                ViewModel::default(),
+                PbrBundle {
+                    mesh: meshes.add(Mesh::from(shape::Box::new(0.08, 0.1, 0.4))),
+                    material: materials.add(StandardMaterial {
+                        base_color: Color::rgb(0.2, 0.2, 0.22),
+                        perceptual_roughness: 0.6,
+                        ..default()
+                    }),
+                    transform: Transform::from_translation(settings.offset),
+                    ..default()
+                },
+                RenderLayers::layer(VIEW_MODEL_LAYER),
+            ));
+        });
+    }
+}
+
+pub fn apply_weapon_recoil(
+    definitions: Res<Assets<WeaponDefinition>>,
+    mut fired: EventReader<WeaponFired>,
+    mut shooters: Query<(&Weapon, &mut Recoil)>,
+) {
+    let mut rng = rand::thread_rng();
+
+    for event in fired.read() {
+        let Ok((weapon, mut recoil)) = shooters.get_mut(event.shooter) else {
+            continue;
+        };
+        let Some(definition) = definitions.get(&weapon.definition) else {
+            continue;
+        };
+
+        let recoil_definition = definition.recoil;
+        let yaw = rng.gen_range(-recoil_definition.yaw..=recoil_definition.yaw);
+        recoil.add_impulse(recoil_definition.pitch, yaw, recoil_definition.kick);
+    }
+}
+
+pub fn update_view_model(
+    time: Res<Time>,
+    settings: Res<ViewModelSettings>,
+    mut input: EventReader<MouseMotion>,
+    mut player_query: Query<(&Velocity, &mut Recoil), With<LocalPlayer>>,
+    mut view_models: Query<(&mut Transform, &mut ViewModel)>,
+) {
+    let mouse_move: Vec2 = input.read().map(|motion| motion.delta).sum();
+    let Ok((velocity, mut recoil)) = player_query.get_single_mut() else {
+        return;
+    };
+    let recovery = (settings.recovery * time.delta_seconds()).min(1.0);
+    recoil.kick *= 1.0 - recovery;
+
+    let speed = Vec2::new(velocity.linvel.x, velocity.linvel.z).length();
+
+    for (mut transform, mut view_model) in view_models.iter_mut() {
+        // Sway lags behind the mouse and springs back to the rest position.
+        view_model.sway = (view_model.sway - mouse_move * settings.sway_amount)
+            .clamp_length_max(settings.max_sway)
+            .lerp(Vec2::ZERO, recovery);
+
+        view_model.bob_phase += speed * settings.bob_frequency * std::f32::consts::TAU * time.delta_seconds();
+        let bob_strength = (speed / 3.0).min(1.0) * settings.bob_amount;
+        let bob = Vec3::new(
+            view_model.bob_phase.cos() * bob_strength,
+            (view_model.bob_phase * 2.0).sin().abs() * bob_strength,
+            0.0,
+        );
+
+        transform.translation = settings.offset
+            + Vec3::new(view_model.sway.x, -view_model.sway.y, 0.0)
+            + bob
+            + Vec3::Z * recoil.kick;
+        transform.rotation = Quat::from_euler(
+            EulerRot::YXZ,
+            view_model.sway.x * 2.0,
+            -view_model.sway.y * 2.0 + recoil.kick * 2.0,
+            0.0,
+        );
+    }
+}
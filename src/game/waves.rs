@@ -0,0 +1,315 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use bevy_asset_loader::prelude::*;
+use bevy_common_assets::ron::RonAssetPlugin;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::app::AppState;
+use crate::game::enemies::{build_enemy, kill_enemies, Enemy, EnemyKilled};
+use crate::game::network::is_offline;
+use crate::game::world::world_ready;
+
+#[derive(Asset, TypePath, Deserialize, Debug, Clone)]
+/// A sequence of enemy waves, loaded from `assets/encounters/*.encounter.ron`.
+pub struct Encounter {
+    pub id: String,
+    /// Seconds before the first and between all following waves.
+    pub intermission: f32,
+    /// Enemies alive at the same time, unless the wave has its own limit.
+    pub max_alive: u32,
+    pub waves: Vec<WaveDefinition>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct WaveDefinition {
+    #[serde(default)]
+    pub name: String,
+    /// Seconds between the start of the wave and the first spawn.
+    #[serde(default)]
+    pub start_delay: f32,
+    /// Seconds between two spawns.
+    pub spawn_delay: f32,
+    #[serde(default)]
+    pub max_alive: Option<u32>,
+    pub groups: Vec<EnemyGroup>,
+    #[serde(default)]
+    pub clear: WaveCondition,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct EnemyGroup {
+    pub count: u32,
+    /// Only spawners with this tag are used, any spawner if empty.
+    #[serde(default)]
+    pub spawner: String,
+    /// Stats of the enemies, missing fields use the `Enemy` defaults.
+    #[serde(default)]
+    pub enemy: Enemy,
+}
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+/// When the next wave starts.
+pub enum WaveCondition {
+    /// Every enemy of the wave was spawned and killed.
+    #[default]
+    AllDead,
+    /// Seconds after the start of the wave, enemies which are still alive stay.
+    Timer(f32),
+    /// Whatever happens first.
+    AllDeadOrTimer(f32),
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct EncounterAssets {
+    #[asset(path = "encounters", collection(typed))]
+    pub encounters: Vec<Handle<Encounter>>,
+}
+
+impl EncounterAssets {
+    pub fn find<'a>(&self, encounters: &'a Assets<Encounter>, id: &str) -> Option<&'a Encounter> {
+        self.encounters
+            .iter()
+            .filter_map(|handle| encounters.get(handle))
+            .find(|encounter| encounter.id == id)
+    }
+}
+
+#[derive(Component, Reflect, Default, Debug)]
+#[reflect(Component)]
+/// Where the wave director spawns enemies, placed in Blender.
+pub struct EnemySpawner {
+    /// Enemy groups can be restricted to spawners with a specific tag.
+    pub tag: String,
+    /// Enemies spawn at a random position within this distance.
+    pub radius: f32,
+}
+
+#[derive(Component, Reflect, Default, Debug)]
+#[reflect(Component)]
+/// Place it anywhere in a level in Blender to run another encounter on it.
+pub struct LevelEncounter {
+    pub encounter: String,
+}
+
+#[derive(Resource, Reflect, Debug, Deref, DerefMut)]
+#[reflect(Resource)]
+/// The encounter of the survival mode. It only runs on levels with enemy spawners.
+pub struct ActiveEncounter(pub String);
+
+impl Default for ActiveEncounter {
+    fn default() -> Self {
+        ActiveEncounter("survival".to_string())
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum DirectorPhase {
+    /// Until the world and the encounter are loaded.
+    #[default]
+    Waiting,
+    /// Seconds until the next wave starts.
+    Intermission(f32),
+    Running,
+    Completed,
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct WaveDirector {
+    pub phase: DirectorPhase,
+    /// Index of the current (or next) wave.
+    pub wave: usize,
+    /// Seconds since the wave started.
+    pub wave_time: f32,
+    /// Enemies of the wave which were not spawned yet, with the spawner tag.
+    pending: VecDeque<(String, Enemy)>,
+    spawn_cooldown: f32,
+    alive: HashSet<Entity>,
+}
+
+impl WaveDirector {
+    pub fn alive(&self) -> usize {
+        self.alive.len()
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.alive.len() + self.pending.len()
+    }
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct WaveStarted {
+    pub index: usize,
+    pub name: String,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct WaveCleared {
+    pub index: usize,
+    pub name: String,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+/// The last wave was cleared.
+pub struct EncounterCompleted;
+
+pub struct WavesPlugin;
+
+impl Plugin for WavesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(RonAssetPlugin::<Encounter>::new(&["encounter.ron"]))
+            .add_collection_to_loading_state::<_, EncounterAssets>(AppState::AssetLoading)
+            .register_type::<EnemySpawner>()
+            .register_type::<LevelEncounter>()
+            .register_type::<ActiveEncounter>()
+            .init_resource::<ActiveEncounter>()
+            .init_resource::<WaveDirector>()
+            .add_event::<WaveStarted>()
+            .add_event::<WaveCleared>()
+            .add_event::<EncounterCompleted>()
+            .add_systems(OnEnter(AppState::Game), reset_wave_director)
+            .add_systems(Update, (
+                apply_level_encounter,
+                track_wave_enemies.after(kill_enemies),
+                // enemies are not replicated yet, so there are no waves in network games
+                run_wave_director
+                    .after(apply_level_encounter)
+                    .after(track_wave_enemies)
+                    .run_if(is_offline)
+                    .run_if(world_ready),
+            ).run_if(in_state(AppState::Game)));
+    }
+}
+
+pub fn reset_wave_director(mut commands: Commands) {
+    commands.insert_resource(WaveDirector::default());
+}
+
+pub fn apply_level_encounter(
+    mut active_encounter: ResMut<ActiveEncounter>,
+    level_encounters: Query<&LevelEncounter, Added<LevelEncounter>>,
+) {
+    for level_encounter in level_encounters.iter() {
+        active_encounter.0 = level_encounter.encounter.clone();
+    }
+}
+
+pub fn track_wave_enemies(mut director: ResMut<WaveDirector>, mut killed: EventReader<EnemyKilled>) {
+    for event in killed.read() {
+        director.alive.remove(&event.entity);
+    }
+}
+
+pub fn run_wave_director(
+    mut commands: Commands,
+    time: Res<Time>,
+    active_encounter: Res<ActiveEncounter>,
+    encounter_assets: Res<EncounterAssets>,
+    encounters: Res<Assets<Encounter>>,
+    mut director: ResMut<WaveDirector>,
+    spawners: Query<(&EnemySpawner, &GlobalTransform)>,
+    enemies: Query<(), With<Enemy>>,
+    mut started: EventWriter<WaveStarted>,
+    mut cleared: EventWriter<WaveCleared>,
+    mut completed: EventWriter<EncounterCompleted>,
+) {
+    let director = director.as_mut();
+    let Some(encounter) = encounter_assets.find(&encounters, &active_encounter) else {
+        return;
+    };
+    // enemies despawned some other way, e.g. by falling out of the world
+    director.alive.retain(|entity| enemies.contains(*entity));
+
+    match director.phase {
+        DirectorPhase::Waiting => {
+            // Levels without spawners don't have a survival mode.
+            if !spawners.is_empty() {
+                director.phase = DirectorPhase::Intermission(encounter.intermission);
+            }
+        }
+        DirectorPhase::Intermission(remaining) => {
+            let remaining = remaining - time.delta_seconds();
+            if remaining > 0.0 {
+                director.phase = DirectorPhase::Intermission(remaining);
+                return;
+            }
+
+            let Some(wave) = encounter.waves.get(director.wave) else {
+                director.phase = DirectorPhase::Completed;
+                return;
+            };
+            director.pending = wave.groups
+                .iter()
+                .flat_map(|group| (0..group.count).map(|_| (group.spawner.clone(), group.enemy.clone())))
+                .collect();
+            director.spawn_cooldown = wave.start_delay;
+            director.wave_time = 0.0;
+            director.phase = DirectorPhase::Running;
+            started.send(WaveStarted {
+                index: director.wave,
+                name: wave.name.clone(),
+            });
+        }
+        DirectorPhase::Running => {
+            let Some(wave) = encounter.waves.get(director.wave) else {
+                director.phase = DirectorPhase::Completed;
+                return;
+            };
+            director.wave_time += time.delta_seconds();
+            director.spawn_cooldown -= time.delta_seconds();
+
+            let max_alive = wave.max_alive.unwrap_or(encounter.max_alive) as usize;
+            if director.spawn_cooldown <= 0.0 && director.alive.len() < max_alive {
+                if let Some((tag, enemy)) = director.pending.pop_front() {
+                    director.spawn_cooldown = wave.spawn_delay;
+
+                    let candidates: Vec<_> = spawners
+                        .iter()
+                        .filter(|(spawner, _)| tag.is_empty() || spawner.tag == tag)
+                        .collect();
+                    if candidates.is_empty() {
+                        warn!("no enemy spawner with tag `{}` found", tag);
+                    } else {
+                        let mut rng = rand::thread_rng();
+                        let (spawner, spawner_transform) = candidates[rng.gen_range(0..candidates.len())];
+                        let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+                        let distance = rng.gen_range(0.0..=spawner.radius.max(0.0));
+                        let offset = Vec3::new(angle.cos(), 0.0, angle.sin()) * distance;
+
+                        let (_, rotation, translation) = spawner_transform.to_scale_rotation_translation();
+                        let transform = Transform::from_translation(translation + offset).with_rotation(rotation);
+                        let entity = build_enemy(&mut commands, transform, enemy);
+                        director.alive.insert(entity);
+                    }
+                }
+            }
+
+            let all_dead = director.pending.is_empty() && director.alive.is_empty();
+            let is_cleared = match wave.clear {
+                WaveCondition::AllDead => all_dead,
+                WaveCondition::Timer(seconds) => director.wave_time >= seconds,
+                WaveCondition::AllDeadOrTimer(seconds) => all_dead || director.wave_time >= seconds,
+            };
+            if !is_cleared {
+                return;
+            }
+
+            // a timer ends the wave, enemies which were not spawned yet are skipped
+            director.pending.clear();
+            cleared.send(WaveCleared {
+                index: director.wave,
+                name: wave.name.clone(),
+            });
+            director.wave += 1;
+            if director.wave < encounter.waves.len() {
+                director.phase = DirectorPhase::Intermission(encounter.intermission);
+            } else {
+                director.phase = DirectorPhase::Completed;
+                completed.send(EncounterCompleted);
+            }
+        }
+        DirectorPhase::Completed => {}
+    }
+}
@@ -0,0 +1,309 @@
+use bevy::prelude::*;
+use bevy_asset_loader::prelude::*;
+use bevy_common_assets::ron::RonAssetPlugin;
+use bevy_rapier3d::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::app::AppState;
+use crate::game::aim::ScopeDefinition;
+use crate::game::health::{apply_damage, find_health_owner, DamageEvent, DamageKind, Health};
+use crate::game::inventory::{Inventory, Loadout};
+use crate::game::network::has_authority;
+use crate::game::network::lag_compensation::LagCompensation;
+use crate::game::player::{LocalPlayer, PlayerView};
+use crate::game::projectiles::{LaunchProjectile, ProjectileDefinition};
+use crate::game::view_model::RecoilDefinition;
+
+#[derive(Asset, TypePath, Deserialize, Debug, Clone)]
+/// Weapon stats, loaded from `assets/weapons/*.weapon.ron`.
+pub struct WeaponDefinition {
+    pub id: String,
+    pub name: String,
+    /// Shots per second.
+    pub fire_rate: f32,
+    /// Keep firing while the trigger is held.
+    pub automatic: bool,
+    /// Maximum deviation from the aim direction in radians.
+    pub spread: f32,
+    /// Rays per shot, e.g. for shotguns.
+    pub pellets: u32,
+    pub range: f32,
+    pub damage: f32,
+    /// Distance from which the damage starts to drop.
+    pub falloff_start: f32,
+    /// Distance at which only `min_damage_factor` of the damage is left.
+    pub falloff_end: f32,
+    pub min_damage_factor: f32,
+    pub magazine_size: u32,
+    pub reload_time: f32,
+    /// Ammo pool used for reloading, shared by all weapons with the same type.
+    /// Weapons without an ammo type have unlimited reserve ammo.
+    #[serde(default)]
+    pub ammo_type: Option<String>,
+    /// Seconds until the weapon can be used after switching to it.
+    #[serde(default)]
+    pub draw_time: f32,
+    /// Seconds to put the weapon away when switching to another one.
+    #[serde(default)]
+    pub holster_time: f32,
+    /// Kick per shot, applied to the look direction and the view model.
+    #[serde(default)]
+    pub recoil: RecoilDefinition,
+    /// Aiming with a scope shows an overlay and allows stronger zoom levels.
+    #[serde(default)]
+    pub scope: Option<ScopeDefinition>,
+    /// Launch physical projectiles instead of hitscan rays.
+    #[serde(default)]
+    pub projectile: Option<ProjectileDefinition>,
+}
+
+impl WeaponDefinition {
+    pub fn damage_at(&self, distance: f32) -> f32 {
+        let falloff = ((distance - self.falloff_start) / (self.falloff_end - self.falloff_start).max(f32::EPSILON))
+            .clamp(0.0, 1.0);
+        self.damage * (1.0 - falloff * (1.0 - self.min_damage_factor))
+    }
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct WeaponAssets {
+    #[asset(path = "weapons", collection(typed))]
+    pub definitions: Vec<Handle<WeaponDefinition>>,
+    #[asset(path = "loadouts", collection(typed))]
+    pub loadouts: Vec<Handle<Loadout>>,
+}
+
+impl WeaponAssets {
+    pub fn find(&self, definitions: &Assets<WeaponDefinition>, id: &str) -> Option<Handle<WeaponDefinition>> {
+        self.definitions
+            .iter()
+            .find(|handle| definitions.get(*handle).is_some_and(|definition| definition.id == id))
+            .cloned()
+    }
+
+    pub fn find_loadout<'a>(&self, loadouts: &'a Assets<Loadout>, id: &str) -> Option<&'a Loadout> {
+        self.loadouts
+            .iter()
+            .filter_map(|handle| loadouts.get(handle))
+            .find(|loadout| loadout.id == id)
+    }
+}
+
+#[derive(Component, Reflect, Default, Debug)]
+#[reflect(Component)]
+/// The weapon state of a shooter. All stats come from the `WeaponDefinition`.
+pub struct Weapon {
+    pub definition: Handle<WeaponDefinition>,
+    pub magazine: u32,
+    /// Seconds until the next shot is possible.
+    pub cooldown: f32,
+    /// Seconds until the reload is done.
+    pub reloading: Option<f32>,
+    /// Set while the weapon is put away to switch to another one.
+    pub holstering: bool,
+
+    pub trigger_held: bool,
+    pub trigger_pressed: bool,
+    pub reload_requested: bool,
+}
+
+impl Weapon {
+    pub fn new(definition: Handle<WeaponDefinition>, magazine: u32) -> Self {
+        Weapon {
+            definition,
+            magazine,
+            ..default()
+        }
+    }
+}
+
+#[derive(Component, Reflect, Debug, Deref, DerefMut)]
+#[reflect(Component)]
+/// Damage multiplier of a collider, e.g. `2.0` on a head hitbox.
+pub struct HitboxMultiplier(pub f32);
+
+impl Default for HitboxMultiplier {
+    fn default() -> Self {
+        HitboxMultiplier(1.0)
+    }
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct WeaponFired {
+    pub shooter: Entity,
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct WeaponHit {
+    pub shooter: Entity,
+    /// The collider which was hit.
+    pub entity: Entity,
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub distance: f32,
+    /// Collider specific multiplier, already included in `damage`.
+    pub multiplier: f32,
+    pub damage: f32,
+}
+
+pub struct WeaponsPlugin;
+
+impl Plugin for WeaponsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(RonAssetPlugin::<WeaponDefinition>::new(&["weapon.ron"]))
+            .add_collection_to_loading_state::<_, WeaponAssets>(AppState::AssetLoading)
+            .register_type::<Weapon>()
+            .register_type::<HitboxMultiplier>()
+            .add_event::<WeaponFired>()
+            .add_event::<WeaponHit>()
+            .add_systems(Update, (
+                apply_weapon_controls,
+                fire_weapons.after(apply_weapon_controls),
+                apply_weapon_hits.after(fire_weapons).before(apply_damage).run_if(has_authority),
+            ).run_if(in_state(AppState::Game)));
+    }
+}
+
+pub fn apply_weapon_controls(
+    mouse: Res<Input<MouseButton>>,
+    keyboard: Res<Input<KeyCode>>,
+    mut weapons: Query<&mut Weapon, With<LocalPlayer>>,
+) {
+    for mut weapon in weapons.iter_mut() {
+        weapon.trigger_held = mouse.pressed(MouseButton::Left);
+        weapon.trigger_pressed = mouse.just_pressed(MouseButton::Left);
+        weapon.reload_requested = keyboard.just_pressed(KeyCode::R);
+    }
+}
+
+pub fn fire_weapons(
+    time: Res<Time>,
+    rapier_context: Res<RapierContext>,
+    mut lag_compensation: LagCompensation,
+    definitions: Res<Assets<WeaponDefinition>>,
+    view_query: Query<(&GlobalTransform, &Parent), With<PlayerView>>,
+    mut weapons: Query<(&mut Weapon, Option<&mut Inventory>)>,
+    multipliers: Query<&HitboxMultiplier>,
+    mut fired: EventWriter<WeaponFired>,
+    mut hits: EventWriter<WeaponHit>,
+    mut launches: EventWriter<LaunchProjectile>,
+) {
+    let mut rng = rand::thread_rng();
+
+    for (view_transform, parent) in view_query.iter() {
+        let shooter = parent.get();
+        let Ok((mut weapon, mut inventory)) = weapons.get_mut(shooter) else {
+            continue;
+        };
+        let Some(definition) = definitions.get(&weapon.definition) else {
+            continue;
+        };
+
+        weapon.cooldown = (weapon.cooldown - time.delta_seconds()).max(0.0);
+        if weapon.holstering {
+            continue;
+        }
+
+        if let Some(remaining) = weapon.reloading {
+            let remaining = remaining - time.delta_seconds();
+            if remaining > 0.0 {
+                weapon.reloading = Some(remaining);
+                continue;
+            }
+            weapon.reloading = None;
+            let missing = definition.magazine_size.saturating_sub(weapon.magazine);
+            weapon.magazine += match (&mut inventory, &definition.ammo_type) {
+                (Some(inventory), Some(ammo_type)) => inventory.take_ammo(ammo_type, missing),
+                _ => missing,
+            };
+        }
+
+        let has_reserve = match (&inventory, &definition.ammo_type) {
+            (Some(inventory), Some(ammo_type)) => inventory.ammo_count(ammo_type) > 0,
+            _ => true,
+        };
+        let wants_to_fire = if definition.automatic { weapon.trigger_held } else { weapon.trigger_pressed };
+        let needs_reload = weapon.magazine == 0 && wants_to_fire;
+        if has_reserve && ((weapon.reload_requested && weapon.magazine < definition.magazine_size) || needs_reload) {
+            weapon.reloading = Some(definition.reload_time);
+            continue;
+        }
+        if !wants_to_fire || weapon.cooldown > 0.0 || weapon.magazine == 0 {
+            continue;
+        }
+
+        weapon.magazine -= 1;
+        weapon.cooldown = 1.0 / definition.fire_rate;
+
+        let origin = view_transform.translation();
+        let (_, aim, _) = view_transform.to_scale_rotation_translation();
+        fired.send(WeaponFired {
+            shooter,
+            origin,
+            direction: aim * Vec3::NEG_Z,
+        });
+
+        for _ in 0..definition.pellets.max(1) {
+            let deviation = Quat::from_euler(
+                EulerRot::YXZ,
+                rng.gen_range(-definition.spread..=definition.spread),
+                rng.gen_range(-definition.spread..=definition.spread),
+                0.0,
+            );
+            let direction = aim * deviation * Vec3::NEG_Z;
+
+            if let Some(projectile) = &definition.projectile {
+                launches.send(LaunchProjectile {
+                    shooter,
+                    definition: projectile.clone(),
+                    origin,
+                    direction,
+                });
+                continue;
+            }
+
+            // Shots of network clients hit the other players where the client saw them.
+            let filter = QueryFilter::new().exclude_sensors().exclude_rigid_body(shooter);
+            let Some((entity, intersection)) =
+                lag_compensation.cast_ray(&rapier_context, shooter, origin, direction, definition.range, filter)
+            else {
+                continue;
+            };
+
+            let multiplier = multipliers.get(entity).map_or(1.0, |multiplier| multiplier.0);
+            hits.send(WeaponHit {
+                shooter,
+                entity,
+                point: intersection.point,
+                normal: intersection.normal,
+                distance: intersection.toi,
+                multiplier,
+                damage: definition.damage_at(intersection.toi) * multiplier,
+            });
+        }
+    }
+}
+
+pub fn apply_weapon_hits(
+    mut hits: EventReader<WeaponHit>,
+    health_query: Query<(), With<Health>>,
+    parents: Query<&Parent>,
+    mut damage: EventWriter<DamageEvent>,
+) {
+    for hit in hits.read() {
+        let Some(target) = find_health_owner(hit.entity, &health_query, &parents) else {
+            continue;
+        };
+
+        damage.send(DamageEvent {
+            target,
+            source: Some(hit.shooter),
+            amount: hit.damage,
+            kind: DamageKind::Bullet,
+            hit_point: Some(hit.point),
+        });
+    }
+}
@@ -1,5 +1,6 @@
 use bevy::{prelude::*};
 use bevy::gltf::Gltf;
+use bevy::scene::{SceneInstance, SceneSpawner};
 
 use crate::app::MyAssets;
 
@@ -8,6 +9,22 @@ use crate::app::MyAssets;
 /// helper marker component
 pub struct LoadedMarker;
 
+#[derive(Component, Default, Debug)]
+/// Put on everything spawned for a game, it is despawned again when leaving `AppState::Game`.
+/// Only needed on entities without a parent, children go with them.
+pub struct GameEntity;
+
+pub fn despawn_game_entities(mut commands: Commands, entities: Query<Entity, With<GameEntity>>) {
+    for entity in entities.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Run condition for systems which need the whole level, e.g. its spawn markers.
+pub fn world_ready(scene_spawner: Res<SceneSpawner>, world_query: Query<&SceneInstance, With<LoadedMarker>>) -> bool {
+    world_query.iter().any(|instance| scene_spawner.instance_is_ready(**instance))
+}
+
 pub fn spawn_world(
     mut commands: Commands,
     assets: Res<MyAssets>,
@@ -26,13 +43,9 @@ pub fn spawn_world(
                     scene: gltf.scenes[0].clone(),
                     ..default()
                 },
-                LoadedMarker
+                LoadedMarker,
+                GameEntity,
             ));
         }
     }
-
-    commands.spawn(TextBundle::from_section(
-        "Press `Q` key to switch angular state",
-        TextStyle::default(),
-    ));
 }
\ No newline at end of file
@@ -0,0 +1,13 @@
+use bevy::prelude::*;
+
+#[derive(Component)]
+pub struct ResultsCamera {}
+
+#[derive(Component)]
+pub struct ResultsScreen {}
+
+#[derive(Component)]
+pub struct PlayAgainButton {}
+
+#[derive(Component)]
+pub struct MainMenuButton {}
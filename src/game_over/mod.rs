@@ -0,0 +1,30 @@
+use self::systems::{
+    camera::{release_cursor, remove_camera, spawn_camera},
+    interactions::{interact_with_main_menu_button, interact_with_play_again_button},
+    layout::{despawn_results_screen, spawn_results_screen},
+};
+use crate::app::AppState;
+use crate::main_menu::systems::interactions::animate_buttons;
+use bevy::prelude::*;
+
+pub mod components;
+pub mod styles;
+pub mod systems;
+
+/// Results of the last game in `AppState::GameOver`, with the way back into the game or to the menu.
+pub struct GameOverPlugin;
+
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::GameOver), (spawn_camera, spawn_results_screen, release_cursor))
+            .add_systems(
+                Update,
+                (
+                    animate_buttons,
+                    interact_with_play_again_button,
+                    interact_with_main_menu_button,
+                ).run_if(in_state(AppState::GameOver)),
+            )
+            .add_systems(OnExit(AppState::GameOver), (despawn_results_screen, remove_camera));
+    }
+}
@@ -0,0 +1,15 @@
+use bevy::prelude::*;
+
+pub const BUTTON_ROW_STYLE: Style = {
+    let mut style = Style::DEFAULT;
+    style.column_gap = Val::Px(16.0);
+    style
+};
+
+pub fn get_title_text_style(asset_server: &Res<AssetServer>) -> TextStyle {
+    TextStyle {
+        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+        font_size: 48.0,
+        color: Color::WHITE,
+    }
+}
@@ -0,0 +1,32 @@
+use bevy::prelude::*;
+use bevy::window::{CursorGrabMode, PrimaryWindow};
+
+use crate::game_over::components::ResultsCamera;
+
+// The camera of the player may still be there when a match ended, the results are drawn over it.
+pub fn spawn_camera(mut commands: Commands) {
+    commands.spawn((
+        Name::new("ResultsCamera"),
+        ResultsCamera {},
+        Camera2dBundle {
+            camera: Camera {
+                order: 1,
+                ..default()
+            },
+            ..default()
+        }));
+}
+
+pub fn remove_camera(mut commands: Commands, query: Query<Entity, With<ResultsCamera>>) {
+    if let Ok(entity) = query.get_single() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// The buttons need the cursor, the game may have locked it.
+pub fn release_cursor(mut windows: Query<&mut Window, With<PrimaryWindow>>) {
+    if let Ok(mut window) = windows.get_single_mut() {
+        window.cursor.grab_mode = CursorGrabMode::None;
+        window.cursor.visible = true;
+    }
+}
@@ -0,0 +1,28 @@
+use bevy::prelude::*;
+
+use crate::{
+    app::AppState,
+    game_over::components::{MainMenuButton, PlayAgainButton},
+};
+
+pub fn interact_with_play_again_button(
+    mut button_query: Query<&Interaction, (Changed<Interaction>, With<PlayAgainButton>)>,
+    mut app_state_next_state: ResMut<NextState<AppState>>,
+) {
+    if let Ok(interaction) = button_query.get_single_mut() {
+        if *interaction == Interaction::Pressed {
+            app_state_next_state.set(AppState::Game);
+        }
+    }
+}
+
+pub fn interact_with_main_menu_button(
+    mut button_query: Query<&Interaction, (Changed<Interaction>, With<MainMenuButton>)>,
+    mut app_state_next_state: ResMut<NextState<AppState>>,
+) {
+    if let Ok(interaction) = button_query.get_single_mut() {
+        if *interaction == Interaction::Pressed {
+            app_state_next_state.set(AppState::MainMenu);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use bevy::prelude::*;
+
+use crate::game::game_modes::{describe_winner, MatchResults};
+use crate::game::hud::scoreboard::build_player_rows;
+use crate::game::hud::styles::{HudTheme, SCOREBOARD_STYLE};
+use crate::game_over::{
+    components::{MainMenuButton, PlayAgainButton, ResultsScreen},
+    styles::{get_title_text_style, BUTTON_ROW_STYLE},
+};
+use crate::main_menu::{
+    elements::{new_button, new_text_label},
+    styles::{get_button_text_style, MAIN_MENU_STYLE},
+};
+
+pub fn spawn_results_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    theme: Res<HudTheme>,
+    results: Res<MatchResults>,
+) {
+    build_results_screen(&mut commands, &asset_server, &theme, &results);
+}
+
+pub fn despawn_results_screen(mut commands: Commands, results_query: Query<Entity, With<ResultsScreen>>) {
+    if let Ok(results_entity) = results_query.get_single() {
+        commands.entity(results_entity).despawn_recursive();
+    }
+}
+
+pub fn build_results_screen(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    theme: &HudTheme,
+    results: &MatchResults,
+) -> Entity {
+    // a lost survival game or a match which was left early has no winner
+    let title = if results.finished {
+        describe_winner(results.winner, &results.players)
+    } else {
+        "Game Over".to_string()
+    };
+
+    commands
+        .spawn((
+            Name::new("ResultsScreen"),
+            NodeBundle {
+                style: MAIN_MENU_STYLE,
+                ..default()
+            },
+            ResultsScreen {},
+        ))
+        .with_children(|parent| {
+            parent.spawn(new_text_label(&title, get_title_text_style(asset_server)));
+            if !results.mode.is_empty() {
+                parent.spawn(new_text_label(&results.mode, get_button_text_style(asset_server)));
+            }
+
+            // the same table as the scoreboard of the HUD
+            if !results.players.is_empty() {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            margin: UiRect::vertical(Val::Px(16.0)),
+                            ..SCOREBOARD_STYLE
+                        },
+                        background_color: theme.scoreboard_background.into(),
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        build_player_rows(parent, &results.players, &results.team_scores, theme, asset_server);
+                    });
+            }
+
+            parent
+                .spawn(NodeBundle {
+                    style: BUTTON_ROW_STYLE,
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn((new_button(), PlayAgainButton {}))
+                        .with_children(|parent| {
+                            parent.spawn(new_text_label("Play again", get_button_text_style(asset_server)));
+                        });
+                    parent
+                        .spawn((new_button(), MainMenuButton {}))
+                        .with_children(|parent| {
+                            parent.spawn(new_text_label("Main menu", get_button_text_style(asset_server)));
+                        });
+                });
+        })
+        .id()
+}
@@ -1,14 +1,37 @@
 use bevy::prelude::*;
-use app::AppPlugin;
+use app::{AppPlugin, ServerAppPlugin};
 use game::GamePlugin;
+use game::game_modes::ActiveGameMode;
+use game::game_modes::deathmatch::DEATHMATCH;
+use game::network::{NetworkMode, NetworkPlugin};
+use game_over::GameOverPlugin;
 use main_menu::MainMenuPlugin;
 
 pub mod app;
 pub mod game;
+pub mod game_over;
 pub mod main_menu;
 
 fn main() {
-    App::new()
-        .add_plugins((AppPlugin, MainMenuPlugin, GamePlugin))
-        .run()
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let (mut game_mode, mode) = ActiveGameMode::take_from_args(&mut args)
+        .and_then(|game_mode| Ok((game_mode, NetworkMode::from_args(args.into_iter())?)))
+        .unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            eprintln!("usage: bevy-fps [--server [address]] [--connect <address>] [--mode <game mode>]");
+            std::process::exit(2);
+        });
+    // a dedicated server without a game mode would only be a sandbox
+    if matches!(mode, NetworkMode::Server { .. }) && game_mode.0.is_none() {
+        game_mode.0 = Some(DEATHMATCH.to_string());
+    }
+
+    let mut app = App::new();
+    match mode {
+        NetworkMode::Server { .. } => app.add_plugins(ServerAppPlugin { frame_rate: 64.0 }),
+        _ => app.add_plugins((AppPlugin, MainMenuPlugin, GameOverPlugin)),
+    };
+    app.insert_resource(game_mode)
+        .add_plugins((GamePlugin, NetworkPlugin { mode }))
+        .run();
 }
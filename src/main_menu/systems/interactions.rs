@@ -25,7 +25,8 @@ pub fn animate_buttons(
         (Changed<Interaction>, With<Button>),
     >,
 ) {
-    if let Ok((interaction, mut background_color)) = button_query.get_single_mut() {
+    // several buttons change at once when the cursor moves from one to the next
+    for (interaction, mut background_color) in button_query.iter_mut() {
         match *interaction {
             Interaction::Pressed => {
                 *background_color = PRESSED_BUTTON_COLOR.into();